@@ -0,0 +1,854 @@
+// Copyright 2019 PingCAP, Inc.
+
+// The configuration is only used when generating Prost code.
+#![cfg_attr(not(feature = "prost-codec"), allow(dead_code))]
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+type NameFn = Arc<dyn Fn(&str) -> String + Send + Sync>;
+type FieldMethodsFn = Arc<dyn Fn(&FieldInfo) -> Option<String> + Send + Sync>;
+type PostProcessFn = Arc<dyn Fn(&mut String) + Send + Sync>;
+type LogFn = Arc<dyn Fn(&Event) + Send + Sync>;
+
+/// Describes a field of a message, passed to hooks in `WrapperConfig`.
+#[derive(Clone, Debug)]
+pub struct FieldInfo<'a> {
+    /// The path to the message's type relative to the generated module, e.g.,
+    /// `foo::Bar`, without any `r#` prefixes.
+    pub message: &'a str,
+    /// The name of the field, without any `r#` prefix.
+    pub field: &'a str,
+    /// The kind of the field, e.g., `string` or `repeated message`.
+    pub kind: &'a str,
+    /// The field number, if the field has a single one (oneofs don't).
+    pub tag: Option<u32>,
+    /// Whether the field is a packed repeated field. Prost packs repeated
+    /// scalar fields unless they are declared with `packed = false`.
+    pub packed: bool,
+}
+
+/// Something the generator did, passed to the hook set by
+/// `WrapperConfig::log`.
+#[derive(Clone, Debug)]
+pub enum Event<'a> {
+    /// Code is being generated for a message, named as in `FieldInfo`.
+    Message(&'a str),
+    /// Accessors are being generated for a field.
+    Field(FieldInfo<'a>),
+    /// A field doesn't get accessors.
+    SkippedField {
+        message: &'a str,
+        field: &'a str,
+        reason: &'a str,
+    },
+}
+
+/// Configuration for the wrapper code generated for Prost messages.
+///
+/// Which items are generated is controlled by `GenOpt`, this controls how they
+/// are generated.
+#[derive(Clone, Default)]
+pub struct WrapperConfig {
+    file_name: Option<NameFn>,
+    display_fields: HashMap<String, String>,
+    as_ref_fields: HashMap<String, String>,
+    field_methods: Option<FieldMethodsFn>,
+    impl_attributes: Vec<String>,
+    item_attributes: Vec<String>,
+    strict: bool,
+    module: Option<String>,
+    bytes_as_vec: bool,
+    bytes_handles: bool,
+    repeated_as_vec: bool,
+    no_std: bool,
+    method_prefix: Option<String>,
+    skip_all_constructors: bool,
+    skip_constructors: HashSet<String>,
+    message_derives: Vec<String>,
+    skipped_methods: HashMap<String, HashSet<String>>,
+    renamed_fields: HashMap<String, HashMap<String, String>>,
+    sensitive_fields: HashMap<String, HashSet<String>>,
+    extern_enums: HashMap<String, String>,
+    field_docs: HashMap<String, HashMap<String, String>>,
+    deprecated_fields: HashMap<String, HashSet<String>>,
+    panic_on_bad_index: bool,
+    constructor_name: Option<String>,
+    constructor_names: Option<NameFn>,
+    overwrite_guard: bool,
+    option_getters: bool,
+    cow_string_getters: bool,
+    skip_unknown_fields: bool,
+    skip_ignored_fields: bool,
+    pretty_print: bool,
+    into_setters: bool,
+    as_ref_str_setters: bool,
+    accessor_traits: bool,
+    serde_helpers: bool,
+    bytes_conversions: bool,
+    shared_enum_helpers: bool,
+    post_process: Option<PostProcessFn>,
+    log: Option<LogFn>,
+    prelude: bool,
+    skip_marker: Option<String>,
+    wrapped_modules: Vec<String>,
+    incremental: bool,
+    split_files: usize,
+    crlf_line_endings: bool,
+    splice_markers: bool,
+}
+
+impl WrapperConfig {
+    pub fn new() -> WrapperConfig {
+        WrapperConfig::default()
+    }
+
+    /// Set the naming scheme for generated wrapper files.
+    ///
+    /// The closure is passed the file name (without directories) of the Prost
+    /// output file and should return the file name for its wrapper, which will
+    /// be placed in the same directory. By default, `foo.rs` is wrapped in
+    /// `wrapper_foo.rs`.
+    pub fn file_name(
+        &mut self,
+        file_name: impl Fn(&str) -> String + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.file_name = Some(Arc::new(file_name));
+        self
+    }
+
+    /// Generate an implementation of `Display` for `message` which displays
+    /// the given field.
+    ///
+    /// `message` is the path to the message's type relative to the generated
+    /// module, e.g., `Foo` or `foo::Bar` for a nested message, without any `r#`
+    /// prefixes. `field` is the field's name without any `r#` prefix. The
+    /// field's type must implement `Display`.
+    pub fn display_field(
+        &mut self,
+        message: impl Into<String>,
+        field: impl Into<String>,
+    ) -> &mut Self {
+        self.display_fields.insert(message.into(), field.into());
+        self
+    }
+
+    pub(crate) fn display_field_for(&self, message: &str) -> Option<&str> {
+        self.display_fields.get(message).map(|s| &**s)
+    }
+
+    /// Generate implementations of `AsRef` and `AsMut` for `message` which
+    /// borrow the given field, so that the message can be passed to generic
+    /// code expecting the field's type. As for `get_*` and `mut_*`, an unset
+    /// field is borrowed as the default message, or set to it.
+    ///
+    /// `message` and `field` are as for `display_field`. The field must be a
+    /// singular message field.
+    pub fn as_ref_field(
+        &mut self,
+        message: impl Into<String>,
+        field: impl Into<String>,
+    ) -> &mut Self {
+        self.as_ref_fields.insert(message.into(), field.into());
+        self
+    }
+
+    pub(crate) fn as_ref_field_for(&self, message: &str) -> Option<&str> {
+        self.as_ref_fields.get(message).map(|s| &**s)
+    }
+
+    /// Add custom methods to the generated code for a field.
+    ///
+    /// The closure is called for every field of every message. It may return
+    /// source code which is included in the message's generated `impl` block
+    /// after the field's generated methods. `None` means no extra methods are
+    /// added.
+    pub fn field_methods(
+        &mut self,
+        field_methods: impl Fn(&FieldInfo) -> Option<String> + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.field_methods = Some(Arc::new(field_methods));
+        self
+    }
+
+    pub(crate) fn custom_field_methods(&self, field: &FieldInfo) -> Option<String> {
+        self.field_methods.as_ref().and_then(|f| f(field))
+    }
+
+    /// Add an attribute to every generated inherent `impl` block.
+    ///
+    /// For example, `#[cfg_attr(docsrs, doc(hidden))]` keeps the generated
+    /// accessors out of a crate's documentation on docs.rs. The attribute is
+    /// emitted verbatim, including the `#[...]`.
+    pub fn impl_attribute(&mut self, attribute: impl Into<String>) -> &mut Self {
+        self.impl_attributes.push(attribute.into());
+        self
+    }
+
+    pub(crate) fn impl_attributes(&self) -> &[String] {
+        &self.impl_attributes
+    }
+
+    /// Add an attribute to every generated item: `impl` blocks (inherent and
+    /// of traits), traits, and modules.
+    ///
+    /// For example, `#[cfg(feature = "proto")]` compiles the wrappers only
+    /// when a crate's `proto` feature is enabled. Unlike `impl_attribute`, the
+    /// attribute must be valid on any item. It is emitted verbatim, including
+    /// the `#[...]`, before any attributes added by `impl_attribute`.
+    pub fn item_attribute(&mut self, attribute: impl Into<String>) -> &mut Self {
+        self.item_attributes.push(attribute.into());
+        self
+    }
+
+    pub(crate) fn item_attributes(&self) -> &[String] {
+        &self.item_attributes
+    }
+
+    /// If `true`, it is an error for a message to be somewhere the generator
+    /// won't find it, e.g., inside a function or `impl` block, or to be in a
+    /// module but private to it (and so inaccessible to wrappers). Otherwise,
+    /// such messages are silently skipped. It is also an error for an enum
+    /// field to refer to an enum which isn't a Prost enumeration in the input,
+    /// unless it is mapped by `extern_enum`, since its accessors might not
+    /// compile. Default is `false`.
+    pub fn strict(&mut self, strict: bool) -> &mut Self {
+        self.strict = strict;
+        self
+    }
+
+    pub(crate) fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Generate wrapper code inside a module with the given name, rather than
+    /// at the top level of the wrapper file.
+    ///
+    /// Inside that module, the module hierarchy of the Prost output is
+    /// reproduced, each module re-exporting the corresponding Prost module,
+    /// so generated `impl`s use short names rather than paths.
+    pub fn module(&mut self, module: impl Into<String>) -> &mut Self {
+        self.module = Some(module.into());
+        self
+    }
+
+    pub(crate) fn wrapper_module(&self) -> Option<&str> {
+        self.module.as_deref()
+    }
+
+    /// Whether getters for `bytes` fields return `&[u8]` (if `true`) or
+    /// `&Vec<u8>` (if `false`). Default is `true`.
+    pub fn deref_bytes(&mut self, deref_bytes: bool) -> &mut Self {
+        self.bytes_as_vec = !deref_bytes;
+        self
+    }
+
+    pub(crate) fn is_deref_bytes(&self) -> bool {
+        !self.bytes_as_vec
+    }
+
+    /// If `true`, getters for `bytes` fields of type `Bytes` return `&Bytes`,
+    /// whatever `deref_bytes`, so callers can cheaply clone the buffer.
+    /// Default is `false`.
+    pub fn bytes_handles(&mut self, bytes_handles: bool) -> &mut Self {
+        self.bytes_handles = bytes_handles;
+        self
+    }
+
+    pub(crate) fn has_bytes_handles(&self) -> bool {
+        self.bytes_handles
+    }
+
+    /// Whether getters for repeated fields return a slice, `&[T]` (if `true`),
+    /// or a reference to the field's container, e.g., `&Vec<T>` (if `false`).
+    /// Default is `true`.
+    ///
+    /// Containers other than `Vec` must implement `Deref<Target = [T]>`.
+    pub fn deref_repeated(&mut self, deref_repeated: bool) -> &mut Self {
+        self.repeated_as_vec = !deref_repeated;
+        self
+    }
+
+    pub(crate) fn is_deref_repeated(&self) -> bool {
+        !self.repeated_as_vec
+    }
+
+    /// If `true`, generated code uses `core` and `alloc` rather than `std`
+    /// wherever possible, for use in `no_std` crates. The crate must declare
+    /// `extern crate alloc`. Default is `false`.
+    ///
+    /// Conversions for well-known types (`GenOpt::WELL_KNOWN_TYPES`) and the
+    /// implementation of `protobuf::Message` (`GenOpt::MESSAGE`) require `std`.
+    pub fn no_std(&mut self, no_std: bool) -> &mut Self {
+        self.no_std = no_std;
+        self
+    }
+
+    pub(crate) fn is_no_std(&self) -> bool {
+        self.no_std
+    }
+
+    /// If `true`, generated code refers to `std`, `core`, and `alloc` through
+    /// aliases (`__std`, `__core`, and `__alloc`) which are imported at the
+    /// top of the generated file and of each generated module, rather than by
+    /// absolute paths, so that only those imports need changing if the code
+    /// is moved. Default is `false`.
+    pub fn prelude(&mut self, prelude: bool) -> &mut Self {
+        self.prelude = prelude;
+        self
+    }
+
+    pub(crate) fn has_prelude(&self) -> bool {
+        self.prelude
+    }
+
+    /// Prefix the names of generated field accessors, e.g., with a prefix of
+    /// `pb_`, `get_foo` becomes `pb_get_foo`. Useful to avoid collisions with
+    /// the methods of traits implemented by messages.
+    pub fn method_prefix(&mut self, prefix: impl Into<String>) -> &mut Self {
+        self.method_prefix = Some(prefix.into());
+        self
+    }
+
+    pub(crate) fn accessor_prefix(&self) -> Option<&str> {
+        self.method_prefix.as_deref()
+    }
+
+    /// Don't generate `new_` and `default_ref` for `message`, e.g., because it
+    /// has hand-written constructors. Getters which would return a reference to
+    /// the message's default instance use a `static` of their own instead.
+    ///
+    /// `message` is the path to the message's type relative to the generated
+    /// module, as for `display_field`.
+    pub fn skip_constructors(&mut self, message: impl Into<String>) -> &mut Self {
+        self.skip_constructors.insert(message.into());
+        self
+    }
+
+    /// Like `skip_constructors`, but for every message. Default is `false`.
+    pub fn skip_all_constructors(&mut self, skip: bool) -> &mut Self {
+        self.skip_all_constructors = skip;
+        self
+    }
+
+    pub(crate) fn has_constructors(&self, message: &str) -> bool {
+        !self.skip_all_constructors && !self.skip_constructors.contains(message)
+    }
+
+    /// Don't generate anything for messages whose documentation contains
+    /// `marker`, e.g., `@protobuf-build:skip` in the comment on a message in
+    /// its proto file, which Prost copies to the message's documentation.
+    ///
+    /// Getters in the same file which would return a reference to a marked
+    /// message's default instance use a `static`, as for `skip_constructors`.
+    /// Messages in other files must skip its constructors explicitly.
+    pub fn skip_marker(&mut self, marker: impl Into<String>) -> &mut Self {
+        self.skip_marker = Some(marker.into());
+        self
+    }
+
+    pub(crate) fn is_skip_marked(&self, doc: &str) -> bool {
+        self.skip_marker
+            .as_ref()
+            .is_some_and(|m| doc.contains(&**m))
+    }
+
+    /// Only generate anything for messages in the module `module` or its
+    /// submodules, e.g., `kvproto::raft_cmdpb` to wrap a single package of a
+    /// file of consolidated Prost output. May be given more than once. By
+    /// default, messages in every module are wrapped.
+    ///
+    /// `module` is the path to the module relative to the generated module, as
+    /// for `display_field`; a trailing `::` is ignored.
+    pub fn wrap_module(&mut self, module: impl Into<String>) -> &mut Self {
+        let module = module.into();
+        self.wrapped_modules
+            .push(module.trim_end_matches("::").to_owned());
+        self
+    }
+
+    // `module` is the path of a module followed by `::`, or empty for the
+    // top-level module.
+    pub(crate) fn wraps_module(&self, module: &str) -> bool {
+        self.wrapped_modules.is_empty()
+            || self.wrapped_modules.iter().any(|m| {
+                module
+                    .strip_prefix(&**m)
+                    .is_some_and(|rest| rest.starts_with("::"))
+            })
+    }
+
+    /// Treat structs which derive `name` as messages, in addition to those
+    /// which derive Prost's `Message`. Derives are matched on the last segment
+    /// of their path, e.g., `my_macros::ProtoMessage` matches `ProtoMessage`.
+    pub fn message_derive(&mut self, name: impl Into<String>) -> &mut Self {
+        self.message_derives.push(name.into());
+        self
+    }
+
+    pub(crate) fn is_message_derive(&self, name: &str) -> bool {
+        self.message_derives.iter().any(|d| d == name)
+    }
+
+    /// Don't generate the method `method` for `message`, e.g., because it is
+    /// written by hand in another `impl` block.
+    ///
+    /// `message` is the path to the message's type relative to the generated
+    /// module, as for `display_field`. `method` is the full name of the method,
+    /// including any prefix set with `method_prefix`.
+    pub fn skip_method(
+        &mut self,
+        message: impl Into<String>,
+        method: impl Into<String>,
+    ) -> &mut Self {
+        self.skipped_methods
+            .entry(message.into())
+            .or_default()
+            .insert(method.into());
+        self
+    }
+
+    pub(crate) fn skips_method(&self, message: &str, method: &str) -> bool {
+        self.skipped_methods
+            .get(message)
+            .is_some_and(|methods| methods.contains(method))
+    }
+
+    /// Name the accessors of `field` of `message` after `name` rather than
+    /// the field, e.g., renaming `type` to `kind` generates `get_kind` and
+    /// `set_kind`, which still access the field `type`.
+    ///
+    /// `message` and `field` are as for `display_field`. Any prefix set with
+    /// `method_prefix` is added to `name`.
+    pub fn rename_field(
+        &mut self,
+        message: impl Into<String>,
+        field: impl Into<String>,
+        name: impl Into<String>,
+    ) -> &mut Self {
+        self.renamed_fields
+            .entry(message.into())
+            .or_default()
+            .insert(field.into(), name.into());
+        self
+    }
+
+    pub(crate) fn renamed_field(&self, message: &str, field: &str) -> Option<&str> {
+        self.renamed_fields
+            .get(message)
+            .and_then(|fields| fields.get(field))
+            .map(|s| &**s)
+    }
+
+    /// Mark `field` of `message` as sensitive, e.g., a password, and generate
+    /// a `redacted_debug` method for `message`. It returns the message
+    /// formatted as for `Debug`, except that sensitive fields are shown as
+    /// `***`, so that it may be logged safely.
+    ///
+    /// `message` and `field` are as for `display_field`.
+    pub fn sensitive_field(
+        &mut self,
+        message: impl Into<String>,
+        field: impl Into<String>,
+    ) -> &mut Self {
+        self.sensitive_fields
+            .entry(message.into())
+            .or_default()
+            .insert(field.into());
+        self
+    }
+
+    pub(crate) fn sensitive_fields(&self, message: &str) -> Option<&HashSet<String>> {
+        self.sensitive_fields.get(message)
+    }
+
+    /// Use the Rust type `path` for fields of the enum `name`, e.g., an enum
+    /// from another package which is compiled in another crate.
+    ///
+    /// `name` is the enum as named in the field's `#[prost(enumeration =
+    /// "...")]` attribute, e.g., `super::common::Kind`. `path` is used
+    /// wherever the wrapper is generated, so it should be absolute, e.g.,
+    /// `::common::Kind`.
+    pub fn extern_enum(&mut self, name: impl Into<String>, path: impl Into<String>) -> &mut Self {
+        self.extern_enums.insert(name.into(), path.into());
+        self
+    }
+
+    pub(crate) fn extern_enum_path(&self, name: &str) -> Option<&str> {
+        self.extern_enums.get(name).map(|s| &**s)
+    }
+
+    /// Document the accessors of `field` of `message` with `doc`, e.g., the
+    /// field's comment in its `.proto` file. See also
+    /// `WrapperGen::descriptor_set`.
+    ///
+    /// `message` and `field` are as for `display_field`.
+    pub fn field_doc(
+        &mut self,
+        message: impl Into<String>,
+        field: impl Into<String>,
+        doc: impl Into<String>,
+    ) -> &mut Self {
+        self.field_docs
+            .entry(message.into())
+            .or_default()
+            .insert(field.into(), doc.into());
+        self
+    }
+
+    pub(crate) fn field_doc_for(&self, message: &str, field: &str) -> Option<&str> {
+        self.field_docs
+            .get(message)
+            .and_then(|fields| fields.get(field))
+            .map(|s| &**s)
+    }
+
+    /// Mark the accessors of `field` of `message` `#[deprecated]`, e.g.,
+    /// because the field is deprecated in its `.proto` file, which Prost
+    /// doesn't mark. See also `WrapperGen::descriptor_set`.
+    ///
+    /// `message` and `field` are as for `display_field`.
+    pub fn deprecated_field(
+        &mut self,
+        message: impl Into<String>,
+        field: impl Into<String>,
+    ) -> &mut Self {
+        self.deprecated_fields
+            .entry(message.into())
+            .or_default()
+            .insert(field.into());
+        self
+    }
+
+    pub(crate) fn is_deprecated_field(&self, message: &str, field: &str) -> bool {
+        self.deprecated_fields
+            .get(message)
+            .is_some_and(|fields| fields.contains(field))
+    }
+
+    /// If `true`, accessors for elements of repeated fields (see
+    /// `GenOpt::INDEXED`) panic if the index is out of bounds and return the
+    /// element itself. Otherwise they return an `Option`. Default is `false`.
+    pub fn panic_on_bad_index(&mut self, panic: bool) -> &mut Self {
+        self.panic_on_bad_index = panic;
+        self
+    }
+
+    pub(crate) fn panics_on_bad_index(&self) -> bool {
+        self.panic_on_bad_index
+    }
+
+    /// The name of the constructor generated with `GenOpt::NEW`. Default is
+    /// `new_`.
+    pub fn constructor_name(&mut self, name: impl Into<String>) -> &mut Self {
+        self.constructor_name = Some(name.into());
+        self
+    }
+
+    /// Set the naming scheme for constructors generated with `GenOpt::NEW`,
+    /// overriding `constructor_name`.
+    ///
+    /// The closure is passed the path to the message's type as for
+    /// `display_field`, e.g., `foo::Bar`, and should return the name of its
+    /// constructor, e.g., `build`.
+    pub fn constructor_names(
+        &mut self,
+        names: impl Fn(&str) -> String + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.constructor_names = Some(Arc::new(names));
+        self
+    }
+
+    pub(crate) fn constructor_for(&self, message: &str) -> String {
+        match &self.constructor_names {
+            Some(names) => names(message),
+            None => self
+                .constructor_name
+                .as_deref()
+                .unwrap_or("new_")
+                .to_owned(),
+        }
+    }
+
+    /// If `true`, it is an error to write a wrapper over an existing file
+    /// which was not generated by this crate (as identified by its first
+    /// line). Default is `false`.
+    pub fn overwrite_guard(&mut self, guard: bool) -> &mut Self {
+        self.overwrite_guard = guard;
+        self
+    }
+
+    pub(crate) fn has_overwrite_guard(&self) -> bool {
+        self.overwrite_guard
+    }
+
+    /// If `true`, `WrapperGen::write` and `WrapperGen::write_to` don't
+    /// regenerate a wrapper file which was modified more recently than its
+    /// input. Changes to the configuration or to this crate are not detected,
+    /// so set it to `false` to force the wrapper to be regenerated. Default is
+    /// `false`.
+    pub fn incremental(&mut self, incremental: bool) -> &mut Self {
+        self.incremental = incremental;
+        self
+    }
+
+    pub(crate) fn is_incremental(&self) -> bool {
+        self.incremental
+    }
+
+    /// If `true`, generated files have CRLF (`\r\n`) line endings, otherwise
+    /// LF (`\n`), whatever the line endings of code added by configuration,
+    /// e.g., by `post_process`. Generated files are always UTF-8, without a
+    /// byte order mark. Default is `false`.
+    pub fn crlf_line_endings(&mut self, crlf: bool) -> &mut Self {
+        self.crlf_line_endings = crlf;
+        self
+    }
+
+    pub(crate) fn has_crlf_line_endings(&self) -> bool {
+        self.crlf_line_endings
+    }
+
+    /// If `true`, the wrapper file is a mostly hand-written file, and only the
+    /// lines between a `// BEGIN generated` line and the next
+    /// `// END generated` line are replaced by the generated code. The rest of
+    /// the file is preserved, so `overwrite_guard` doesn't apply. It's an
+    /// error if the file or either marker is missing. Can't be used with
+    /// `split_files`. Default is `false`.
+    pub fn splice_markers(&mut self, splice: bool) -> &mut Self {
+        self.splice_markers = splice;
+        self
+    }
+
+    pub(crate) fn has_splice_markers(&self) -> bool {
+        self.splice_markers
+    }
+
+    /// Split each wrapper file into `count` files, to reduce the size of the
+    /// files rustc and IDEs must process for very large inputs. Messages and
+    /// enums are assigned to the files in turn, e.g., `wrapper_foo_0.rs` and
+    /// `wrapper_foo_1.rs`, and the wrapper file includes them. Can't be used
+    /// with `module`. Default is `1`, i.e., files aren't split.
+    pub fn split_files(&mut self, count: usize) -> &mut Self {
+        self.split_files = count;
+        self
+    }
+
+    pub(crate) fn split_count(&self) -> usize {
+        self.split_files.max(1)
+    }
+
+    /// If `true`, getters for optional message fields and message variants of
+    /// oneofs return an `Option` (`None` if the field is unset). Otherwise,
+    /// they return a default message if the field is unset. Default is
+    /// `false`.
+    pub fn option_getters(&mut self, option_getters: bool) -> &mut Self {
+        self.option_getters = option_getters;
+        self
+    }
+
+    pub(crate) fn has_option_getters(&self) -> bool {
+        self.option_getters
+    }
+
+    /// If `true`, getters for string fields return a `Cow<str>`, which
+    /// borrows the field, or is an owned empty string if an optional field is
+    /// unset, rather than a `&str`. Getters for string variants of oneofs are
+    /// unchanged. Default is `false`.
+    pub fn cow_string_getters(&mut self, cow: bool) -> &mut Self {
+        self.cow_string_getters = cow;
+        self
+    }
+
+    pub(crate) fn has_cow_string_getters(&self) -> bool {
+        self.cow_string_getters
+    }
+
+    /// If `true`, fields whose type isn't supported (e.g., one added by a
+    /// newer version of Prost) are skipped, leaving a comment in the generated
+    /// code. Otherwise, such a field is an error. Default is `false`.
+    ///
+    /// Use `WrapperGen::validate` to list the fields which would be skipped.
+    pub fn skip_unknown_fields(&mut self, skip: bool) -> &mut Self {
+        self.skip_unknown_fields = skip;
+        self
+    }
+
+    pub(crate) fn skips_unknown_fields(&self) -> bool {
+        self.skip_unknown_fields
+    }
+
+    /// If `true`, fields which Prost doesn't encode, i.e., which are marked
+    /// `#[prost(skip)]`, don't get accessors. Otherwise, they get plain
+    /// accessors, which clear the field to its `Default` value. Default is
+    /// `false`.
+    pub fn skip_ignored_fields(&mut self, skip: bool) -> &mut Self {
+        self.skip_ignored_fields = skip;
+        self
+    }
+
+    pub(crate) fn skips_ignored_fields(&self) -> bool {
+        self.skip_ignored_fields
+    }
+
+    /// If `true`, setters for string, bytes, and message fields take an
+    /// `impl Into<T>` rather than a `T`, e.g., `set_name` accepts a `&str` as
+    /// well as a `String`. Setters for other fields are unchanged. Default is
+    /// `false`.
+    pub fn into_setters(&mut self, into_setters: bool) -> &mut Self {
+        self.into_setters = into_setters;
+        self
+    }
+
+    pub(crate) fn has_into_setters(&self) -> bool {
+        self.into_setters
+    }
+
+    /// If `true`, setters for string fields take an `impl AsRef<str>` and
+    /// copy it into a new `String`, e.g., `set_name` accepts a `&str`,
+    /// `String`, or `&String`. For string fields, this takes precedence over
+    /// `into_setters`. Default is `false`.
+    pub fn as_ref_str_setters(&mut self, as_ref_str_setters: bool) -> &mut Self {
+        self.as_ref_str_setters = as_ref_str_setters;
+        self
+    }
+
+    pub(crate) fn has_as_ref_str_setters(&self) -> bool {
+        self.as_ref_str_setters
+    }
+
+    /// If `true`, each message's field accessors are declared in a trait
+    /// named for the message, e.g., `FooAccessors` for `Foo` or
+    /// `BarBazAccessors` for `bar::Baz`, which is implemented for the
+    /// message, rather than in its inherent `impl`. Callers must `use` the
+    /// trait to call the accessors. Constructors and other generated methods
+    /// are unaffected. Default is `false`.
+    pub fn accessor_traits(&mut self, accessor_traits: bool) -> &mut Self {
+        self.accessor_traits = accessor_traits;
+        self
+    }
+
+    pub(crate) fn has_accessor_traits(&self) -> bool {
+        self.accessor_traits
+    }
+
+    /// If `true`, generate an associated function `is_default_*` for each
+    /// field, which is `true` if a value of the field's type is the default,
+    /// i.e., Prost wouldn't encode it. It can be used to skip default fields
+    /// when messages also derive serde's `Serialize`, e.g.,
+    /// `#[serde(skip_serializing_if = "Foo::is_default_bar")]`.
+    ///
+    /// Generated methods don't affect serde, which only sees the fields, so
+    /// this is only needed to skip fields. Default is `false`.
+    pub fn serde_helpers(&mut self, serde_helpers: bool) -> &mut Self {
+        self.serde_helpers = serde_helpers;
+        self
+    }
+
+    pub(crate) fn has_serde_helpers(&self) -> bool {
+        self.serde_helpers
+    }
+
+    /// If `true`, implement `From<Foo>` for `Vec<u8>` and `prost::bytes::Bytes`
+    /// for each message `Foo`, encoding the message, so that encoded messages
+    /// can be produced by `into()`. Default is `false`.
+    pub fn bytes_conversions(&mut self, bytes_conversions: bool) -> &mut Self {
+        self.bytes_conversions = bytes_conversions;
+        self
+    }
+
+    pub(crate) fn has_bytes_conversions(&self) -> bool {
+        self.bytes_conversions
+    }
+
+    /// If `true`, accessors for enum fields convert to and from the field's
+    /// `i32` by calling helper functions in a module `__enums`, rather than
+    /// each inlining the conversion. The module is declared once, at the top
+    /// of the generated `mod.rs`, and imported into each module which
+    /// includes a wrapper. Code which includes wrappers without the generated
+    /// `mod.rs` must declare or import it too. Default is `false`.
+    pub fn shared_enum_helpers(&mut self, shared_enum_helpers: bool) -> &mut Self {
+        self.shared_enum_helpers = shared_enum_helpers;
+        self
+    }
+
+    pub(crate) fn has_shared_enum_helpers(&self) -> bool {
+        self.shared_enum_helpers
+    }
+
+    /// If `true`, generated code is formatted (indented, with long lines
+    /// wrapped), without needing rustfmt. Comments in the generated code
+    /// are dropped. Default is `false`.
+    pub fn pretty_print(&mut self, pretty_print: bool) -> &mut Self {
+        self.pretty_print = pretty_print;
+        self
+    }
+
+    pub(crate) fn is_pretty_print(&self) -> bool {
+        self.pretty_print
+    }
+
+    /// Post-process each generated file, e.g., to add a license header.
+    ///
+    /// The closure is passed the complete generated code, after formatting
+    /// with `pretty_print`, and may modify it before it is written.
+    pub fn post_process(
+        &mut self,
+        post_process: impl Fn(&mut String) + Send + Sync + 'static,
+    ) -> &mut Self {
+        self.post_process = Some(Arc::new(post_process));
+        self
+    }
+
+    pub(crate) fn post_processor(&self) -> Option<&dyn Fn(&mut String)> {
+        self.post_process
+            .as_deref()
+            .map(|f| f as &dyn Fn(&mut String))
+    }
+
+    /// Call `log` for each message and field the generator processes, e.g.,
+    /// to find out why a field doesn't get the expected accessors.
+    pub fn log(&mut self, log: impl Fn(&Event) + Send + Sync + 'static) -> &mut Self {
+        self.log = Some(Arc::new(log));
+        self
+    }
+
+    pub(crate) fn log_event(&self, event: Event) {
+        if let Some(log) = &self.log {
+            log(&event);
+        }
+    }
+
+    pub(crate) fn wrapper_file_name(&self, file_name: &str) -> String {
+        match &self.file_name {
+            Some(f) => f(file_name),
+            None => format!("wrapper_{}", file_name),
+        }
+    }
+
+    // The name of the `index`th file of a split wrapper, see `split_files`.
+    pub(crate) fn wrapper_part_file_name(&self, file_name: &str, index: usize) -> String {
+        let wrapper = self.wrapper_file_name(file_name);
+        match wrapper.strip_suffix(".rs") {
+            Some(stem) => format!("{}_{}.rs", stem, index),
+            None => format!("{}_{}", wrapper, index),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_wrapper_file_name() {
+        let mut config = WrapperConfig::new();
+        assert_eq!("wrapper_foo.rs", config.wrapper_file_name("foo.rs"));
+        config.file_name(|name| format!("{}.wrapper.rs", name.trim_end_matches(".rs")));
+        assert_eq!("foo.wrapper.rs", config.wrapper_file_name("foo.rs"));
+        assert_eq!(
+            "foo.wrapper_1.rs",
+            config.wrapper_part_file_name("foo.rs", 1)
+        );
+    }
+}
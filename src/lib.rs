@@ -14,10 +14,16 @@ mod protobuf_impl;
 #[cfg(feature = "prost-codec")]
 mod prost_impl;
 
+mod config;
+
+pub use crate::config::{Event, FieldInfo, WrapperConfig};
+#[cfg(feature = "prost-codec")]
+pub use crate::wrapper::{FileSink, MemSink, Sink, Summary, Warning, WrapperGen};
+
 use bitflags::bitflags;
-use std::fmt::Write as _;
+use std::collections::HashSet;
 use std::fs::{self, File};
-use std::io::Write;
+use std::io::{self, Write};
 use std::path::{Path, PathBuf};
 
 pub struct Builder {
@@ -26,10 +32,16 @@ pub struct Builder {
     black_list: Vec<String>,
     out_dir: String,
     wrapper_opts: GenOpt,
+    wrapper_config: WrapperConfig,
     package_name: Option<String>,
     re_export_services: bool,
 }
 
+// Build scripts may move a `Builder` to another thread, so its callbacks must
+// be `Send` and `Sync`.
+fn _assert_send_sync<T: Send + Sync>() {}
+const _: fn() = _assert_send_sync::<Builder>;
+
 impl Builder {
     pub fn new() -> Builder {
         Builder {
@@ -44,26 +56,49 @@ impl Builder {
                 "{}/protos",
                 std::env::var("OUT_DIR").expect("No OUT_DIR defined")
             ),
-            wrapper_opts: GenOpt::all(),
+            wrapper_opts: GenOpt::default(),
+            wrapper_config: WrapperConfig::default(),
             package_name: None,
             re_export_services: true,
         }
     }
 
-    pub fn generate(&self) {
-        assert!(!self.files.is_empty(), "No files specified for generation");
-        self.prep_out_dir();
-        self.generate_files();
-        self.generate_mod_file();
+    /// Generate code for the proto files, including wrappers for Prost
+    /// output, and a `mod.rs` which declares it in `out_dir`.
+    ///
+    /// Returns an error naming the file if it can't be generated, e.g.,
+    /// because Prost's output can't be read or parsed.
+    pub fn generate(&self) -> Result<(), io::Error> {
+        if self.files.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "No files specified for generation",
+            ));
+        }
+        self.prep_out_dir()?;
+        self.generate_files()?;
+        self.generate_mod_file()
     }
 
     /// This option is only used when generating Prost code. Otherwise, it is
     /// silently ignored.
+    ///
+    /// Defaults to `GenOpt::default()`, i.e., the rust-protobuf style API.
+    /// Additional helpers must be opted in to.
     pub fn wrapper_options(&mut self, wrapper_opts: GenOpt) -> &mut Self {
         self.wrapper_opts = wrapper_opts;
         self
     }
 
+    /// Configure how wrapper code is generated.
+    ///
+    /// This option is only used when generating Prost code. Otherwise, it is
+    /// silently ignored.
+    pub fn wrapper_config(&mut self, wrapper_config: WrapperConfig) -> &mut Self {
+        self.wrapper_config = wrapper_config;
+        self
+    }
+
     /// Finds proto files to operate on in the `proto_dir` directory.
     pub fn search_dir_for_protos(&mut self, proto_dir: &str) -> &mut Self {
         self.files = fs::read_dir(proto_dir)
@@ -131,60 +166,77 @@ impl Builder {
         self
     }
 
-    fn generate_mod_file(&self) {
-        let mut f = File::create(format!("{}/mod.rs", self.out_dir)).unwrap();
+    fn generate_mod_file(&self) -> Result<(), io::Error> {
+        let mut f = File::create(format!("{}/mod.rs", self.out_dir))?;
 
-        let modules = self.list_rs_files().filter_map(|path| {
-            let name = path.file_stem().unwrap().to_str().unwrap();
-            if name.starts_with("wrapper_")
-                || name == "mod"
-                || self.black_list.iter().any(|i| name.contains(i))
-            {
-                return None;
+        // The files of Prost output, wrappers are included alongside them.
+        let files: Vec<_> = self.list_rs_files().collect();
+        let mut wrappers = HashSet::new();
+        for path in &files {
+            let file_name = path.file_name().unwrap().to_str().unwrap();
+            wrappers.insert(self.wrapper_config.wrapper_file_name(file_name));
+            if self.wrapper_config.split_count() > 1 {
+                for index in 0..self.wrapper_config.split_count() {
+                    wrappers.insert(self.wrapper_config.wrapper_part_file_name(file_name, index));
+                }
             }
-            Some((name.replace('-', "_"), name.to_owned()))
+        }
+        let files = files.iter().filter(|path| {
+            let name = path.file_stem().unwrap().to_str().unwrap();
+            let file_name = path.file_name().unwrap().to_str().unwrap();
+            !wrappers.contains(file_name)
+                && name != "mod"
+                && !self.black_list.iter().any(|i| name.contains(i))
         });
 
-        let mut exports = String::new();
-        for (module, file_name) in modules {
-            if cfg!(feature = "protobuf-codec") {
+        #[cfg(all(feature = "prost-codec", not(feature = "protobuf-codec")))]
+        {
+            // Included relative to `mod.rs`.
+            let files: Vec<_> = files
+                .map(|path| PathBuf::from(path.file_name().unwrap()))
+                .collect();
+            let code =
+                crate::wrapper::mod_file(&files, self.wrapper_opts, &self.wrapper_config, None)?;
+            f.write_all(code.as_bytes())?;
+        }
+
+        #[cfg(feature = "protobuf-codec")]
+        {
+            use std::fmt::Write as _;
+
+            let mut exports = String::new();
+            for path in files {
+                let module = path
+                    .file_stem()
+                    .unwrap()
+                    .to_str()
+                    .unwrap()
+                    .replace('-', "_");
                 if self.package_name.is_some() {
                     writeln!(exports, "pub use super::{}::*;", module).unwrap();
                 } else {
-                    writeln!(f, "pub ").unwrap();
+                    writeln!(f, "pub ")?;
                 }
-                writeln!(f, "mod {};", module).unwrap();
-                continue;
+                writeln!(f, "mod {};", module)?;
             }
 
-            let mut level = 0;
-            for part in module.split('.') {
-                writeln!(f, "pub mod {} {{", part).unwrap();
-                level += 1;
+            if !exports.is_empty() {
+                writeln!(
+                    f,
+                    "pub mod {} {{ {} }}",
+                    self.package_name.as_ref().unwrap(),
+                    exports
+                )?;
             }
-            writeln!(f, "include!(\"{}.rs\");", file_name,).unwrap();
-            if Path::new(&format!("{}/wrapper_{}.rs", self.out_dir, file_name)).exists() {
-                writeln!(f, "include!(\"wrapper_{}.rs\");", file_name,).unwrap();
-            }
-            writeln!(f, "{}", "}\n".repeat(level)).unwrap();
-        }
-
-        if !exports.is_empty() {
-            writeln!(
-                f,
-                "pub mod {} {{ {} }}",
-                self.package_name.as_ref().unwrap(),
-                exports
-            )
-            .unwrap();
         }
+        Ok(())
     }
 
-    fn prep_out_dir(&self) {
+    fn prep_out_dir(&self) -> Result<(), io::Error> {
         if Path::new(&self.out_dir).exists() {
-            fs::remove_dir_all(&self.out_dir).unwrap();
+            fs::remove_dir_all(&self.out_dir)?;
         }
-        fs::create_dir_all(&self.out_dir).unwrap();
+        fs::create_dir_all(&self.out_dir)
     }
 
     // List all `.rs` files in `self.out_dir`.
@@ -202,6 +254,77 @@ impl Builder {
     }
 }
 
+// Implemented for every message by wrappers generated with `GenOpt::MESSAGE_EXT`.
+#[cfg(feature = "prost-codec")]
+const MESSAGE_EXT_TRAIT: &str = "/// Functionality common to all generated messages.
+pub trait MessageExt: Sized {
+    /// Create a new message with default values.
+    fn new_() -> Self;
+    /// A reference to a shared message with default values.
+    fn default_instance() -> &'static Self;
+}
+";
+
+// Called by the accessors of enum fields in wrappers generated with
+// `WrapperConfig::shared_enum_helpers`.
+#[cfg(feature = "prost-codec")]
+const ENUM_HELPERS: &str =
+    "/// Conversions between enums and the `i32`s which store them in messages.
+#[allow(dead_code)]
+pub mod __enums {
+    /// The enum for `v`, or the default enum if `v` isn't a known value.
+    #[inline]
+    pub fn get<E, F>(v: i32, from_i32: F) -> E
+    where
+        E: ::core::default::Default,
+        F: ::core::ops::FnOnce(i32) -> ::core::option::Option<E>,
+    {
+        from_i32(v).unwrap_or_default()
+    }
+
+    /// The enum for `v`, or `v` if it isn't a known value.
+    #[inline]
+    pub fn try_get<E, F>(v: i32, from_i32: F) -> ::core::result::Result<E, i32>
+    where
+        F: ::core::ops::FnOnce(i32) -> ::core::option::Option<E>,
+    {
+        from_i32(v).ok_or(v)
+    }
+
+    /// Like `get`, but the default enum if `v` is unset.
+    #[inline]
+    pub fn get_opt<E, F>(v: ::core::option::Option<i32>, from_i32: F) -> E
+    where
+        E: ::core::default::Default,
+        F: ::core::ops::FnOnce(i32) -> ::core::option::Option<E>,
+    {
+        v.and_then(from_i32).unwrap_or_default()
+    }
+
+    /// Like `try_get`, but the default enum if `v` is unset.
+    #[inline]
+    pub fn try_get_opt<E, F>(
+        v: ::core::option::Option<i32>,
+        from_i32: F,
+    ) -> ::core::result::Result<E, i32>
+    where
+        E: ::core::default::Default,
+        F: ::core::ops::FnOnce(i32) -> ::core::option::Option<E>,
+    {
+        match v {
+            ::core::option::Option::Some(v) => from_i32(v).ok_or(v),
+            ::core::option::Option::None => ::core::result::Result::Ok(E::default()),
+        }
+    }
+
+    /// The `i32` which stores `v`.
+    #[inline]
+    pub fn set<E: ::core::convert::Into<i32>>(v: E) -> i32 {
+        v.into()
+    }
+}
+";
+
 impl Default for Builder {
     fn default() -> Builder {
         Builder::new()
@@ -209,23 +332,114 @@ impl Default for Builder {
 }
 
 bitflags! {
+    /// Which items are generated for Prost messages.
+    ///
+    /// Each family of accessors has its own flag, so, e.g., only getters and
+    /// setters are generated with `GenOpt::TRIVIAL_GET | GenOpt::TRIVIAL_SET`.
+    /// Getters and setters which convert their field, e.g., for optional and
+    /// enum fields, are generated whichever flags are set.
     pub struct GenOpt: u32 {
         /// Generate implementation for trait `::protobuf::Message`.
         const MESSAGE = 0b0000_0001;
-        /// Generate getters.
+        /// Generate getters, including `get_*_seconds` and `get_*_nanos` for
+        /// fields of the well-known `Timestamp` and `Duration` types.
         const TRIVIAL_GET = 0b0000_0010;
         /// Generate setters.
         const TRIVIAL_SET = 0b0000_0100;
-        /// Generate the `new_` constructors.
+        /// Generate the `new_` constructors, see `WrapperConfig::constructor_name`.
         const NEW = 0b0000_1000;
         /// Generate `clear_*` functions.
         const CLEAR = 0b0001_0000;
         /// Generate `has_*` functions.
         const HAS = 0b0010_0000;
-        /// Generate mutable getters.
+        /// Generate mutable getters. For optional fields, `mut_*` inserts a
+        /// default if the field is unset, and `mut_*_opt` returns `None`.
+        /// Optional message fields also get `mut_*_or_insert_with`, which
+        /// inserts the result of a closure instead of a default. Repeated,
+        /// string, and bytes fields stored in a `Vec` or `String` also get
+        /// `reserve_*`.
         const MUT = 0b0100_0000;
         /// Generate `take_*` functions.
         const TAKE = 0b1000_0000;
+        /// Generate conversions between fields of the well-known `Timestamp`
+        /// and `Duration` types and their `std::time` equivalents, and
+        /// `pack_*` and `unpack_*` functions for fields of type `Any`.
+        const WELL_KNOWN_TYPES = 0b1_0000_0000;
+        /// Generate a `copy_from` function which copies every field from
+        /// another message.
+        const COPY_FROM = 0b10_0000_0000;
+        /// Generate accessors for individual elements of repeated fields, e.g.,
+        /// `get_*_at`, `mut_*_at`, and `add_*`. Scalar elements are returned
+        /// by value, enum elements as their enum, and string and bytes
+        /// elements as `&str` and `&[u8]`. For maps of messages, generate
+        /// `get_*_or_default` and `mut_*_or_default`, which return the value
+        /// for a key, or the default value.
+        const INDEXED = 0b100_0000_0000;
+        /// Generate a `FIELDS` constant listing the name, tag, and kind of
+        /// each field, and a `*_TAGS` constant listing the tags of each oneof,
+        /// both ordered by tag.
+        const FIELDS = 0b1000_0000_0000;
+        /// Generate `has_*` functions for singular scalar, string, bytes, and
+        /// enum fields, which return whether the field differs from its
+        /// default value.
+        const SCALAR_HAS = 0b1_0000_0000_0000;
+        /// Generate implementations of a `MessageExt` trait, which provides
+        /// `new_` and `default_instance` for use in generic code. The trait
+        /// is declared at the top of the generated `mod.rs`.
+        const MESSAGE_EXT = 0b10_0000_0000_0000;
+        /// Generate `to_vec`, `from_bytes`, `encoded_len`, and
+        /// `merge_length_delimited` functions which encode, decode, and size
+        /// messages using Prost, without needing `prost::Message` in scope.
+        const ENCODE_DECODE = 0b100_0000_0000_0000;
+        /// Generate `iter_*` and `iter_*_mut` functions which iterate over
+        /// the elements of repeated fields.
+        const ITER = 0b1000_0000_0000_0000;
+        /// Generate a `#[cfg(test)]` module for each message which checks
+        /// that its fields can be set, read back, and cleared. Only generated
+        /// along with getters, setters, `has_*`, and `clear_*` functions.
+        const TESTS = 0b1_0000_0000_0000_0000;
+        /// Generate `clear_field_by_name` and `copy_field_by_name` functions,
+        /// which clear a field or copy it from another message given the
+        /// field's name, e.g., to apply a `FieldMask`. They return `false` if
+        /// there is no such field.
+        const FIELD_MASK = 0b10_0000_0000_0000_0000;
+        /// Generate a `merge_from` function which merges another message
+        /// into this one: fields which are set in the other message overwrite
+        /// those of this one, except that repeated fields and maps are
+        /// extended, and message fields are merged recursively (so their
+        /// messages must also have a `merge_from`).
+        const MERGE_FROM = 0b100_0000_0000_0000_0000;
+        /// Generate `clone_*` functions for message, repeated, and map fields,
+        /// which return a clone of the field's value without modifying the
+        /// message, unlike `take_*`.
+        const CLONE = 0b1000_0000_0000_0000_0000;
+        /// Generate `for_each_string_field_mut` and `for_each_bytes_field_mut`
+        /// functions, which call a closure on every string (or bytes) value
+        /// of a message's singular, optional, and repeated fields, e.g., to
+        /// trim or normalize them. They aren't generated for messages without
+        /// such fields, and don't recurse into nested messages.
+        const VISIT_MUT = 0b1_0000_0000_0000_0000_0000;
+        /// Generate a module for each message, e.g., `foo_bar_field_names`
+        /// for `FooBar`, with a constant for the name of each field, e.g.,
+        /// `pub const ID: &str = "id";`, so that the paths of a `FieldMask`
+        /// can be built without string literals. A oneof is named by its
+        /// field, as in `clear_field_by_name`.
+        const FIELD_NAMES = 0b10_0000_0000_0000_0000_0000;
+        /// Generate `full_name` and `type_url` functions which return the
+        /// fully-qualified protobuf name of a message, e.g., `foo.Bar.Baz`,
+        /// and its type URL for packing it into an `Any`.
+        ///
+        /// The names are those in the descriptors given to
+        /// `WrapperGen::descriptor_set`, if any. Otherwise they are guessed
+        /// from Prost's output: the package is the name of the input file and
+        /// messages are named by their types. Prost changes the case of some
+        /// names, e.g., `HTTPRequest` to `HttpRequest`, so the guessed names
+        /// may not match other implementations.
+        const FULL_NAME = 0b100_0000_0000_0000_0000_0000;
+        /// Generate an `is_default` function which returns whether every
+        /// field of a message has its default value, without constructing a
+        /// default message, e.g., to only send messages which have changed.
+        const IS_DEFAULT = 0b1000_0000_0000_0000_0000_0000;
         /// Except `impl protobuf::Message`.
         const NO_MSG = Self::TRIVIAL_GET.bits
          | Self::TRIVIAL_SET.bits
@@ -238,5 +452,26 @@ bitflags! {
          | Self::TRIVIAL_SET.bits
          | Self::MUT.bits
          | Self::TAKE.bits;
+        /// As much of the API generated by rust-protobuf as can be generated,
+        /// for migrating code which uses it: the default items (accessors,
+        /// constructors, and `impl protobuf::Message`, which provides
+        /// `default_instance` and, via `protobuf::Clear`, `clear`) and
+        /// `merge_from`, `copy_from`, and accessors for elements of repeated
+        /// fields. rust-protobuf's constructor is `new`, see
+        /// `WrapperConfig::constructor_name`.
+        const RUST_PROTOBUF = Self::MESSAGE.bits
+         | Self::NEW.bits
+         | Self::NO_MSG.bits
+         | Self::COPY_FROM.bits
+         | Self::INDEXED.bits
+         | Self::MERGE_FROM.bits;
+    }
+}
+
+impl Default for GenOpt {
+    /// Everything needed for the rust-protobuf style API, but none of the
+    /// optional extras.
+    fn default() -> GenOpt {
+        GenOpt::MESSAGE | GenOpt::NEW | GenOpt::NO_MSG
     }
 }
@@ -0,0 +1,229 @@
+// Copyright 2019 PingCAP, Inc.
+
+//! Generates wrappers for Prost output outside of a build script.
+//!
+//! ```text
+//! protobuf-build --in <file> [--in <file> ...] [--out <dir>] [options]
+//! ```
+
+use std::env;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::SystemTime;
+
+use protobuf_build::{FileSink, GenOpt, Sink, WrapperConfig, WrapperGen};
+
+const USAGE: &str = "usage: protobuf-build --in <file> [--in <file> ...] [--out <dir>] [options]
+
+Generates wrappers for files of Prost output. Wrappers are written next to
+their input file, or to the directory given by `--out`.
+
+options:
+    --in <file>             a file of Prost output, may be repeated
+    --out <dir>             the directory to write wrappers to
+    --opt <name>            generate the given items, may be repeated; if not
+                            given, the default items are generated
+    --wrapper-prefix <s>    name wrapper files `<s><file>` (default `wrapper_`)
+    --module <name>         generate wrappers inside the module `name`
+    --wrap-module <path>    only wrap messages in the module `path`, may be
+                            repeated
+    --method-prefix <s>     prefix the names of generated accessors with `s`
+    --constructor <name>    name generated constructors `name` (default `new_`)
+    --no-std                use `core` and `alloc` rather than `std`
+    --pretty                format the generated code
+    --strict                error on messages which won't be wrapped
+    --skip-unknown-fields   skip fields of unsupported types, rather than error
+    --incremental           don't regenerate wrappers newer than their input
+    --split <n>             split each wrapper into `n` files
+    --crlf                  write files with CRLF line endings
+    --splice                replace only the lines between `// BEGIN generated`
+                            and `// END generated` in existing wrapper files
+    --descriptor-set <file> document accessors using an encoded
+                            `FileDescriptorSet` of the input's `.proto` files
+    --force                 overwrite existing files which weren't generated,
+                            or are up to date
+    --help                  print this message";
+
+fn main() {
+    if let Err(e) = run(env::args().skip(1)) {
+        eprintln!("error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run(mut args: impl Iterator<Item = String>) -> Result<(), String> {
+    let mut inputs = Vec::new();
+    let mut out_dir = None;
+    let mut gen_opt = None;
+    let mut force = false;
+    let mut descriptor_set = None;
+    let mut config = WrapperConfig::new();
+
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("missing value for `{}`", arg))
+        };
+        match &*arg {
+            "--in" => inputs.push(PathBuf::from(value()?)),
+            "--out" => out_dir = Some(PathBuf::from(value()?)),
+            "--opt" => {
+                let opt = value()?;
+                let opt =
+                    gen_opt_from_name(&opt).ok_or_else(|| format!("unknown option `{}`", opt))?;
+                gen_opt = Some(gen_opt.unwrap_or_else(GenOpt::empty) | opt);
+            }
+            "--wrapper-prefix" => {
+                let prefix = value()?;
+                config.file_name(move |name| format!("{}{}", prefix, name));
+            }
+            "--module" => {
+                config.module(value()?);
+            }
+            "--wrap-module" => {
+                config.wrap_module(value()?);
+            }
+            "--method-prefix" => {
+                config.method_prefix(value()?);
+            }
+            "--constructor" => {
+                config.constructor_name(value()?);
+            }
+            "--no-std" => {
+                config.no_std(true);
+            }
+            "--pretty" => {
+                config.pretty_print(true);
+            }
+            "--strict" => {
+                config.strict(true);
+            }
+            "--skip-unknown-fields" => {
+                config.skip_unknown_fields(true);
+            }
+            "--incremental" => {
+                config.incremental(true);
+            }
+            "--split" => {
+                let count = value()?;
+                let count = count
+                    .parse()
+                    .map_err(|_| format!("invalid number of files `{}`", count))?;
+                config.split_files(count);
+            }
+            "--descriptor-set" => {
+                let path = value()?;
+                descriptor_set =
+                    Some(std::fs::read(&path).map_err(|e| format!("{}: {}", path, e))?);
+            }
+            "--crlf" => {
+                config.crlf_line_endings(true);
+            }
+            "--splice" => {
+                config.splice_markers(true);
+            }
+            "--force" => force = true,
+            "--help" => {
+                println!("{}", USAGE);
+                return Ok(());
+            }
+            _ => return Err(format!("unknown argument `{}`\n\n{}", arg, USAGE)),
+        }
+    }
+
+    if inputs.is_empty() {
+        return Err(format!("no input files\n\n{}", USAGE));
+    }
+    // Wrappers are named after their input.
+    if let Some(input) = inputs.iter().find(|i| i.file_name().is_none()) {
+        return Err(format!(
+            "input `{}` has no file name\n\n{}",
+            input.display(),
+            USAGE
+        ));
+    }
+    if force {
+        config.incremental(false);
+    }
+    config.overwrite_guard(!force);
+
+    let gen_opt = gen_opt.unwrap_or_default();
+    for input in inputs {
+        let mut gen = WrapperGen::new(input, gen_opt, config.clone()).map_err(|e| e.to_string())?;
+        if let Some(bytes) = &descriptor_set {
+            gen.descriptor_set(bytes).map_err(|e| e.to_string())?;
+        }
+        match &out_dir {
+            Some(dir) => gen.write_to(&mut DirSink(dir)),
+            None => gen.write(),
+        }
+        .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+fn gen_opt_from_name(name: &str) -> Option<GenOpt> {
+    Some(match name {
+        "message" => GenOpt::MESSAGE,
+        "trivial_get" => GenOpt::TRIVIAL_GET,
+        "trivial_set" => GenOpt::TRIVIAL_SET,
+        "new" => GenOpt::NEW,
+        "clear" => GenOpt::CLEAR,
+        "has" => GenOpt::HAS,
+        "mut" => GenOpt::MUT,
+        "take" => GenOpt::TAKE,
+        "well_known_types" => GenOpt::WELL_KNOWN_TYPES,
+        "copy_from" => GenOpt::COPY_FROM,
+        "indexed" => GenOpt::INDEXED,
+        "fields" => GenOpt::FIELDS,
+        "scalar_has" => GenOpt::SCALAR_HAS,
+        "message_ext" => GenOpt::MESSAGE_EXT,
+        "encode_decode" => GenOpt::ENCODE_DECODE,
+        "iter" => GenOpt::ITER,
+        "tests" => GenOpt::TESTS,
+        "field_mask" => GenOpt::FIELD_MASK,
+        "field_names" => GenOpt::FIELD_NAMES,
+        "full_name" => GenOpt::FULL_NAME,
+        "is_default" => GenOpt::IS_DEFAULT,
+        "merge_from" => GenOpt::MERGE_FROM,
+        "clone" => GenOpt::CLONE,
+        "visit_mut" => GenOpt::VISIT_MUT,
+        "no_msg" => GenOpt::NO_MSG,
+        "accessor" => GenOpt::ACCESSOR,
+        "rust_protobuf" => GenOpt::RUST_PROTOBUF,
+        "default" => GenOpt::default(),
+        "all" => GenOpt::all(),
+        _ => return None,
+    })
+}
+
+// Writes files into a directory, rather than next to their input.
+struct DirSink<'a>(&'a Path);
+
+impl Sink for DirSink<'_> {
+    fn create(&mut self, path: &Path) -> Result<Box<dyn Write + '_>, io::Error> {
+        Ok(Box::new(BufWriter::new(File::create(self.path(path)?)?)))
+    }
+
+    fn read(&mut self, path: &Path) -> Result<Option<Vec<u8>>, io::Error> {
+        FileSink.read(&self.path(path)?)
+    }
+
+    fn modified(&mut self, path: &Path) -> Result<Option<SystemTime>, io::Error> {
+        FileSink.modified(&self.path(path)?)
+    }
+}
+
+impl DirSink<'_> {
+    fn path(&self, path: &Path) -> Result<PathBuf, io::Error> {
+        match path.file_name() {
+            Some(name) => Ok(self.0.join(name)),
+            None => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{}: no file name", path.display()),
+            )),
+        }
+    }
+}
@@ -1,8 +1,10 @@
 use crate::wrapper::WrapperGen;
 use crate::Builder;
+use std::io;
+use std::path::Path;
 
 impl Builder {
-    pub fn generate_files(&self) {
+    pub fn generate_files(&self) -> Result<(), io::Error> {
         #[cfg(feature = "grpcio-prost-codec")]
         {
             grpcio_compiler::prost_codegen::compile_protos(
@@ -16,11 +18,14 @@ impl Builder {
         {
             prost_build::Config::new()
                 .out_dir(&self.out_dir)
-                .compile_protos(&self.files, &self.includes)
-                .unwrap();
+                .compile_protos(&self.files, &self.includes)?;
         }
 
-        self.list_rs_files()
-            .for_each(|path| WrapperGen::new(path, self.wrapper_opts).write());
+        WrapperGen::write_dir(
+            Path::new(&self.out_dir),
+            self.wrapper_opts,
+            &self.wrapper_config,
+        )?;
+        Ok(())
     }
 }
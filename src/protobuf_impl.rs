@@ -2,7 +2,7 @@
 
 use std::env;
 use std::fs::File;
-use std::io::{Read, Write};
+use std::io::{self, Read, Write};
 use std::path::Path;
 use std::process::Command;
 use std::str::from_utf8;
@@ -59,7 +59,7 @@ fn check_protoc_version(protoc: &str) -> Result<String, ()> {
 }
 
 impl Builder {
-    pub fn generate_files(&self) {
+    pub fn generate_files(&self) -> Result<(), io::Error> {
         let mut cmd = Command::new(get_protoc());
         let desc_file = format!("{}/mod.desc", self.out_dir);
         for i in &self.includes {
@@ -78,9 +78,9 @@ impl Builder {
             e => panic!("failed to generate descriptor set files: {:?}", e),
         }
 
-        let desc_bytes = std::fs::read(&desc_file).unwrap();
+        let desc_bytes = std::fs::read(&desc_file)?;
         let desc: protobuf::descriptor::FileDescriptorSet =
-            protobuf::parse_from_bytes(&desc_bytes).unwrap();
+            protobuf::Message::parse_from_bytes(&desc_bytes).unwrap();
         let mut files_to_generate = Vec::new();
         'outer: for file in &self.files {
             for include in &self.includes {
@@ -99,13 +99,14 @@ impl Builder {
         protobuf_codegen::gen_and_write(
             desc.get_file(),
             &files_to_generate,
-            &Path::new(&self.out_dir),
+            Path::new(&self.out_dir),
             &protobuf_codegen::Customize::default(),
         )
         .unwrap();
-        self.generate_grpcio(&desc.get_file(), &files_to_generate);
+        self.generate_grpcio(desc.get_file(), &files_to_generate);
         self.import_grpcio();
         self.replace_read_unknown_fields();
+        Ok(())
     }
 
     /// Convert protobuf files to use the old way of reading protobuf enums.
@@ -1,760 +1,8859 @@
 // Copyright 2019 PingCAP, Inc.
 
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
 use std::fs::{self, File};
 use std::io::{self, BufWriter, Write};
-use std::path::PathBuf;
+use std::ops::{Deref, DerefMut};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
 use proc_macro2::Span;
+use prost_types::{DescriptorProto, FileDescriptorProto, FileDescriptorSet};
 use quote::ToTokens;
+use syn::visit::{self, Visit};
 use syn::{
-    Attribute, GenericArgument, Ident, Item, ItemEnum, ItemStruct, Meta, NestedMeta, PathArguments,
-    Token, Type, TypePath,
+    Attribute, GenericArgument, Ident, Item, ItemEnum, ItemStruct, Lit, Meta, NestedMeta,
+    PathArguments, Token, Type, TypePath,
 };
 
-use crate::GenOpt;
+use crate::{Event, FieldInfo, GenOpt, WrapperConfig};
 
+/// Generates wrapper code (accessors, etc.) for a file of Prost output.
+///
+/// The input must be the code Prost generates, in which messages derive
+/// Prost's `Message` (or a derive named by `WrapperConfig::message_derive`)
+/// and enums derive `Enumeration`. Macros aren't expanded, so source in which
+/// the derives are produced by some other macro, e.g., an attribute macro
+/// wrapping Prost, has nothing to wrap. A file which declares structs or
+/// enums, none of which are Prost messages or enums, is an error.
+///
+/// Prost doesn't generate code for proto2 extensions, so there is nothing to
+/// wrap: extension ranges and fields declared in `extend` blocks are ignored.
+/// Any field of a kind the generator doesn't recognize, such as one added by a
+/// newer Prost, is an error naming its message, unless it is skipped with
+/// `WrapperConfig::skip_unknown_fields`. Fields without a `#[prost(...)]`
+/// attribute, e.g., added to a message by hand, don't get accessors, and
+/// those marked `#[prost(skip)]` get plain accessors, see
+/// `WrapperConfig::skip_ignored_fields`.
 pub struct WrapperGen {
-    input: String,
+    input: Input,
     input_file: PathBuf,
     gen_opt: GenOpt,
+    config: WrapperConfig,
+    // The descriptors of the input's `.proto` files, see `descriptor_set`.
+    descriptors: Vec<FileDescriptorProto>,
+}
+
+// The Prost output a `WrapperGen` wraps.
+enum Input {
+    Source(String),
+    Ast(syn::File),
 }
 
 impl WrapperGen {
-    pub fn new(file_name: PathBuf, gen_opt: GenOpt) -> WrapperGen {
-        let input = String::from_utf8(
-            fs::read(&file_name).unwrap_or_else(|_| panic!("Could not read {:?}", file_name)),
-        )
-        .expect("File not utf8");
+    /// Create a generator for the Prost output in `file_name`.
+    ///
+    /// Returns an error naming the file if it can't be read or isn't UTF-8.
+    pub fn new(
+        file_name: PathBuf,
+        gen_opt: GenOpt,
+        config: WrapperConfig,
+    ) -> Result<WrapperGen, io::Error> {
+        let input = fs::read_to_string(&file_name)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", file_name.display(), e)))?;
+        Ok(WrapperGen::from_source(file_name, input, gen_opt, config))
+    }
+
+    /// Create a generator for Prost output which has already been read into
+    /// memory. `file_name` is used to name the output and in error messages,
+    /// it is not read.
+    pub fn from_source(
+        file_name: PathBuf,
+        input: String,
+        gen_opt: GenOpt,
+        config: WrapperConfig,
+    ) -> WrapperGen {
         WrapperGen {
-            input,
+            input: Input::Source(input),
             gen_opt,
+            config,
             input_file: file_name,
+            descriptors: Vec::new(),
         }
     }
 
-    pub fn write(&self) {
-        let mut path = self.input_file.clone();
-        path.set_file_name(format!(
-            "wrapper_{}",
-            path.file_name().unwrap().to_str().unwrap()
-        ));
-        let mut out = BufWriter::new(File::create(&path).expect("Could not create file"));
-        self.generate(&mut out).expect("Error generating code");
+    /// Create a generator for Prost output which has already been parsed,
+    /// e.g., by a larger syn-based code generator, avoiding printing and
+    /// re-parsing it. `file_name` is used as for `from_source`.
+    pub fn from_ast(
+        file_name: PathBuf,
+        file: syn::File,
+        gen_opt: GenOpt,
+        config: WrapperConfig,
+    ) -> WrapperGen {
+        WrapperGen {
+            input: Input::Ast(file),
+            gen_opt,
+            config,
+            input_file: file_name,
+            descriptors: Vec::new(),
+        }
     }
 
-    fn generate<W>(&self, buf: &mut W) -> Result<(), io::Error>
-    where
-        W: Write,
-    {
-        let file = ::syn::parse_file(&self.input).expect("Could not parse file");
-        writeln!(buf, "// Generated file, please don't edit manually.\n")?;
-        generate_from_items(&file.items, self.gen_opt, "", buf)
+    /// Use the descriptors in `bytes`, an encoded `FileDescriptorSet` (e.g.,
+    /// written by `protoc --include_source_info --descriptor_set_out`), for
+    /// metadata which isn't in Prost's output. Accessors are documented with
+    /// their field's comment, and those of deprecated fields are marked
+    /// `#[deprecated]`, as with `WrapperConfig::field_doc` and
+    /// `WrapperConfig::deprecated_field`. Messages' full names, see
+    /// `GenOpt::FULL_NAME`, are taken from their descriptors.
+    ///
+    /// Only the descriptors of files in the input's package, i.e., the input
+    /// file's name as given by Prost, are used. Messages are matched by name
+    /// and fields by tag, fields without a descriptor are unchanged.
+    pub fn descriptor_set(&mut self, bytes: &[u8]) -> Result<(), io::Error> {
+        let set = <FileDescriptorSet as prost::Message>::decode(bytes).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("invalid descriptor set: {}", e),
+            )
+        })?;
+        let package = match self.package() {
+            Some(package) => package,
+            None => return Ok(()),
+        };
+        self.descriptors = set
+            .file
+            .into_iter()
+            .filter(|f| f.package() == package)
+            .collect();
+        Ok(())
     }
-}
 
-fn generate_from_items<W>(
-    items: &[Item],
-    gen_opt: GenOpt,
-    prefix: &str,
-    buf: &mut W,
-) -> Result<(), io::Error>
-where
-    W: Write,
-{
-    for item in items {
-        if let Item::Struct(item) = item {
-            if is_message(&item.attrs) {
-                generate_struct(item, gen_opt, prefix, buf)?;
+    /// Write wrappers for every file of Prost output in `dir`, e.g., the
+    /// output directory of prost-build, next to their input files.
+    ///
+    /// Every `.rs` file is wrapped, except `mod.rs` and existing wrapper files
+    /// (as named by `config`). Returns the paths of the wrapped files.
+    pub fn write_dir(
+        dir: &Path,
+        gen_opt: GenOpt,
+        config: &WrapperConfig,
+    ) -> Result<Vec<PathBuf>, io::Error> {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.is_file() && path.extension().is_some_and(|e| e == "rs") {
+                files.push(path);
             }
-        } else if let Item::Enum(item) = item {
-            if is_enum(&item.attrs) {
-                generate_enum(item, prefix, buf)?;
-            }
-        } else if let Item::Mod(m) = item {
-            if let Some(ref content) = m.content {
-                let prefix = format!("{}{}::", prefix, m.ident);
-                generate_from_items(&content.1, gen_opt, &prefix, buf)?;
+        }
+        files.sort();
+
+        let mut wrappers = HashSet::new();
+        for name in files.iter().filter_map(|path| path.file_name()?.to_str()) {
+            wrappers.insert(config.wrapper_file_name(name));
+            if config.split_count() > 1 {
+                for index in 0..config.split_count() {
+                    wrappers.insert(config.wrapper_part_file_name(name, index));
+                }
             }
         }
+        files.retain(|path| match path.file_name().and_then(|n| n.to_str()) {
+            Some(name) => name != "mod.rs" && !wrappers.contains(name),
+            None => false,
+        });
+
+        for path in &files {
+            WrapperGen::new(path.clone(), gen_opt, config.clone())?.write()?;
+        }
+        Ok(files)
     }
-    Ok(())
-}
 
-fn generate_struct<W>(
-    item: &ItemStruct,
-    gen_opt: GenOpt,
-    prefix: &str,
-    buf: &mut W,
-) -> Result<(), io::Error>
-where
-    W: Write,
-{
-    writeln!(buf, "impl {}{} {{", prefix, item.ident)?;
-    if gen_opt.contains(GenOpt::NEW) {
-        generate_new(&item.ident, prefix, buf)?;
+    /// Write a file to `path` which declares a module for each of `files`
+    /// (files of Prost output, e.g., as returned by `write_dir`) and includes
+    /// the Prost output and its wrapper in that module.
+    ///
+    /// As for Prost, modules are named after the file, e.g., `foo.bar.rs` is
+    /// included in `foo::bar`. Files in the build script's `OUT_DIR` are
+    /// included relative to `OUT_DIR`, so the written file may be included
+    /// with `include!(concat!(env!("OUT_DIR"), "/..."))`.
+    ///
+    /// `gen_opt` should be the options the wrappers were generated with, e.g.,
+    /// the `MessageExt` trait is declared for `GenOpt::MESSAGE_EXT`.
+    pub fn write_mod_file(
+        path: &Path,
+        files: &[PathBuf],
+        gen_opt: GenOpt,
+        config: &WrapperConfig,
+    ) -> Result<(), io::Error> {
+        let out_dir = std::env::var_os("OUT_DIR").map(PathBuf::from);
+        let code = mod_file(files, gen_opt, config, out_dir.as_deref())?;
+        fs::write(path, with_line_endings(config, &code))
     }
-    generate_default_ref(&item.ident, prefix, gen_opt, buf)?;
-    item.fields
-        .iter()
-        .filter_map(|f| {
-            f.ident
-                .as_ref()
-                .map(|i| (i, &f.ty, FieldKind::from_attrs(&f.attrs, prefix)))
+
+    /// Write the wrapper file next to the input file.
+    pub fn write(&self) -> Result<Summary, io::Error> {
+        self.write_to(&mut FileSink)
+    }
+
+    // Whether `output` in `sink` was modified after the input file. If either
+    // doesn't exist, e.g., because the input was read from elsewhere, it isn't.
+    fn is_up_to_date(&self, sink: &mut dyn Sink, output: &Path) -> Result<bool, io::Error> {
+        let input = fs::metadata(&self.input_file)
+            .and_then(|m| m.modified())
+            .ok();
+        Ok(match (input, sink.modified(output)?) {
+            (Some(input), Some(output)) => output > input,
+            _ => false,
         })
-        .filter_map(|(n, t, k)| k.methods(t, n))
-        .map(|m| m.write_methods(buf, gen_opt))
-        .collect::<Result<Vec<_>, _>>()?;
-    writeln!(buf, "}}")?;
-    if gen_opt.contains(GenOpt::MESSAGE) {
-        generate_message_trait(&item.ident, prefix, buf)?;
     }
-    Ok(())
-}
 
-fn generate_enum<W>(item: &ItemEnum, prefix: &str, buf: &mut W) -> Result<(), io::Error>
-where
-    W: Write,
-{
-    writeln!(buf, "impl {}{} {{", prefix, item.ident)?;
-    writeln!(buf, "pub fn values() -> &'static [Self] {{")?;
-    writeln!(
-        buf,
-        "static VALUES: &'static [{}{}] = &[",
-        prefix, item.ident
-    )?;
-    for v in &item.variants {
-        writeln!(buf, "{}{}::{},", prefix, item.ident, v.ident)?;
+    /// Write the wrapper file to `sink`.
+    ///
+    /// With `WrapperConfig::split_files`, the files the wrapper is split into
+    /// are written before the wrapper file which includes them.
+    ///
+    /// With `WrapperConfig::incremental`, nothing is written if the wrapper
+    /// file in `sink` is newer than the input file, and the summary only
+    /// records that the file was up to date.
+    pub fn write_to(&self, sink: &mut dyn Sink) -> Result<Summary, io::Error> {
+        if self.config.is_incremental() && self.is_up_to_date(sink, &self.output_file()?)? {
+            return Ok(Summary {
+                up_to_date: true,
+                ..Summary::default()
+            });
+        }
+        if self.config.has_splice_markers() {
+            let path = self.output_file()?;
+            let existing = sink.read(&path)?.ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("{}: file does not exist", path.display()),
+                )
+            })?;
+            let (code, summary) = self.splice(&path, &existing)?;
+            let mut out = sink.create(&path)?;
+            out.write_all(code.as_bytes())?;
+            out.flush()?;
+            return Ok(summary);
+        }
+        if self.config.split_count() > 1 {
+            let (files, summary) = self.generate_split()?;
+            for (path, _) in &files {
+                self.check_overwrite(sink, path)?;
+            }
+            for (path, code) in files {
+                let mut out = sink.create(&path)?;
+                out.write_all(code.as_bytes())?;
+                out.flush()?;
+            }
+            return Ok(summary);
+        }
+
+        let path = self.output_file()?;
+        self.check_overwrite(sink, &path)?;
+        let mut out = sink.create(&path)?;
+        let summary = self.generate(&mut out)?;
+        out.flush()?;
+        Ok(summary)
     }
-    writeln!(buf, "];\nVALUES\n}}")?;
-    writeln!(buf, "}}")
-}
 
-fn generate_new<W>(name: &Ident, prefix: &str, buf: &mut W) -> Result<(), io::Error>
-where
-    W: Write,
-{
-    writeln!(
-        buf,
-        "pub fn new_() -> {}{} {{ ::std::default::Default::default() }}",
-        prefix, name,
-    )
-}
+    // Replaces the code between the markers in `existing`, the contents of
+    // `path`, with the wrapper code, see `WrapperConfig::splice_markers`.
+    fn splice(&self, path: &Path, existing: &[u8]) -> Result<(String, Summary), io::Error> {
+        let invalid = |msg: &str| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), msg),
+            )
+        };
+        if self.config.split_count() > 1 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{}: a wrapper spliced into a file can't be split into files",
+                    path.display()
+                ),
+            ));
+        }
+        let existing = std::str::from_utf8(existing).map_err(|_| invalid("file not utf8"))?;
+        // The generated code starts on the line after the begin marker, and
+        // ends before the line of the end marker.
+        let start = existing
+            .find(BEGIN_MARKER)
+            .map(|i| {
+                existing[i..]
+                    .find('\n')
+                    .map_or(existing.len(), |j| i + j + 1)
+            })
+            .ok_or_else(|| invalid(&format!("no `{}` marker", BEGIN_MARKER)))?;
+        let end = existing[start..]
+            .find(END_MARKER)
+            .map(|i| existing[..start + i].rfind('\n').map_or(0, |j| j + 1))
+            .ok_or_else(|| invalid(&format!("no `{}` marker", END_MARKER)))?;
+        let mut code = Vec::new();
+        let summary = self.generate(&mut code)?;
+        let code = String::from_utf8(code).expect("Generated code not utf8");
+        Ok((
+            format!("{}{}{}", &existing[..start], code, &existing[end..]),
+            summary,
+        ))
+    }
 
-fn generate_default_ref<W>(
-    name: &Ident,
-    prefix: &str,
-    gen_opt: GenOpt,
-    buf: &mut W,
-) -> Result<(), io::Error>
-where
-    W: Write,
-{
-    if gen_opt.contains(GenOpt::MESSAGE) {
-        writeln!(
-            buf,
-            "#[inline] pub fn default_ref() -> &'static Self {{ ::protobuf::Message::default_instance() }}",
-        )
-    } else {
-        writeln!(
-            buf,
-            "#[inline] pub fn default_ref() -> &'static Self {{
-                ::lazy_static::lazy_static! {{
-                    static ref INSTANCE: {0}{1} = {0}{1}::default();
-                }}
-                &*INSTANCE
-            }}",
-            prefix, name,
+    fn check_overwrite(&self, sink: &mut dyn Sink, path: &Path) -> Result<(), io::Error> {
+        if self.config.has_overwrite_guard() {
+            if let Some(existing) = sink.read(path)? {
+                if !existing.starts_with(HEADER.as_bytes()) {
+                    return Err(io::Error::new(
+                        io::ErrorKind::AlreadyExists,
+                        format!(
+                            "{}: not a generated file, refusing to overwrite it",
+                            path.display()
+                        ),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Compare the wrapper file on disk with what would be generated, without
+    /// writing anything.
+    ///
+    /// Returns `None` if the file is up to date, otherwise a description of
+    /// the first difference. A missing wrapper file counts as a difference.
+    pub fn diff(&self) -> Result<Option<String>, io::Error> {
+        if self.config.has_splice_markers() {
+            let path = self.output_file()?;
+            return match FileSink.read(&path)? {
+                Some(existing) => diff_file(&path, &self.splice(&path, &existing)?.0),
+                None => Ok(Some(format!("{}: file does not exist", path.display()))),
+            };
+        }
+        if self.config.split_count() > 1 {
+            let (files, _) = self.generate_split()?;
+            for (path, expected) in files {
+                if let Some(diff) = diff_file(&path, &expected)? {
+                    return Ok(Some(diff));
+                }
+            }
+            return Ok(None);
+        }
+
+        let path = self.output_file()?;
+        let mut buf = Vec::new();
+        self.generate(&mut buf)?;
+        diff_file(
+            &path,
+            &String::from_utf8(buf).expect("Generated code not utf8"),
         )
     }
-}
 
-fn generate_message_trait<W>(name: &Ident, prefix: &str, buf: &mut W) -> Result<(), io::Error>
-where
-    W: Write,
-{
-    write!(buf, "impl ::protobuf::Clear for {}{} {{", prefix, name)?;
-    writeln!(
-        buf,
-        "fn clear(&mut self) {{ ::prost::Message::clear(self); }}",
-    )?;
-    writeln!(buf, "}}")?;
+    /// Check that the input can be parsed and that every field is supported,
+    /// without generating any code.
+    ///
+    /// Returns an error if the input can't be parsed, otherwise a list of
+    /// fields which would not get accessors.
+    pub fn validate(&self) -> Result<Vec<Warning>, io::Error> {
+        let file = self.parse()?;
+        let mut warnings = Vec::new();
+        validate_items(&file.items, &self.config, "", &mut warnings);
+        Ok(warnings)
+    }
 
-    write!(buf, "impl ::protobuf::Message for {}{} {{", prefix, name)?;
-    writeln!(
-        buf,
-        "fn compute_size(&self) -> u32 {{ ::prost::Message::encoded_len(self) as u32 }}",
-    )?;
-    writeln!(
-        buf,
-        "fn get_cached_size(&self) -> u32 {{ ::prost::Message::encoded_len(self) as u32 }}",
-    )?;
-    writeln!(
-        buf,
-        "fn as_any(&self) -> &dyn ::std::any::Any {{ self as &dyn ::std::any::Any }}",
-    )?;
-    writeln!(
-        buf,
-        "fn descriptor(&self) -> &'static ::protobuf::reflect::MessageDescriptor {{ Self::descriptor_static() }}",
-    )?;
-    writeln!(buf, "fn new() -> Self {{ Self::default() }}",)?;
-    writeln!(
-        buf,
-        "fn default_instance() -> &'static {}{} {{
-        ::lazy_static::lazy_static! {{
-            static ref INSTANCE: {0}{1} = {0}{1}::default();
-        }}
-        &*INSTANCE
-    }}",
-        prefix, name,
-    )?;
-    // The only way for this to be false is if there are `required` fields, but
-    // afaict, we never use that feature. In any case rust-protobuf plans to
-    // always return `true` in 3.0.
-    writeln!(buf, "fn is_initialized(&self) -> bool {{ true }}",)?;
-    writeln!(
-        buf,
-        "fn write_to_with_cached_sizes(&self, _os: &mut ::protobuf::CodedOutputStream) -> ::protobuf::ProtobufResult<()> {{ unimplemented!(); }}",
-    )?;
-    writeln!(
-        buf,
-        "fn merge_from(&mut self, _is: &mut ::protobuf::CodedInputStream) -> ::protobuf::ProtobufResult<()> {{ unimplemented!(); }}",
-    )?;
-    writeln!(
-        buf,
-        "fn get_unknown_fields(&self) -> &::protobuf::UnknownFields {{ unimplemented!(); }}",
-    )?;
-    writeln!(
-        buf,
-        "fn mut_unknown_fields(&mut self) -> &mut ::protobuf::UnknownFields {{ unimplemented!(); }}",
-    )?;
-    writeln!(
-        buf,
-        "fn write_to_bytes(&self) -> ::protobuf::ProtobufResult<Vec<u8>> {{
-            let mut buf = Vec::new();
-            if ::prost::Message::encode(self, &mut buf).is_err() {{
-                return Err(::protobuf::ProtobufError::WireError(::protobuf::error::WireError::Other));
-            }}
-            Ok(buf)
-        }}"
-    )?;
-    writeln!(
-        buf,
-        "fn merge_from_bytes(&mut self, bytes: &[u8]) -> ::protobuf::ProtobufResult<()> {{
-            if ::prost::Message::merge(self, bytes).is_err() {{
-                return Err(::protobuf::ProtobufError::WireError(::protobuf::error::WireError::Other));
-            }}
-            Ok(())
-        }}"
-    )?;
-    writeln!(buf, "}}")
-}
+    /// Find fields which prevent their message from implementing `Hash` and
+    /// `Eq`, and so from being used as a key in a `HashMap`, i.e., `float` and
+    /// `double` fields.
+    ///
+    /// Returns an error if the input can't be parsed, otherwise a list of
+    /// such fields.
+    pub fn check_hashable(&self) -> Result<Vec<Warning>, io::Error> {
+        let file = self.parse()?;
+        let mut warnings = Vec::new();
+        unhashable_items(&file.items, &self.config, "", &mut warnings);
+        Ok(warnings)
+    }
 
-const INT_TYPES: [&str; 4] = ["int32", "int64", "uint32", "uint64"];
+    /// The messages which would be wrapped, without generating any code, e.g.,
+    /// to check that messages are recognized (see
+    /// `WrapperConfig::message_derive`).
+    ///
+    /// Messages are named as in configuration, e.g., `foo::Bar`, in the order
+    /// they appear in the input. Returns an error if the input can't be parsed.
+    pub fn message_names(&self) -> Result<Vec<String>, io::Error> {
+        let file = self.parse()?;
+        let mut names = Vec::new();
+        collect_message_names(&file.items, &self.config, "", &mut names);
+        Ok(names)
+    }
 
-#[derive(Clone, Eq, PartialEq, Debug, Ord, PartialOrd)]
-enum FieldKind {
-    Optional(Box<FieldKind>),
-    Repeated {
-        // A module prefix of a repeated message.
-        prefix: String,
-    },
-    Message,
-    Int,
-    Float,
-    Bool,
-    Bytes,
-    String,
-    OneOf(String),
-    Enumeration(String),
-    Map,
-    // Fixed are not handled.
-}
+    fn output_file(&self) -> Result<PathBuf, io::Error> {
+        Ok(self
+            .input_file
+            .with_file_name(self.config.wrapper_file_name(self.file_name()?)))
+    }
 
-impl FieldKind {
-    fn from_attrs(attrs: &[Attribute], prefix: &str) -> FieldKind {
-        for a in attrs {
-            if a.path.is_ident("prost") {
-                if let Ok(Meta::List(list)) = a.parse_meta() {
-                    let mut kinds = list
-                        .nested
-                        .iter()
-                        .filter_map(|item| {
-                            if let NestedMeta::Meta(Meta::Path(id)) = item {
-                                if id.is_ident("optional") {
-                                    Some(FieldKind::Optional(Box::new(FieldKind::Message)))
-                                } else if id.is_ident("message") {
-                                    Some(FieldKind::Message)
-                                } else if id.is_ident("repeated") {
-                                    Some(FieldKind::Repeated {
-                                        prefix: prefix.to_owned(),
-                                    })
-                                } else if id.is_ident("bytes") {
-                                    Some(FieldKind::Bytes)
-                                } else if id.is_ident("string") {
-                                    Some(FieldKind::String)
-                                } else if id.is_ident("bool") {
-                                    Some(FieldKind::Bool)
-                                } else if id.is_ident("float") || id.is_ident("double") {
-                                    Some(FieldKind::Float)
-                                } else if let Some(id) = id.get_ident() {
-                                    if INT_TYPES.contains(&id.to_string().as_str()) {
-                                        Some(FieldKind::Int)
-                                    } else {
-                                        None
-                                    }
-                                } else {
-                                    None
-                                }
-                            } else if let NestedMeta::Meta(Meta::NameValue(mnv)) = item {
-                                let value = mnv.lit.clone().into_token_stream().to_string();
-                                // Trim leading and trailing `"` and add prefix.
-                                let value = format!("{}{}", prefix, &value[1..value.len() - 1]);
-                                if mnv.path.is_ident("enumeration") {
-                                    Some(FieldKind::Enumeration(value))
-                                } else if mnv.path.is_ident("oneof") {
-                                    Some(FieldKind::OneOf(value))
-                                } else if mnv.path.is_ident("map") {
-                                    Some(FieldKind::Map)
-                                } else {
-                                    None
-                                }
-                            } else {
-                                None
-                            }
-                        })
-                        .collect::<Vec<_>>();
-                    kinds.sort();
-                    if !kinds.is_empty() {
-                        let mut iter = kinds.into_iter();
-                        let mut result = iter.next().unwrap();
-                        // If the type is an optional, keep looking to find the underlying type.
-                        if let FieldKind::Optional(_) = result {
-                            result = FieldKind::Optional(Box::new(iter.next().unwrap()));
-                        }
-                        return result;
-                    }
-                }
-            }
+    fn file_name(&self) -> Result<&str, io::Error> {
+        // `Path::file_name` ignores trailing slashes, but such a path names a directory.
+        let is_dir = self
+            .input_file
+            .to_string_lossy()
+            .ends_with(std::path::is_separator);
+        self.input_file
+            .file_name()
+            .and_then(|n| n.to_str())
+            .filter(|_| !is_dir)
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{}: not a valid file name", self.input_file.display()),
+                )
+            })
+    }
+
+    // The files a split wrapper is written to, see `WrapperConfig::split_files`,
+    // and their contents. The wrapper file itself is last.
+    fn generate_split(&self) -> Result<(Vec<(PathBuf, String)>, Summary), io::Error> {
+        if self.config.wrapper_module().is_some() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{}: a wrapper generated in a module can't be split into files",
+                    self.input_file.display()
+                ),
+            ));
+        }
+        let file_name = self.file_name()?;
+        let count = self.config.split_count();
+        let mut files = Vec::new();
+        let mut summary = Summary::default();
+        // The prelude is imported once, by the wrapper file.
+        let mut wrapper = Vec::new();
+        writeln!(wrapper, "{}\n", HEADER)?;
+        write_prelude(&self.config, &mut wrapper)?;
+        for index in 0..count {
+            let name = self.config.wrapper_part_file_name(file_name, index);
+            let mut buf = Vec::new();
+            let part = self.generate_part(&mut buf, Split::new(index, count))?;
+            summary = Summary {
+                methods: summary.methods + part.methods,
+                ..part
+            };
+            writeln!(wrapper, "include!({:?});", name)?;
+            files.push((
+                self.input_file.with_file_name(name),
+                String::from_utf8(buf).expect("Generated code not utf8"),
+            ));
+        }
+        let mut wrapper = String::from_utf8(wrapper).expect("Generated code not utf8");
+        if let Some(post_process) = self.config.post_processor() {
+            post_process(&mut wrapper);
         }
-        unreachable!("Unknown field kind");
+        files.push((
+            self.output_file()?,
+            with_line_endings(&self.config, &wrapper),
+        ));
+        Ok((files, summary))
     }
 
-    fn methods(&self, ty: &Type, ident: &Ident) -> Option<FieldMethods> {
-        let mut result = FieldMethods::new(ty, ident);
-        match self {
-            FieldKind::Optional(fk) => {
-                let unwrapped_type = unwrap_type(ty, "Option");
-                let unboxed_type = unwrap_type(&unwrapped_type, "Box");
-                let nested_methods = fk.methods(&unwrapped_type, ident).unwrap();
-                let unwrapped_type = unwrapped_type.into_token_stream().to_string();
-                let unboxed_type = unboxed_type.into_token_stream().to_string();
+    fn generate<W>(&self, buf: &mut W) -> Result<Summary, io::Error>
+    where
+        W: Write,
+    {
+        self.generate_part(buf, Split::WHOLE)
+    }
 
-                result.override_ty = Some(match nested_methods.override_ty {
-                    Some(t) => t,
-                    None => unwrapped_type.clone(),
-                });
-                result.ref_ty = nested_methods.ref_ty;
-                result.enum_set = nested_methods.enum_set;
-                result.has = true;
-                result.clear = Some("::std::option::Option::None".to_owned());
-                result.set = Some(match &**fk {
-                    FieldKind::Enumeration(_) => "::std::option::Option::Some(v as i32)".to_owned(),
-                    _ => "::std::option::Option::Some(v)".to_owned(),
-                });
+    fn generate_part<W>(&self, buf: &mut W, split: Split) -> Result<Summary, io::Error>
+    where
+        W: Write,
+    {
+        let buf = &mut LineEndings::new(buf, &self.config);
+        // Unless the code must be rewritten as a whole, stream it to `buf`
+        // as each message is generated.
+        if !self.config.is_no_std()
+            && !self.config.has_prelude()
+            && !self.config.is_pretty_print()
+            && self.config.post_processor().is_none()
+        {
+            return self.generate_code(buf, split);
+        }
 
-                let as_ref = match &result.ref_ty {
-                    RefType::Ref | RefType::Deref(_) => {
-                        let unwrapped_type = match &**fk {
-                            FieldKind::Bytes | FieldKind::Repeated { .. } => "::std::vec::Vec",
-                            _ => &unwrapped_type,
-                        };
-                        result.mt = MethodKind::Custom(format!(
-                            "if self.{}.is_none() {{
-                                self.{0} = ::std::option::Option::Some({1}::default());
-                            }}
-                            self.{0}.as_mut().unwrap()",
-                            result.name,
-                            type_in_expr_context(unwrapped_type),
+        let mut code = Vec::new();
+        let summary = self.generate_code(&mut code, split)?;
+        let mut code = rewrite_paths(
+            &self.config,
+            String::from_utf8(code).expect("Generated code not utf8"),
+        );
+        if self.config.is_pretty_print() {
+            code = pretty_print(&code).map_err(|e| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!(
+                        "{}: could not parse generated code: {}",
+                        self.input_file.display(),
+                        e
+                    ),
+                )
+            })?;
+        }
+        if let Some(post_process) = self.config.post_processor() {
+            post_process(&mut code);
+        }
+        buf.write_all(code.as_bytes())?;
+        Ok(summary)
+    }
+
+    /// Write the wrapper code for a single message to `buf`, e.g., to
+    /// regenerate or test it in isolation.
+    ///
+    /// `name` is the path to the message's type as for
+    /// `WrapperConfig::display_field`, e.g., `foo::Bar`. Only the message's
+    /// items are written, with paths relative to the top of the input, as
+    /// for a wrapper without `WrapperConfig::module`. They aren't formatted or
+    /// post-processed, and crate aliases (see `WrapperConfig::prelude`) must
+    /// be imported by the caller.
+    ///
+    /// Returns whether there is such a message, which would be wrapped.
+    pub fn generate_message<W>(&self, name: &str, buf: &mut W) -> Result<bool, io::Error>
+    where
+        W: Write,
+    {
+        let file = self.parse()?;
+        let (item, prefix) = match find_message(&file.items, name, "", &self.config) {
+            Some(found) => found,
+            None => return Ok(false),
+        };
+        let config = self.input_config(&file.items);
+        let mut oneofs = Oneofs::new();
+        collect_oneofs(&file.items, "", &mut oneofs);
+        let mut code = Vec::new();
+        generate_struct(
+            item,
+            self.gen_opt,
+            &config,
+            &oneofs,
+            &prefix,
+            &prefix,
+            &mut code,
+        )?;
+        let code = rewrite_paths(
+            &config,
+            String::from_utf8(code).expect("Generated code not utf8"),
+        );
+        buf.write_all(code.as_bytes())?;
+        Ok(true)
+    }
+
+    fn generate_code<W>(&self, buf: &mut W, mut split: Split) -> Result<Summary, io::Error>
+    where
+        W: Write,
+    {
+        let file = self.parse()?;
+        let config = self.input_config(&file.items);
+        let mut types = TypeCounts::default();
+        types.count(&file.items, &config);
+        if types.all > 0 && types.prost == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{}: no Prost messages or enums found, the input must be Prost output \
+                     with its derives expanded (see `WrapperConfig::message_derive` for \
+                     messages with other derives)",
+                    self.input_file.display(),
+                ),
+            ));
+        }
+        if config.is_strict() {
+            self.check_unprocessed(&file.items, 0)?;
+            self.check_enums(&file.items, &file.items, &config, "")?;
+        }
+        let mut oneofs = Oneofs::new();
+        collect_oneofs(&file.items, "", &mut oneofs);
+        writeln!(buf, "{}\n", HEADER)?;
+        if split.count == 1 {
+            write_prelude(&config, buf)?;
+        }
+        let methods = match config.wrapper_module() {
+            Some(module) => {
+                write_item_attributes(&config, buf)?;
+                writeln!(buf, "pub mod {} {{", module)?;
+                writeln!(buf, "#[allow(unused_imports)]\npub use super::*;")?;
+                write_prelude(&config, buf)?;
+                let methods =
+                    generate_nested(&file.items, self.gen_opt, &config, &oneofs, "", 1, buf)?;
+                writeln!(buf, "}}")?;
+                methods
+            }
+            None => generate_from_items(
+                &file.items,
+                self.gen_opt,
+                &config,
+                &oneofs,
+                "",
+                &mut split,
+                buf,
+            )?,
+        };
+
+        let mut skipped_fields = Vec::new();
+        validate_items(&file.items, &config, "", &mut skipped_fields);
+        Ok(Summary {
+            messages: count_messages(&file.items, &config, ""),
+            methods,
+            skipped_fields,
+            up_to_date: false,
+        })
+    }
+
+    // Error if there are messages which `generate_from_items` would not find,
+    // e.g., inside a function or `impl` block, or which it can't wrap because
+    // they are private to their module. `depth` is the depth of `items` in
+    // the input.
+    fn check_unprocessed(&self, items: &[Item], depth: usize) -> Result<(), io::Error> {
+        for item in items {
+            match item {
+                Item::Struct(item)
+                    if is_message(&item.attrs, &self.config) && !is_visible(&item.vis, depth) =>
+                {
+                    let start = item.ident.span().start();
+                    return Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!(
+                            "{}:{}:{}: message `{}` is not visible outside its module and won't be wrapped",
+                            self.input_file.display(),
+                            start.line,
+                            start.column + 1,
+                            item.ident,
+                        ),
+                    ));
+                }
+                Item::Struct(_) | Item::Enum(_) => {}
+                Item::Mod(m) => {
+                    if let Some(ref content) = m.content {
+                        self.check_unprocessed(&content.1, depth + 1)?;
+                    }
+                }
+                item => {
+                    let mut finder = MessageFinder {
+                        config: &self.config,
+                        messages: Vec::new(),
+                    };
+                    finder.visit_item(item);
+                    if let Some(message) = finder.messages.first() {
+                        let start = message.span().start();
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!(
+                                "{}:{}:{}: message `{}` is not in a module and won't be wrapped",
+                                self.input_file.display(),
+                                start.line,
+                                start.column + 1,
+                                message,
+                            ),
                         ));
-                        ".as_ref()"
                     }
-                    RefType::Copy => "",
-                };
+                }
+            }
+        }
+        Ok(())
+    }
 
-                let init_val = match &**fk {
-                    FieldKind::Message => {
-                        result.take = Some(format!(
-                            "self.{}.take().unwrap_or_else({}::default)",
-                            result.name,
-                            type_in_expr_context(&unwrapped_type),
+    // Error if an enum field of a wrapped message refers to an enum which isn't
+    // a Prost enumeration in the input, and so may not have `from_i32`, unless
+    // it is mapped by `WrapperConfig::extern_enum`. `root` is the whole input.
+    fn check_enums(
+        &self,
+        items: &[Item],
+        root: &[Item],
+        config: &WrapperConfig,
+        prefix: &str,
+    ) -> Result<(), io::Error> {
+        for item in items {
+            match item {
+                Item::Struct(item) if is_wrapped(item, config, prefix) => {
+                    for f in &item.fields {
+                        let attrs = FieldAttrs::from_attrs(&f.attrs, prefix);
+                        let (name, enumeration) = match (&f.ident, &attrs.enumeration) {
+                            (Some(name), Some(enumeration)) => (name, enumeration),
+                            _ => continue,
+                        };
+                        if config.extern_enum_path(enumeration).is_some() {
+                            continue;
+                        }
+                        let reason = match find_enum(root, &qualify_path(enumeration, prefix)) {
+                            Some(e) if is_enum(&e.attrs) => continue,
+                            Some(_) => "is not a Prost enumeration",
+                            None => "is not in the input",
+                        };
+                        let start = name.span().start();
+                        return Err(io::Error::new(
+                            io::ErrorKind::InvalidData,
+                            format!(
+                                "{}:{}:{}: enum `{}` of field `{}` {} (see `WrapperConfig::extern_enum`)",
+                                self.input_file.display(),
+                                start.line,
+                                start.column + 1,
+                                enumeration,
+                                name,
+                                reason,
+                            ),
                         ));
-                        format!("{}::default_ref()", type_in_expr_context(&unboxed_type))
                     }
-                    FieldKind::Bytes => {
-                        result.take = Some(format!(
-                            "self.{}.take().unwrap_or_else(::std::vec::Vec::new)",
-                            result.name,
-                        ));
-                        "&[]".to_owned()
+                }
+                Item::Mod(m) => {
+                    if let Some(ref content) = m.content {
+                        let prefix = format!("{}{}::", prefix, m.ident);
+                        self.check_enums(&content.1, root, config, &prefix)?;
                     }
-                    FieldKind::String => {
-                        result.take = Some(format!(
-                            "self.{}.take().unwrap_or_else(::std::string::String::new)",
-                            result.name,
-                        ));
-                        "\"\"".to_owned()
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    // The protobuf package of the input, which Prost names its output after.
+    // Prost names the output for files without a package `_.rs`.
+    fn package(&self) -> Option<&str> {
+        match self.input_file.file_stem().and_then(|s| s.to_str()) {
+            Some("_") => Some(""),
+            stem => stem,
+        }
+    }
+
+    // Configuration for generating the input, with information taken from the
+    // input and descriptors.
+    fn input_config(&self, items: &[Item]) -> InputConfig {
+        let mut config = InputConfig::new(self.config.clone());
+        // Marked messages don't have constructors for other messages to use.
+        skip_marked_constructors(items, "", &mut config);
+        for descriptor in &self.descriptors {
+            apply_descriptor(items, descriptor, &mut config);
+        }
+        // Without descriptors, the names are guessed from Prost's output.
+        if self.descriptors.is_empty() {
+            let package = match self.package() {
+                Some("") | None => String::new(),
+                Some(package) => format!("{}.", package),
+            };
+            collect_full_names(items, "", &package, &mut config);
+        }
+        config
+    }
+
+    fn parse(&self) -> Result<Cow<'_, syn::File>, io::Error> {
+        let input = match &self.input {
+            Input::Source(input) => input,
+            Input::Ast(file) => return Ok(Cow::Borrowed(file)),
+        };
+        ::syn::parse_file(input).map(Cow::Owned).map_err(|e| {
+            let start = e.span().start();
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{}:{}:{}: could not parse Prost output: {}",
+                    self.input_file.display(),
+                    start.line,
+                    start.column + 1,
+                    e
+                ),
+            )
+        })
+    }
+}
+
+// The user's configuration with what the generator learns about the messages
+// in its input, which `WrapperConfig` doesn't record.
+#[derive(Clone)]
+struct InputConfig {
+    config: WrapperConfig,
+    // As for `WrapperConfig::skip_constructors`, see `skip_marked_constructors`.
+    skipped_constructors: HashSet<String>,
+    // From the descriptors, see `apply_descriptor`.
+    field_docs: HashMap<String, HashMap<String, String>>,
+    deprecated_fields: HashMap<String, HashSet<String>>,
+    // See `collect_full_names`.
+    full_names: HashMap<String, String>,
+}
+
+impl InputConfig {
+    fn new(config: WrapperConfig) -> InputConfig {
+        InputConfig {
+            config,
+            skipped_constructors: HashSet::new(),
+            field_docs: HashMap::new(),
+            deprecated_fields: HashMap::new(),
+            full_names: HashMap::new(),
+        }
+    }
+
+    fn has_constructors(&self, message: &str) -> bool {
+        self.config.has_constructors(message) && !self.skipped_constructors.contains(message)
+    }
+
+    // A comment in a descriptor takes precedence over `WrapperConfig::field_doc`.
+    fn field_doc_for(&self, message: &str, field: &str) -> Option<&str> {
+        self.field_docs
+            .get(message)
+            .and_then(|fields| fields.get(field))
+            .map(|s| &**s)
+            .or_else(|| self.config.field_doc_for(message, field))
+    }
+
+    fn is_deprecated_field(&self, message: &str, field: &str) -> bool {
+        self.config.is_deprecated_field(message, field)
+            || self
+                .deprecated_fields
+                .get(message)
+                .is_some_and(|fields| fields.contains(field))
+    }
+
+    fn full_name_for(&self, message: &str) -> Option<&str> {
+        self.full_names.get(message).map(|s| &**s)
+    }
+}
+
+impl Deref for InputConfig {
+    type Target = WrapperConfig;
+
+    fn deref(&self) -> &WrapperConfig {
+        &self.config
+    }
+}
+
+impl DerefMut for InputConfig {
+    fn deref_mut(&mut self) -> &mut WrapperConfig {
+        &mut self.config
+    }
+}
+
+// Which messages and enums are generated into one of the files a wrapper is
+// split into: every `count`th one, starting with the `index`th.
+#[derive(Clone, Copy)]
+struct Split {
+    index: usize,
+    count: usize,
+    seen: usize,
+}
+
+impl Split {
+    const WHOLE: Split = Split::new(0, 1);
+
+    const fn new(index: usize, count: usize) -> Split {
+        Split {
+            index,
+            count,
+            seen: 0,
+        }
+    }
+
+    // Whether the next message or enum is in this file.
+    fn includes_next(&mut self) -> bool {
+        let included = self.seen % self.count == self.index;
+        self.seen += 1;
+        included
+    }
+}
+
+// Collects the names of all messages in a syntax tree.
+struct MessageFinder<'a> {
+    config: &'a WrapperConfig,
+    messages: Vec<Ident>,
+}
+
+impl<'ast> Visit<'ast> for MessageFinder<'_> {
+    fn visit_item_struct(&mut self, item: &'ast ItemStruct) {
+        if is_message(&item.attrs, self.config) {
+            self.messages.push(item.ident.clone());
+        }
+        visit::visit_item_struct(self, item);
+    }
+
+    // Macros can't be expanded, but the tokens of an invocation are often
+    // items, e.g., `foo! { struct Bar { ... } }`.
+    fn visit_macro(&mut self, mac: &'ast syn::Macro) {
+        if let Ok(file) = syn::parse2::<syn::File>(mac.tokens.clone()) {
+            self.visit_file(&file);
+        }
+    }
+}
+
+// The structs and enums in the input (in modules), and how many of them are
+// Prost messages and enums.
+#[derive(Default)]
+struct TypeCounts {
+    all: usize,
+    prost: usize,
+}
+
+impl TypeCounts {
+    fn count(&mut self, items: &[Item], config: &WrapperConfig) {
+        for item in items {
+            match item {
+                Item::Struct(item) => {
+                    self.all += 1;
+                    self.prost += is_message(&item.attrs, config) as usize;
+                }
+                Item::Enum(item) => {
+                    self.all += 1;
+                    self.prost += is_enum(&item.attrs) as usize;
+                }
+                Item::Mod(m) => {
+                    if let Some(ref content) = m.content {
+                        self.count(&content.1, config);
                     }
-                    FieldKind::Int | FieldKind::Enumeration(_) => "0".to_owned(),
-                    FieldKind::Float => "0.".to_owned(),
-                    FieldKind::Bool => "false".to_owned(),
-                    _ => unimplemented!(),
-                };
+                }
+                _ => {}
+            }
+        }
+    }
+}
 
-                result.get = Some(match &**fk {
-                    FieldKind::Enumeration(t) => format!(
-                        "match self.{} {{
-                            Some(v) => match {}::from_i32(v) {{\
-                                Some(e) => e,
-                                None => panic!(\"Unknown enum variant: {{}}\", v),
-                            }},
-                            None => {}::default(),
-                        }}",
-                        result.name,
-                        type_in_expr_context(t),
-                        t,
-                    ),
-                    _ => format!(
-                        "match self.{}{} {{
-                            Some(v) => v,
-                            None => {},
-                        }}",
-                        result.name, as_ref, init_val,
-                    ),
+/// What was generated for a file, returned by `WrapperGen::write`.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct Summary {
+    /// The number of messages which were wrapped.
+    pub messages: usize,
+    /// The number of methods generated for those messages, including any
+    /// added with `WrapperConfig::field_methods`.
+    pub methods: usize,
+    /// Fields which didn't get accessors, as for `WrapperGen::validate`.
+    pub skipped_fields: Vec<Warning>,
+    /// Whether the file wasn't written because it was already up to date,
+    /// see `WrapperConfig::incremental`. If so, nothing else is recorded.
+    pub up_to_date: bool,
+}
+
+// The number of messages which `generate_from_items` wraps.
+fn count_messages(items: &[Item], config: &WrapperConfig, prefix: &str) -> usize {
+    items
+        .iter()
+        .map(|item| match item {
+            Item::Struct(item) if is_wrapped(item, config, prefix) => 1,
+            Item::Mod(m) => m.content.as_ref().map_or(0, |content| {
+                count_messages(&content.1, config, &format!("{}{}::", prefix, m.ident))
+            }),
+            _ => 0,
+        })
+        .sum()
+}
+
+// The names of the messages which `generate_from_items` wraps.
+fn collect_message_names(
+    items: &[Item],
+    config: &WrapperConfig,
+    prefix: &str,
+    names: &mut Vec<String>,
+) {
+    for item in items {
+        match item {
+            Item::Struct(item) if is_wrapped(item, config, prefix) => {
+                names.push(config_path(&format!("{}{}", prefix, item.ident)))
+            }
+            Item::Mod(m) => {
+                if let Some(ref content) = m.content {
+                    let prefix = format!("{}{}::", prefix, m.ident);
+                    collect_message_names(&content.1, config, &prefix, names);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// A problem with a field, see `WrapperGen::validate` and
+/// `WrapperGen::check_hashable`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Warning {
+    /// The path to the message's type relative to the generated module.
+    pub message: String,
+    /// The name of the field.
+    pub field: String,
+    /// A description of the problem.
+    pub reason: String,
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}: {}", self.message, self.field, self.reason)
+    }
+}
+
+fn validate_items(
+    items: &[Item],
+    config: &WrapperConfig,
+    prefix: &str,
+    warnings: &mut Vec<Warning>,
+) {
+    for item in items {
+        if let Item::Struct(item) = item {
+            if !is_message(&item.attrs, config)
+                || is_marked(item, config)
+                || !config.wraps_module(&config_path(prefix))
+            {
+                continue;
+            }
+            for (i, f) in item.fields.iter().enumerate() {
+                let (name, reason) = match (&f.ident, FieldKind::try_from_attrs(&f.attrs, prefix)) {
+                    (None, _) => (i.to_string(), "fields of tuple structs are not supported"),
+                    (Some(name), None) if !is_prost_field(f) => {
+                        (name.to_string(), "not a Prost field")
+                    }
+                    (Some(name), None) => (name.to_string(), "unsupported field type"),
+                    (Some(_), Some(_)) => continue,
+                };
+                warnings.push(Warning {
+                    message: format!("{}{}", prefix, item.ident),
+                    field: name,
+                    reason: reason.to_owned(),
                 });
             }
-            FieldKind::Message => {
-                let unboxed_type = unwrap_type(ty, "Box");
-                if ty != &unboxed_type {
-                    result.ref_ty = RefType::Deref(unboxed_type.into_token_stream().to_string());
+        } else if let Item::Mod(m) = item {
+            if let Some(ref content) = m.content {
+                let prefix = format!("{}{}::", prefix, m.ident);
+                validate_items(&content.1, config, &prefix, warnings);
+            }
+        }
+    }
+}
+
+fn unhashable_items(
+    items: &[Item],
+    config: &WrapperConfig,
+    prefix: &str,
+    warnings: &mut Vec<Warning>,
+) {
+    for item in items {
+        if let Item::Struct(item) = item {
+            if !is_message(&item.attrs, config) {
+                continue;
+            }
+            for f in &item.fields {
+                let is_float = match FieldKind::try_from_attrs(&f.attrs, prefix) {
+                    Some(FieldKind::Float) => true,
+                    Some(FieldKind::Optional(kind)) => *kind == FieldKind::Float,
+                    Some(FieldKind::Repeated {
+                        element: Some(kind),
+                        ..
+                    }) => *kind == FieldKind::Float,
+                    _ => false,
+                };
+                if let (true, Some(name)) = (is_float, &f.ident) {
+                    warnings.push(Warning {
+                        message: format!("{}{}", prefix, item.ident),
+                        field: name.to_string(),
+                        reason: "floats don't implement `Hash` or `Eq`".to_owned(),
+                    });
                 }
             }
-            FieldKind::Int => {
-                result.ref_ty = RefType::Copy;
-                result.clear = Some("0".to_owned());
+        } else if let Item::Mod(m) = item {
+            if let Some(ref content) = m.content {
+                let prefix = format!("{}{}::", prefix, m.ident);
+                unhashable_items(&content.1, config, &prefix, warnings);
+            }
+        }
+    }
+}
+
+// Replacements for `std` paths in generated code when generating for `no_std`.
+const NO_STD_PATHS: [(&str, &str); 13] = [
+    ("::std::any::", "::core::any::"),
+    ("::std::borrow::", "::alloc::borrow::"),
+    ("::std::convert::", "::core::convert::"),
+    ("::std::default::", "::core::default::"),
+    ("::std::fmt::", "::core::fmt::"),
+    ("::std::format!", "::alloc::format!"),
+    ("::std::iter::", "::core::iter::"),
+    ("::std::mem::", "::core::mem::"),
+    ("::std::ops::", "::core::ops::"),
+    ("::std::option::", "::core::option::"),
+    ("::std::result::", "::core::result::"),
+    ("::std::string::", "::alloc::string::"),
+    ("::std::vec::", "::alloc::vec::"),
+];
+
+// The crates which generated code uses, see `WrapperConfig::prelude`.
+fn prelude_crates(config: &WrapperConfig) -> [&'static str; 2] {
+    if config.is_no_std() {
+        ["core", "alloc"]
+    } else {
+        ["std", "core"]
+    }
+}
+
+// Rewrites paths in generated code for `no_std` and `WrapperConfig::prelude`.
+fn rewrite_paths(config: &WrapperConfig, mut code: String) -> String {
+    if config.is_no_std() {
+        for (std_path, no_std_path) in NO_STD_PATHS {
+            code = code.replace(std_path, no_std_path);
+        }
+    }
+    if config.has_prelude() {
+        for krate in prelude_crates(config) {
+            code = alias_crate(&code, krate);
+        }
+    }
+    code
+}
+
+// Imports the aliases for crates used by generated code.
+fn write_prelude<W>(config: &WrapperConfig, buf: &mut W) -> Result<(), io::Error>
+where
+    W: Write,
+{
+    if config.has_prelude() {
+        for krate in prelude_crates(config) {
+            writeln!(buf, "#[allow(unused_imports)]\nuse ::{0} as __{0};", krate)?;
+        }
+    }
+    Ok(())
+}
+
+// Replaces absolute paths into `krate` with paths through its alias, e.g.,
+// `::std::mem::take` with `__std::mem::take`, but not `::prost::alloc::`.
+// Types copied from the input are formatted as tokens, e.g., `:: std :: vec`.
+fn alias_crate(code: &str, krate: &str) -> String {
+    let mut code = code.to_owned();
+    for (path, alias) in [
+        (format!("::{}::", krate), format!("__{}::", krate)),
+        (format!(":: {} ::", krate), format!("__{} ::", krate)),
+    ] {
+        let mut result = String::new();
+        let mut rest = &*code;
+        while let Some(i) = rest.find(&path) {
+            result.push_str(&rest[..i]);
+            let prefixed = rest[..i]
+                .trim_end()
+                .ends_with(|c: char| c.is_alphanumeric() || c == '_' || c == ':');
+            result.push_str(if prefixed { &path } else { &alias });
+            rest = &rest[i + path.len()..];
+        }
+        result.push_str(rest);
+        code = result;
+    }
+    code
+}
+
+// Writes text to `inner` with the line endings set by
+// `WrapperConfig::crlf_line_endings`, whatever the line endings written, and
+// without a byte order mark.
+struct LineEndings<W> {
+    inner: W,
+    crlf: bool,
+    start: bool,
+}
+
+impl<W> LineEndings<W> {
+    fn new(inner: W, config: &WrapperConfig) -> LineEndings<W> {
+        LineEndings {
+            inner,
+            crlf: config.has_crlf_line_endings(),
+            start: true,
+        }
+    }
+}
+
+impl<W: Write> Write for LineEndings<W> {
+    fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+        let mut text = data;
+        if std::mem::take(&mut self.start) {
+            text = text.strip_prefix("\u{feff}".as_bytes()).unwrap_or(text);
+        }
+        let mut converted = Vec::with_capacity(text.len());
+        for &b in text {
+            match b {
+                b'\r' => {}
+                b'\n' if self.crlf => converted.extend_from_slice(b"\r\n"),
+                b => converted.push(b),
+            }
+        }
+        self.inner.write_all(&converted)?;
+        Ok(data.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+// `text` with the line endings set by `WrapperConfig::crlf_line_endings`.
+fn with_line_endings(config: &WrapperConfig, text: &str) -> String {
+    let mut out = LineEndings::new(Vec::new(), config);
+    out.write_all(text.as_bytes()).unwrap();
+    String::from_utf8(out.inner).expect("Generated code not utf8")
+}
+
+// Formats generated code. Comments are lost, except for the header.
+fn pretty_print(code: &str) -> Result<String, syn::Error> {
+    let file = syn::parse_file(code)?;
+    Ok(format!("{}\n\n{}", HEADER, prettyplease::unparse(&file)))
+}
+
+// Describes the first difference between `expected` and the file at `path`.
+fn diff_file(path: &Path, expected: &str) -> Result<Option<String>, io::Error> {
+    let actual = match fs::read_to_string(path) {
+        Ok(s) => s,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return Ok(Some(format!("{}: file does not exist", path.display())));
+        }
+        Err(e) => return Err(e),
+    };
+    Ok(first_difference(expected, &actual).map(|diff| format!("{}:{}", path.display(), diff)))
+}
+
+// Describes the first line which differs between `expected` and `actual`, if any.
+fn first_difference(expected: &str, actual: &str) -> Option<String> {
+    let mut expected_lines = expected.lines();
+    let mut actual_lines = actual.lines();
+    let mut line = 1;
+    loop {
+        match (expected_lines.next(), actual_lines.next()) {
+            (None, None) => return None,
+            (e, a) if e != a => {
+                return Some(format!(
+                    "{}: expected `{}`, found `{}`",
+                    line,
+                    e.unwrap_or("<end of file>"),
+                    a.unwrap_or("<end of file>"),
+                ));
+            }
+            _ => line += 1,
+        }
+    }
+}
+
+// The contents of the file written by `WrapperGen::write_mod_file`, and by
+// `Builder` for Prost. Relative paths in `files` are included relative to the
+// written file.
+pub(crate) fn mod_file(
+    files: &[PathBuf],
+    gen_opt: GenOpt,
+    config: &WrapperConfig,
+    out_dir: Option<&Path>,
+) -> Result<String, io::Error> {
+    // An `include!` of `path`, relative to `OUT_DIR` if possible.
+    let include = |path: &Path| match out_dir.and_then(|dir| path.strip_prefix(dir).ok()) {
+        Some(rel) => format!(
+            "include!(concat!(env!(\"OUT_DIR\"), {:?}));",
+            format!("/{}", rel.display())
+        ),
+        None => format!("include!({:?});", path.display().to_string()),
+    };
+
+    // Files with the same package prefix share modules, e.g., `foo.rs` and
+    // `foo.bar.rs` are both in `foo`.
+    let mut root = ModTree::default();
+    for file in files {
+        let (stem, name) = match (
+            file.file_stem().and_then(|s| s.to_str()),
+            file.file_name().and_then(|s| s.to_str()),
+        ) {
+            (Some(stem), Some(name)) => (stem, name),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("{}: invalid file name", file.display()),
+                ))
+            }
+        };
+        let node = stem.split('.').fold(&mut root, |node, module| {
+            node.modules.entry(module.replace('-', "_")).or_default()
+        });
+        node.includes.push(include(file));
+        node.includes.push(include(
+            &file.with_file_name(config.wrapper_file_name(name)),
+        ));
+    }
+
+    let mut result = format!("{}\n\n", HEADER);
+    let message_ext = gen_opt.contains(GenOpt::MESSAGE_EXT);
+    if message_ext {
+        result.push_str(crate::MESSAGE_EXT_TRAIT);
+        result.push('\n');
+    }
+    if config.has_shared_enum_helpers() {
+        result.push_str(crate::ENUM_HELPERS);
+        result.push('\n');
+    }
+    root.write(config, message_ext, 0, &mut result);
+    Ok(result)
+}
+
+#[derive(Default)]
+struct ModTree {
+    includes: Vec<String>,
+    modules: BTreeMap<String, ModTree>,
+}
+
+impl ModTree {
+    fn write(&self, config: &WrapperConfig, message_ext: bool, depth: usize, buf: &mut String) {
+        if message_ext && !self.includes.is_empty() {
+            *buf += &format!(
+                "#[allow(unused_imports)]\nuse {}MessageExt;\n",
+                "super::".repeat(depth)
+            );
+        }
+        if config.has_shared_enum_helpers() && !self.includes.is_empty() {
+            *buf += &format!(
+                "#[allow(unused_imports)]\nuse {}__enums;\n",
+                "super::".repeat(depth)
+            );
+        }
+        for include in &self.includes {
+            buf.push_str(include);
+            buf.push('\n');
+        }
+        for (name, module) in &self.modules {
+            *buf += &format!("pub mod {} {{\n", name);
+            module.write(config, message_ext, depth + 1, buf);
+            buf.push_str("}\n");
+        }
+    }
+}
+
+// The first line of every generated file.
+const HEADER: &str = "// Generated file, please don't edit manually.";
+// Delimit the generated code in a file, see `WrapperConfig::splice_markers`.
+const BEGIN_MARKER: &str = "// BEGIN generated";
+const END_MARKER: &str = "// END generated";
+
+/// Where generated files are written.
+pub trait Sink {
+    /// Create (or truncate) the file at `path` for writing.
+    fn create(&mut self, path: &Path) -> Result<Box<dyn Write + '_>, io::Error>;
+
+    /// The contents of the file at `path`, or `None` if there is no such file.
+    ///
+    /// Used to check that a file was generated before it is overwritten, see
+    /// `WrapperConfig::overwrite_guard`. By default, there are never any files.
+    fn read(&mut self, _path: &Path) -> Result<Option<Vec<u8>>, io::Error> {
+        Ok(None)
+    }
+
+    /// When the file at `path` was last modified, or `None` if there is no
+    /// such file or it isn't known.
+    ///
+    /// Used to skip wrappers which are up to date, see
+    /// `WrapperConfig::incremental`. By default, files are never up to date.
+    fn modified(&mut self, _path: &Path) -> Result<Option<SystemTime>, io::Error> {
+        Ok(None)
+    }
+}
+
+/// Writes generated files to the file system.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FileSink;
+
+impl Sink for FileSink {
+    fn create(&mut self, path: &Path) -> Result<Box<dyn Write + '_>, io::Error> {
+        Ok(Box::new(BufWriter::new(File::create(path)?)))
+    }
+
+    fn read(&mut self, path: &Path) -> Result<Option<Vec<u8>>, io::Error> {
+        match fs::read(path) {
+            Ok(contents) => Ok(Some(contents)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn modified(&mut self, path: &Path) -> Result<Option<SystemTime>, io::Error> {
+        match fs::metadata(path) {
+            Ok(metadata) => Ok(Some(metadata.modified()?)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Collects generated files in memory, useful for testing.
+#[derive(Clone, Debug, Default)]
+pub struct MemSink {
+    pub files: HashMap<PathBuf, Vec<u8>>,
+}
+
+impl Sink for MemSink {
+    fn create(&mut self, path: &Path) -> Result<Box<dyn Write + '_>, io::Error> {
+        let buf = self.files.entry(path.to_owned()).or_default();
+        buf.clear();
+        Ok(Box::new(buf))
+    }
+
+    fn read(&mut self, path: &Path) -> Result<Option<Vec<u8>>, io::Error> {
+        Ok(self.files.get(path).cloned())
+    }
+}
+
+// Oneof enums, keyed by their path relative to the generated module.
+type Oneofs<'a> = HashMap<String, &'a ItemEnum>;
+
+fn collect_oneofs<'a>(items: &'a [Item], prefix: &str, oneofs: &mut Oneofs<'a>) {
+    for item in items {
+        if let Item::Enum(item) = item {
+            if derives_prost(&item.attrs, "Oneof") {
+                oneofs.insert(format!("{}{}", prefix, item.ident), item);
+            }
+        } else if let Item::Mod(m) = item {
+            if let Some(ref content) = m.content {
+                let prefix = format!("{}{}::", prefix, m.ident);
+                collect_oneofs(&content.1, &prefix, oneofs);
+            }
+        }
+    }
+}
+
+// Only messages and enums in (possibly nested) modules are wrapped. Other
+// items, e.g., `use`s, constants, and `impl`s, are ignored, as are messages
+// anywhere else, e.g., in a function or in a macro invocation, and messages
+// which aren't visible where their `impl` is generated. In strict mode,
+// `WrapperGen::check_unprocessed` reports messages which are skipped.
+fn generate_from_items<W>(
+    items: &[Item],
+    gen_opt: GenOpt,
+    config: &InputConfig,
+    oneofs: &Oneofs,
+    prefix: &str,
+    split: &mut Split,
+    buf: &mut W,
+) -> Result<usize, io::Error>
+where
+    W: Write,
+{
+    let mut methods = 0;
+    for item in items {
+        if let Item::Struct(item) = item {
+            if is_wrapped(item, config, prefix) && split.includes_next() {
+                methods += generate_struct(item, gen_opt, config, oneofs, prefix, prefix, buf)?;
+            }
+        } else if let Item::Enum(item) = item {
+            if is_enum(&item.attrs)
+                && config.wraps_module(&config_path(prefix))
+                && split.includes_next()
+            {
+                generate_enum(item, config, prefix, buf)?;
+            }
+        } else if let Item::Mod(m) = item {
+            if let Some(ref content) = m.content {
+                let prefix = format!("{}{}::", prefix, m.ident);
+                methods +=
+                    generate_from_items(&content.1, gen_opt, config, oneofs, &prefix, split, buf)?;
+            }
+        }
+    }
+    Ok(methods)
+}
+
+// Like `generate_from_items`, but generates a `mod` for each module in the
+// input, rather than using paths. `module` is the path to the module being
+// generated, and `depth` is its depth relative to the input.
+fn generate_nested<W>(
+    items: &[Item],
+    gen_opt: GenOpt,
+    config: &InputConfig,
+    oneofs: &Oneofs,
+    module: &str,
+    depth: usize,
+    buf: &mut W,
+) -> Result<usize, io::Error>
+where
+    W: Write,
+{
+    let mut methods = 0;
+    for item in items {
+        if let Item::Struct(item) = item {
+            if is_wrapped(item, config, module) {
+                methods += generate_struct(item, gen_opt, config, oneofs, "", module, buf)?;
+            }
+        } else if let Item::Enum(item) = item {
+            if is_enum(&item.attrs) && config.wraps_module(&config_path(module)) {
+                generate_enum(item, config, "", buf)?;
+            }
+        } else if let Item::Mod(m) = item {
+            if let Some(ref content) = m.content {
+                let module = format!("{}{}::", module, m.ident);
+                write_item_attributes(config, buf)?;
+                writeln!(buf, "pub mod {} {{", m.ident)?;
+                // Re-export the Prost types, so that paths in the wrapper code
+                // resolve the same way as in the Prost code.
+                writeln!(
+                    buf,
+                    "#[allow(unused_imports)]\npub use {}{}*;",
+                    "super::".repeat(depth + 1),
+                    module,
+                )?;
+                if gen_opt.contains(GenOpt::MESSAGE_EXT) {
+                    writeln!(buf, "#[allow(unused_imports)]\nuse super::MessageExt;")?;
+                }
+                if config.has_shared_enum_helpers() {
+                    writeln!(buf, "#[allow(unused_imports)]\nuse super::__enums;")?;
+                }
+                write_prelude(config, buf)?;
+                methods +=
+                    generate_nested(&content.1, gen_opt, config, oneofs, &module, depth + 1, buf)?;
+                writeln!(buf, "}}")?;
+            }
+        }
+    }
+    Ok(methods)
+}
+
+fn generate_struct<W>(
+    item: &ItemStruct,
+    gen_opt: GenOpt,
+    config: &InputConfig,
+    oneofs: &Oneofs,
+    prefix: &str,
+    module: &str,
+    buf: &mut W,
+) -> Result<usize, io::Error>
+where
+    W: Write,
+{
+    if let syn::Fields::Unnamed(_) = item.fields {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Message `{}{}` is a tuple struct, accessors can only be generated for named fields",
+                prefix, item.ident
+            ),
+        ));
+    }
+    let message = config_path(&format!("{}{}", module, item.ident));
+    config.log_event(Event::Message(&message));
+    let constructors = config.has_constructors(&message);
+    let mut tests = Vec::new();
+    // Accessors which are declared in a trait, rather than the inherent `impl`.
+    let mut trait_accessors = String::new();
+    let mut oneof_fields = Vec::new();
+    // The code for each field, and the names of the accessors named after
+    // a field, see `is_accessor_of`.
+    let mut fields = Vec::new();
+    let mut primaries = HashSet::new();
+    let method_prefix = config.accessor_prefix().unwrap_or("");
+    // The tests use all of these functions.
+    let tests_opt =
+        GenOpt::TESTS | GenOpt::TRIVIAL_GET | GenOpt::TRIVIAL_SET | GenOpt::HAS | GenOpt::CLEAR;
+    write_impl_attributes(config, buf)?;
+    writeln!(buf, "impl {}{} {{", prefix, item.ident)?;
+    let skip = |method: &str| config.skips_method(&message, method);
+    // Generated items are `pub`, unless the message isn't. Their exact
+    // visibility (e.g., `pub(super)`) would mean something else in the
+    // generated code, so they are `pub(crate)`.
+    let public = matches!(item.vis, syn::Visibility::Public(_));
+    let vis = if public { "pub" } else { "pub(crate)" };
+    // How accessors are declared, followed by their names. Those in an
+    // accessor trait are declared `pub` here, see `generate_accessor_trait`.
+    let decl = if config.has_accessor_traits() {
+        format!("pub fn {}", method_prefix)
+    } else {
+        format!("{} fn {}", vis, method_prefix)
+    };
+    // The number of methods written to `buf`, counted as they are emitted.
+    let mut count = 0;
+    let mut emit = |code: Vec<u8>| -> String {
+        let code = String::from_utf8(code).expect("Generated code not utf8");
+        let code = filter_methods(&code, skip);
+        count += method_names(&code).count();
+        code
+    };
+    if constructors {
+        let mut methods = Vec::new();
+        let constructor = config.constructor_for(&message);
+        if gen_opt.contains(GenOpt::NEW) && !skip(&constructor) {
+            generate_new(&item.ident, prefix, &constructor, vis, &mut methods)?;
+        }
+        if !skip("default_ref") {
+            generate_default_ref(&item.ident, prefix, gen_opt, vis, &mut methods)?;
+        }
+        buf.write_all(emit(methods).as_bytes())?;
+    }
+    let skip_field = |field: &Ident, reason: &str| {
+        config.log_event(Event::SkippedField {
+            message: &message,
+            field: field.to_string().trim_start_matches("r#"),
+            reason,
+        })
+    };
+    let unsupported = |name: &Ident| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Field `{}` of message `{}{}` has an unsupported type",
+                name, prefix, item.ident
+            ),
+        )
+    };
+    for f in &item.fields {
+        let name = match &f.ident {
+            // Fields added by hand aren't encoded, so don't get accessors.
+            Some(name) if !is_prost_field(f) => {
+                skip_field(name, "not a Prost field");
+                continue;
+            }
+            Some(name) => name,
+            None => continue,
+        };
+        let attrs = FieldAttrs::from_attrs(&f.attrs, prefix);
+        if let Some(path) = attrs
+            .enumeration
+            .as_deref()
+            .and_then(|e| config.extern_enum_path(e))
+        {
+            parse_enum_type(path, &message, &name.to_string())?;
+        }
+        let kind = match attrs.resolved_kind(config) {
+            Some(kind) => kind,
+            None if config.skips_unknown_fields() => {
+                skip_field(name, "unsupported field type");
+                writeln!(buf, "// Skipped field `{}`: unsupported field type.", name)?;
+                continue;
+            }
+            None => return Err(unsupported(name)),
+        };
+        if kind == FieldKind::Ignored && config.skips_ignored_fields() {
+            skip_field(name, "ignored field");
+            continue;
+        }
+        let field = name.to_string();
+        let field = field.trim_start_matches("r#");
+        let description = kind.description();
+        let info = FieldInfo {
+            message: &message,
+            field,
+            kind: &description,
+            tag: attrs.tag,
+            packed: attrs.is_packed(),
+        };
+        config.log_event(Event::Field(info.clone()));
+        // The base of the names of the field's accessors, e.g., `foo` in `get_foo`.
+        let base = config.renamed_field(&message, field).unwrap_or(field);
+        // A qualified type is relative to the generated code, not `module`.
+        let (ty, ty_module) = match qualify_message_field(&f.ty, &kind, prefix) {
+            Some(ty) => (ty, module.strip_prefix(prefix).unwrap_or(module)),
+            None => (f.ty.clone(), module),
+        };
+        let methods = kind
+            .methods(&ty, name, attrs.default.as_deref(), config, ty_module)
+            .map_err(|_| unsupported(name))?;
+        if gen_opt.contains(tests_opt) {
+            let into_ty = methods
+                .as_ref()
+                .filter(|m| m.into_set || m.as_ref_set)
+                .map(|m| m.override_ty.as_deref().unwrap_or(&m.ty));
+            tests.extend(kind.accessor_test(base, config.accessor_prefix().unwrap_or(""), into_ty));
+        }
+        let mut accessors = Vec::new();
+        if let Some(mut methods) = methods {
+            methods.unesc_base = base.to_owned();
+            methods.write_methods(&mut accessors, gen_opt, &decl)?;
+        }
+        if let FieldKind::OneOf(path) = &kind {
+            if gen_opt.contains(GenOpt::TRIVIAL_GET) {
+                // The active case, if any, for exhaustive matching.
+                writeln!(
+                    accessors,
+                    "#[inline] {decl}which_{}(&self) -> ::std::option::Option<&{}> {{ self.{}.as_ref() }}",
+                    base, path, name,
+                )?;
+            }
+            if gen_opt.contains(GenOpt::CLEAR) {
+                writeln!(
+                    accessors,
+                    "#[inline] {decl}clear_{}(&mut self) {{ self.{} = ::std::option::Option::None }}",
+                    base, name,
+                )?;
+            }
+            oneof_fields.push(name);
+            let key = format!("{}{}", module, &path[prefix.len()..]);
+            if let Some(oneof) = oneofs.get(&key) {
+                let oneof_module = match key.rfind("::") {
+                    Some(i) => &key[..i + 2],
+                    None => "",
+                };
+                generate_oneof_methods(
+                    oneof,
+                    path,
+                    &message,
+                    name,
+                    gen_opt,
+                    config,
+                    oneof_module,
+                    &decl,
+                    &mut accessors,
+                )?;
+            }
+        }
+        let mut attributes = String::new();
+        if let Some(doc) = config.field_doc_for(&message, field) {
+            attributes += &format!("#[doc = {:?}]\n", doc);
+        }
+        if config.is_deprecated_field(&message, field) {
+            attributes += "#[deprecated]\n";
+        }
+        if !attributes.is_empty() {
+            let code = String::from_utf8(accessors).expect("Generated code not utf8");
+            accessors = annotate_methods(&code, &attributes).into_bytes();
+        }
+        let accessors = String::from_utf8(accessors).expect("Generated code not utf8");
+        primaries.extend(
+            method_names(&accessors)
+                .filter(|m| is_accessor_of(m, base, method_prefix))
+                .map(str::to_owned),
+        );
+        // Written once all the fields' accessors are known, see below.
+        let mut rest = Vec::new();
+        let mut custom_count = 0;
+        if let Some(custom) = config.custom_field_methods(&info) {
+            custom_count = count_custom_methods(&custom);
+            writeln!(rest, "{}", custom)?;
+        }
+        // Not accessors, so never in an accessor trait.
+        if config.has_serde_helpers() {
+            let mut helper = Vec::new();
+            writeln!(
+                helper,
+                // Serde passes the field by reference, whatever its type.
+                "#[allow(clippy::trivially_copy_pass_by_ref, clippy::ref_option)]\n#[inline] {vis} fn is_default_{0}(v: &{1}) -> bool {{ *v == <{1} as ::std::default::Default>::default() }}",
+                base,
+                ty.to_token_stream(),
+            )?;
+            rest.write_all(emit(helper).as_bytes())?;
+        }
+        fields.push((base.to_owned(), accessors, rest, custom_count));
+    }
+    // A helper, e.g., `get_foo_at` for `foo`, is dropped if it has the same
+    // name as an accessor of another field, e.g., `get_foo_at` for `foo_at`.
+    // Methods which aren't counted by `emit`, in an accessor trait or from
+    // `WrapperConfig::field_methods`.
+    let mut other_count = 0;
+    for (base, accessors, rest, custom_count) in fields {
+        let clashes = |method: &str| {
+            primaries.contains(method) && !is_accessor_of(method, &base, method_prefix)
+        };
+        let accessors = filter_methods(&accessors, clashes);
+        if config.has_accessor_traits() {
+            let accessors = filter_methods(&accessors, skip);
+            other_count += method_names(&accessors).count();
+            trait_accessors.push_str(&accessors);
+        } else {
+            buf.write_all(emit(accessors.into_bytes()).as_bytes())?;
+        }
+        buf.write_all(&rest)?;
+        other_count += custom_count;
+    }
+    let mut methods = Vec::new();
+    if oneof_fields.len() > 1 && gen_opt.contains(GenOpt::CLEAR) {
+        writeln!(methods, "#[inline] {vis} fn clear_all_oneofs(&mut self) {{")?;
+        for name in oneof_fields {
+            writeln!(methods, "    self.{} = ::std::option::Option::None;", name)?;
+        }
+        writeln!(methods, "    }}")?;
+    }
+    if gen_opt.contains(GenOpt::COPY_FROM) {
+        generate_copy_from(item, vis, &mut methods)?;
+    }
+    if gen_opt.contains(GenOpt::MERGE_FROM) {
+        generate_merge_from(item, prefix, vis, &mut methods)?;
+    }
+    if gen_opt.contains(GenOpt::FIELDS) {
+        generate_fields_const(item, prefix, vis, &mut methods)?;
+    }
+    if gen_opt.contains(GenOpt::FIELD_MASK) {
+        generate_field_mask(item, vis, &mut methods)?;
+    }
+    if gen_opt.contains(GenOpt::ENCODE_DECODE) {
+        generate_encode_decode(vis, &mut methods)?;
+    }
+    if gen_opt.contains(GenOpt::IS_DEFAULT) {
+        generate_is_default(item, prefix, vis, &mut methods)?;
+    }
+    if let Some(full_name) = config.full_name_for(&message) {
+        if gen_opt.contains(GenOpt::FULL_NAME) {
+            generate_full_name(full_name, vis, &mut methods)?;
+        }
+    }
+    if gen_opt.contains(GenOpt::VISIT_MUT) {
+        let string = ("::std::string::String", &["String"][..]);
+        generate_for_each_mut(item, prefix, "string", string, vis, &mut methods)?;
+        let bytes = ("::std::vec::Vec<u8>", &["Vec", "u8"][..]);
+        generate_for_each_mut(item, prefix, "bytes", bytes, vis, &mut methods)?;
+    }
+    if let Some(sensitive) = config.sensitive_fields(&message) {
+        if !skip("redacted_debug") {
+            generate_redacted_debug(item, prefix, sensitive, vis, &mut methods)?;
+        }
+    }
+    let methods = String::from_utf8(methods).expect("Generated code not utf8");
+    let methods = filter_methods(&methods, |m| primaries.contains(m));
+    buf.write_all(emit(methods.into_bytes()).as_bytes())?;
+    writeln!(buf, "}}")?;
+    if !trait_accessors.is_empty() {
+        generate_accessor_trait(item, config, prefix, public, &trait_accessors, buf)?;
+    }
+    if gen_opt.contains(GenOpt::MESSAGE) {
+        generate_message_trait(&item.ident, config, prefix, buf)?;
+    }
+    if gen_opt.contains(GenOpt::MESSAGE_EXT) {
+        generate_message_ext(&item.ident, config, prefix, constructors, buf)?;
+    }
+    if let Some(field) = config.display_field_for(&message) {
+        generate_display(item, config, prefix, field, buf)?;
+    }
+    if let Some(field) = config.as_ref_field_for(&message) {
+        generate_as_ref(item, config, prefix, module, field, buf)?;
+    }
+    if config.has_bytes_conversions() {
+        generate_bytes_conversions(item, config, prefix, buf)?;
+    }
+    if gen_opt.contains(GenOpt::FIELD_NAMES) {
+        generate_field_names(item, config, prefix, public, buf)?;
+    }
+    if !tests.is_empty() {
+        generate_tests(&item.ident, config, prefix, &tests, buf)?;
+    }
+    Ok(count + other_count)
+}
+
+// Declares `accessors` in a trait and implements it for the message, see
+// `WrapperConfig::accessor_traits`. Each accessor is declared with an
+// unindented `#[inline] pub fn`, and its signature ends at its first brace.
+fn generate_accessor_trait<W>(
+    item: &ItemStruct,
+    config: &WrapperConfig,
+    prefix: &str,
+    public: bool,
+    accessors: &str,
+    buf: &mut W,
+) -> Result<(), io::Error>
+where
+    W: Write,
+{
+    let name = accessor_trait_name(&item.ident, prefix);
+    let vis = if public { "pub" } else { "pub(crate)" };
+    write_item_attributes(config, buf)?;
+    writeln!(buf, "{} trait {} {{", vis, name)?;
+    for line in accessors.lines() {
+        if let Some(decl) = line.strip_prefix("#[inline] pub fn ") {
+            let end = decl.find('{').expect("Accessor without a body");
+            writeln!(buf, "fn {};", decl[..end].trim_end())?;
+        } else if is_declaration_attribute(line) {
+            writeln!(buf, "{}", line)?;
+        }
+    }
+    writeln!(buf, "}}")?;
+    write_item_attributes(config, buf)?;
+    writeln!(buf, "impl {} for {}{} {{", name, prefix, item.ident)?;
+    for line in accessors.lines() {
+        match line.strip_prefix("#[inline] pub fn ") {
+            // Trait items can't have a visibility.
+            Some(rest) => writeln!(buf, "#[inline] fn {}", rest)?,
+            None if is_declaration_attribute(line) => {}
+            None => writeln!(buf, "{}", line)?,
+        }
+    }
+    writeln!(buf, "}}")
+}
+
+// Whether `line` is an attribute added by `annotate_methods`, which belongs on
+// the declaration of an accessor in a trait rather than its implementation.
+fn is_declaration_attribute(line: &str) -> bool {
+    line.starts_with("#[doc = ") || line == "#[deprecated]"
+}
+
+// E.g., `FooAccessors` for `Foo`, and `FooBarAccessors` for `foo::Bar`.
+fn accessor_trait_name(name: &Ident, prefix: &str) -> String {
+    let mut result = String::new();
+    for module in prefix.split("::").filter(|m| !m.is_empty()) {
+        for word in module.trim_start_matches("r#").split('_') {
+            let mut chars = word.chars();
+            if let Some(c) = chars.next() {
+                result.extend(c.to_uppercase());
+                result.push_str(chars.as_str());
+            }
+        }
+    }
+    format!("{}{}Accessors", result, name)
+}
+
+// Adds `attributes` (lines of attributes) to each method in generated code, as
+// laid out for `filter_methods`.
+fn annotate_methods(code: &str, attributes: &str) -> String {
+    let mut result = String::new();
+    for line in code.lines() {
+        if method_name(line).is_some() {
+            result.push_str(attributes);
+        }
+        result.push_str(line);
+        result.push('\n');
+    }
+    result
+}
+
+// The prefixes of accessors named after a field, e.g., `get_foo` for `foo`.
+const PRIMARY_PREFIXES: [&str; 6] = ["get_", "set_", "has_", "clear_", "mut_", "take_"];
+
+// Whether `method` is named after the field whose accessors are named after
+// `base`, rather than being a helper such as `get_foo_at`.
+fn is_accessor_of(method: &str, base: &str, method_prefix: &str) -> bool {
+    PRIMARY_PREFIXES
+        .iter()
+        .any(|p| method == format!("{}{}{}", method_prefix, p, base))
+}
+
+// The names of the methods in generated code, laid out as for
+// `filter_methods`.
+fn method_names(code: &str) -> impl Iterator<Item = &str> {
+    code.lines().filter_map(method_name)
+}
+
+// The number of methods in `code`, from `WrapperConfig::field_methods`, which
+// isn't laid out as for `filter_methods`. Code which doesn't parse as the items
+// of an `impl` counts as none.
+fn count_custom_methods(code: &str) -> usize {
+    syn::parse_str::<syn::ItemImpl>(&format!("impl Custom {{ {} }}", code)).map_or(0, |i| {
+        i.items
+            .iter()
+            .filter(|item| matches!(item, syn::ImplItem::Method(_)))
+            .count()
+    })
+}
+
+// The name of the method declared by `line`, if any.
+fn method_name(line: &str) -> Option<&str> {
+    let line = line.trim_start_matches("#[inline] ");
+    let rest = line
+        .strip_prefix("pub fn ")
+        .or_else(|| line.strip_prefix("pub(crate) fn "))?;
+    let end = rest.find(['(', '<']).unwrap_or(rest.len());
+    Some(&rest[..end])
+}
+
+// Whether `line` starts an item in generated code, laid out as for
+// `filter_methods`, e.g., a method or a constant.
+fn is_declaration(line: &str) -> bool {
+    let line = line.trim_start_matches("#[inline] ");
+    ["pub ", "pub(", "fn ", "const "]
+        .iter()
+        .any(|p| line.starts_with(p))
+}
+
+// Removes the methods named by `skip` from generated code.
+//
+// Each generated method starts on a new, unindented line, any following
+// indented lines are the rest of the method. Unindented lines which are only
+// an attribute, e.g., `#[track_caller]`, belong to the following method. Any
+// other unindented line which doesn't declare an item, e.g., a stray closing
+// brace, belongs to the preceding method.
+fn filter_methods(code: &str, skip: impl Fn(&str) -> bool) -> String {
+    let mut result = String::new();
+    let mut keep = true;
+    let mut attributes = String::new();
+    for line in code.lines() {
+        if line.starts_with("#[") && line.ends_with(']') {
+            attributes.push_str(line);
+            attributes.push('\n');
+            continue;
+        }
+        if is_declaration(line) {
+            keep = !method_name(line).is_some_and(&skip);
+            if keep {
+                result.push_str(&attributes);
+            }
+            attributes.clear();
+        }
+        if keep {
+            result.push_str(line);
+            result.push('\n');
+        }
+    }
+    result
+}
+
+// A test module for a message, `tests` are the bodies produced by
+// `FieldKind::accessor_test`.
+fn generate_tests<W>(
+    name: &Ident,
+    config: &WrapperConfig,
+    prefix: &str,
+    tests: &[String],
+    buf: &mut W,
+) -> Result<(), io::Error>
+where
+    W: Write,
+{
+    write_item_attributes(config, buf)?;
+    writeln!(
+        buf,
+        "#[cfg(test)]
+        #[allow(non_snake_case, deprecated)]
+        mod test_{}{} {{
+            #[allow(unused_imports)]
+            use super::*;
+
+            #[test]
+            fn test_accessors() {{
+                let mut m = {}{}::default();",
+        config_path(prefix).replace("::", "_"),
+        name,
+        prefix,
+        name,
+    )?;
+    for test in tests {
+        writeln!(buf, "{}", test)?;
+        writeln!(buf, "assert_eq!(m, {}{}::default());", prefix, name)?;
+    }
+    writeln!(
+        buf,
+        "}}
+}}"
+    )
+}
+
+fn generate_enum<W>(
+    item: &ItemEnum,
+    config: &WrapperConfig,
+    prefix: &str,
+    buf: &mut W,
+) -> Result<(), io::Error>
+where
+    W: Write,
+{
+    write_impl_attributes(config, buf)?;
+    writeln!(buf, "impl {}{} {{", prefix, item.ident)?;
+    writeln!(buf, "pub fn values() -> &'static [Self] {{")?;
+    writeln!(
+        buf,
+        "static VALUES: &'static [{}{}] = &[",
+        prefix, item.ident
+    )?;
+    for v in &item.variants {
+        writeln!(buf, "{}{}::{},", prefix, item.ident, v.ident)?;
+    }
+    writeln!(buf, "];\nVALUES\n}}")?;
+    writeln!(buf, "}}")
+}
+
+// The enum `path` of `field` of `message`, an error if it isn't a valid path,
+// e.g., a typo in `WrapperConfig::extern_enum`.
+fn parse_enum_type(path: &str, message: &str, field: &str) -> Result<TypePath, io::Error> {
+    syn::parse_str(path).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Invalid enum `{}` for field `{}` of message `{}`",
+                path, field, message
+            ),
+        )
+    })
+}
+
+// Accessors for each message variant of the oneof `field` of `message`, whose
+// type is the enum `oneof`, at `path`.
+#[allow(clippy::too_many_arguments)]
+fn generate_oneof_methods<W>(
+    oneof: &ItemEnum,
+    path: &str,
+    message: &str,
+    field: &Ident,
+    gen_opt: GenOpt,
+    config: &InputConfig,
+    module: &str,
+    decl: &str,
+    buf: &mut W,
+) -> Result<(), io::Error>
+where
+    W: Write,
+{
+    // Types in the enum are relative to its module.
+    let enum_prefix = match path.rfind("::") {
+        Some(i) => &path[..i + 2],
+        None => "",
+    };
+    for variant in &oneof.variants {
+        let payload = match &variant.fields {
+            syn::Fields::Unnamed(fields) if fields.unnamed.len() == 1 => &fields.unnamed[0].ty,
+            _ => continue,
+        };
+        let kind = match FieldAttrs::from_attrs(&variant.attrs, "").resolved_kind(config) {
+            Some(kind) => kind,
+            None => continue,
+        };
+        // Prost boxes recursive messages.
+        let unboxed = unwrap_type(payload, "Box");
+        let boxed = unboxed != payload;
+        // Only messages are relative to the enum's module, other payloads are
+        // primitives or absolute paths.
+        let mut ty = match kind {
+            FieldKind::Message => qualify_type(unboxed, enum_prefix),
+            _ => unboxed.to_token_stream().to_string(),
+        };
+        // The getter's return type, the value it returns given a reference
+        // to the case's payload, `v`, and its value for other cases.
+        let (get_ty, get, default) = match &kind {
+            FieldKind::Message => {
+                let default_ref =
+                    if config.has_constructors(&config_path(&qualify_type(unboxed, module))) {
+                        format!("{}::default_ref()", type_in_expr_context(&ty))
+                    } else {
+                        static_default(&ty)
+                    };
+                (format!("&{}", ty), "v".to_owned(), default_ref)
+            }
+            FieldKind::Int => (ty.clone(), "*v".to_owned(), "0".to_owned()),
+            FieldKind::Float => (ty.clone(), "*v".to_owned(), "0.".to_owned()),
+            FieldKind::Bool => (ty.clone(), "*v".to_owned(), "false".to_owned()),
+            FieldKind::String => ("&str".to_owned(), "v".to_owned(), "\"\"".to_owned()),
+            FieldKind::Bytes if derefs_bytes(config, unboxed) => {
+                ("&[u8]".to_owned(), "v".to_owned(), "&[]".to_owned())
+            }
+            FieldKind::Bytes => (
+                format!("&{}", ty),
+                "v".to_owned(),
+                format!(
+                    "{{ const EMPTY: &{} = &{}::new(); EMPTY }}",
+                    ty,
+                    type_in_expr_context(&ty)
+                ),
+            ),
+            // Stored as an `i32`, but set and got as the enum.
+            FieldKind::Enumeration(enum_ty) => {
+                let variant_field =
+                    format!("{}.{}", field, to_snake_case(&variant.ident.to_string()));
+                let enum_ty = parse_enum_type(enum_ty, message, &variant_field)?;
+                let enum_ty = qualify_type(&Type::Path(enum_ty), enum_prefix);
+                let from_i32 = format!("{}::from_i32", type_in_expr_context(&enum_ty));
+                let get = enum_helper(
+                    config,
+                    "get",
+                    &format!("*v, {}", from_i32),
+                    format!("{}(*v).unwrap_or_default()", from_i32),
+                );
+                let default = format!("{}::default()", type_in_expr_context(&enum_ty));
+                ty = enum_ty.clone();
+                (enum_ty, get, default)
+            }
+            _ => continue,
+        };
+        // Whether the payload is returned by reference, and so has `mut_` and
+        // `take_` functions.
+        let by_ref = get_ty.starts_with('&');
+        let base = to_snake_case(&variant.ident.to_string());
+        let case = format!("{}::{}", path, variant.ident);
+
+        if gen_opt.contains(GenOpt::HAS) {
+            writeln!(
+                buf,
+                "#[inline] {decl}has_{}(&self) -> bool {{ matches!(self.{}, ::std::option::Option::Some({}(_))) }}",
+                base, field, case,
+            )?;
+        }
+        if gen_opt.contains(GenOpt::CLEAR) {
+            writeln!(
+                buf,
+                "#[inline] {decl}clear_{}(&mut self) {{ self.{} = ::std::option::Option::None }}",
+                base, field,
+            )?;
+        }
+        if gen_opt.contains(GenOpt::TRIVIAL_SET) {
+            let value = if boxed {
+                "::std::boxed::Box::new(v)".to_owned()
+            } else if let FieldKind::Enumeration(_) = kind {
+                enum_helper(config, "set", "v", "v as i32".to_owned())
+            } else {
+                "v".to_owned()
+            };
+            writeln!(
+                buf,
+                "#[inline] {decl}set_{}(&mut self, v: {}) {{ self.{} = ::std::option::Option::Some({}({})); }}",
+                base, ty, field, case, value,
+            )?;
+        }
+        if gen_opt.contains(GenOpt::TRIVIAL_GET)
+            && config.has_option_getters()
+            && kind == FieldKind::Message
+        {
+            writeln!(
+                buf,
+                "#[inline] {decl}get_{}(&self) -> ::std::option::Option<&{}> {{
+                    match &self.{} {{
+                        ::std::option::Option::Some({}(v)) => ::std::option::Option::Some({}v),
+                        _ => ::std::option::Option::None,
+                    }}
+                }}",
+                base,
+                ty,
+                field,
+                case,
+                if boxed { "&**" } else { "" },
+            )?;
+        } else if gen_opt.contains(GenOpt::TRIVIAL_GET) {
+            writeln!(
+                buf,
+                "#[inline] {decl}get_{}(&self) -> {} {{
+                    match &self.{} {{
+                        ::std::option::Option::Some({}(v)) => {},
+                        _ => {},
+                    }}
+                }}",
+                base, get_ty, field, case, get, default,
+            )?;
+        }
+        if gen_opt.contains(GenOpt::MUT) && by_ref {
+            writeln!(
+                buf,
+                "#[inline] {decl}mut_{0}(&mut self) -> &mut {1} {{
+                    if !matches!(self.{2}, ::std::option::Option::Some({3}(_))) {{
+                        self.{2} = ::std::option::Option::Some({3}(::core::default::Default::default()));
+                    }}
+                    match &mut self.{2} {{
+                        ::std::option::Option::Some({3}(v)) => v,
+                        _ => unreachable!(),
+                    }}
+                }}",
+                base, ty, field, case,
+            )?;
+        }
+        if gen_opt.contains(GenOpt::TAKE) && by_ref {
+            writeln!(
+                buf,
+                "#[inline] {decl}take_{}(&mut self) -> {} {{
+                    match self.{}.take() {{
+                        ::std::option::Option::Some({}(v)) => {}v,
+                        other => {{
+                            self.{2} = other;
+                            ::core::default::Default::default()
+                        }}
+                    }}
+                }}",
+                base,
+                ty,
+                field,
+                case,
+                if boxed { "*" } else { "" },
+            )?;
+        }
+    }
+    Ok(())
+}
+
+fn generate_encode_decode<W>(vis: &str, buf: &mut W) -> Result<(), io::Error>
+where
+    W: Write,
+{
+    writeln!(
+        buf,
+        "#[inline] {vis} fn to_vec(&self) -> ::std::vec::Vec<u8> {{
+            let mut buf = ::std::vec::Vec::with_capacity(::prost::Message::encoded_len(self));
+            ::prost::Message::encode(self, &mut buf).expect(\"Vec has sufficient capacity\");
+            buf
+        }}"
+    )?;
+    writeln!(
+        buf,
+        "#[inline] {vis} fn from_bytes(bytes: &[u8]) -> ::std::result::Result<Self, ::prost::DecodeError> {{
+            ::prost::Message::decode(bytes)
+        }}"
+    )?;
+    writeln!(
+        buf,
+        "#[inline] {vis} fn encoded_len(&self) -> usize {{ ::prost::Message::encoded_len(self) }}"
+    )?;
+    writeln!(
+        buf,
+        "#[inline] {vis} fn merge_length_delimited(&mut self, buf: &mut impl ::prost::bytes::Buf) -> ::std::result::Result<(), ::prost::DecodeError> {{
+            ::prost::Message::merge_length_delimited(self, buf)
+        }}"
+    )
+}
+
+fn write_impl_attributes<W>(config: &WrapperConfig, buf: &mut W) -> Result<(), io::Error>
+where
+    W: Write,
+{
+    write_item_attributes(config, buf)?;
+    for attr in config.impl_attributes() {
+        writeln!(buf, "{}", attr)?;
+    }
+    Ok(())
+}
+
+fn write_item_attributes<W>(config: &WrapperConfig, buf: &mut W) -> Result<(), io::Error>
+where
+    W: Write,
+{
+    for attr in config.item_attributes() {
+        writeln!(buf, "{}", attr)?;
+    }
+    Ok(())
+}
+
+fn generate_new<W>(
+    name: &Ident,
+    prefix: &str,
+    constructor: &str,
+    vis: &str,
+    buf: &mut W,
+) -> Result<(), io::Error>
+where
+    W: Write,
+{
+    writeln!(
+        buf,
+        "{vis} fn {}() -> {}{} {{ ::std::default::Default::default() }}",
+        constructor, prefix, name,
+    )
+}
+
+// See `WrapperConfig::sensitive_field`. The message is formatted through a
+// local wrapper type, since `Debug` is already implemented by Prost.
+fn generate_redacted_debug<W>(
+    item: &ItemStruct,
+    prefix: &str,
+    sensitive: &HashSet<String>,
+    vis: &str,
+    buf: &mut W,
+) -> Result<(), io::Error>
+where
+    W: Write,
+{
+    let names: Vec<_> = item
+        .fields
+        .iter()
+        .filter_map(|f| f.ident.as_ref())
+        .collect();
+    let mut missing: Vec<_> = sensitive
+        .iter()
+        .filter(|field| {
+            !names
+                .iter()
+                .any(|n| n.to_string().trim_start_matches("r#") == **field)
+        })
+        .collect();
+    missing.sort();
+    if let Some(field) = missing.first() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "Sensitive field `{}` not found in message `{}{}`",
+                field, prefix, item.ident
+            ),
+        ));
+    }
+
+    writeln!(
+        buf,
+        "#[inline] {vis} fn redacted_debug(&self) -> ::std::string::String {{
+    struct Mask;
+    impl ::std::fmt::Debug for Mask {{
+        fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {{ f.write_str(\"***\") }}
+    }}
+    struct Redacted<'a>(&'a {0}{1});
+    impl ::std::fmt::Debug for Redacted<'_> {{
+        fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {{
+            f.debug_struct(\"{1}\")",
+        prefix, item.ident,
+    )?;
+    for name in names {
+        let field = name.to_string();
+        let field = field.trim_start_matches("r#");
+        if sensitive.contains(field) {
+            writeln!(buf, "                .field(\"{}\", &Mask)", field)?;
+        } else {
+            writeln!(
+                buf,
+                "                .field(\"{}\", &self.0.{})",
+                field, name
+            )?;
+        }
+    }
+    writeln!(
+        buf,
+        "                .finish()
+        }}
+    }}
+    ::std::format!(\"{{:?}}\", Redacted(self))
+}}"
+    )
+}
+
+fn generate_display<W>(
+    item: &ItemStruct,
+    config: &WrapperConfig,
+    prefix: &str,
+    field: &str,
+    buf: &mut W,
+) -> Result<(), io::Error>
+where
+    W: Write,
+{
+    let name = item
+        .fields
+        .iter()
+        .filter_map(|f| f.ident.as_ref())
+        .find(|i| i.to_string().trim_start_matches("r#") == field)
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "Display field `{}` not found in message `{}{}`",
+                    field, prefix, item.ident
+                ),
+            )
+        })?;
+    write_item_attributes(config, buf)?;
+    writeln!(
+        buf,
+        "impl ::std::fmt::Display for {}{} {{
+            fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {{
+                ::std::fmt::Display::fmt(&self.{}, f)
+            }}
+        }}",
+        prefix, item.ident, name,
+    )
+}
+
+// See `WrapperConfig::bytes_conversions`.
+fn generate_bytes_conversions<W>(
+    item: &ItemStruct,
+    config: &WrapperConfig,
+    prefix: &str,
+    buf: &mut W,
+) -> Result<(), io::Error>
+where
+    W: Write,
+{
+    write_item_attributes(config, buf)?;
+    writeln!(
+        buf,
+        "impl ::std::convert::From<{0}{1}> for ::std::vec::Vec<u8> {{
+            fn from(msg: {0}{1}) -> Self {{
+                let mut buf = ::std::vec::Vec::with_capacity(::prost::Message::encoded_len(&msg));
+                ::prost::Message::encode(&msg, &mut buf).expect(\"Vec has sufficient capacity\");
+                buf
+            }}
+        }}",
+        prefix, item.ident,
+    )?;
+    write_item_attributes(config, buf)?;
+    writeln!(
+        buf,
+        "impl ::std::convert::From<{0}{1}> for ::prost::bytes::Bytes {{
+            fn from(msg: {0}{1}) -> Self {{
+                ::prost::bytes::Bytes::from(::std::vec::Vec::<u8>::from(msg))
+            }}
+        }}",
+        prefix, item.ident,
+    )
+}
+
+fn generate_as_ref<W>(
+    item: &ItemStruct,
+    config: &InputConfig,
+    prefix: &str,
+    module: &str,
+    field: &str,
+    buf: &mut W,
+) -> Result<(), io::Error>
+where
+    W: Write,
+{
+    let f = item
+        .fields
+        .iter()
+        .find(|f| {
+            f.ident
+                .as_ref()
+                .is_some_and(|i| i.to_string().trim_start_matches("r#") == field)
+        })
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "AsRef field `{}` not found in message `{}{}`",
+                    field, prefix, item.ident
+                ),
+            )
+        })?;
+    let kind = FieldAttrs::from_attrs(&f.attrs, prefix).kind();
+    let is_message = match &kind {
+        Some(FieldKind::Message) => true,
+        Some(FieldKind::Optional(fk)) => **fk == FieldKind::Message,
+        _ => false,
+    };
+    if !is_message {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "AsRef field `{}` of message `{}{}` is not a message",
+                field, prefix, item.ident
+            ),
+        ));
+    }
+    // The same expressions as `get_*` and `mut_*`, which always borrow the
+    // message itself.
+    let mut config = config.clone();
+    config.option_getters(false);
+    let kind = kind.unwrap();
+    let (ty, module) = match qualify_message_field(&f.ty, &kind, prefix) {
+        Some(ty) => (ty, module.strip_prefix(prefix).unwrap_or(module)),
+        None => (f.ty.clone(), module),
+    };
+    let methods = kind
+        .methods(&ty, f.ident.as_ref().unwrap(), None, &config, module)?
+        .unwrap();
+    let ty = match &methods.ref_ty {
+        RefType::Deref(s) => s,
+        _ => methods.override_ty.as_ref().unwrap_or(&methods.ty),
+    };
+    let name = &methods.name;
+    let get = match &methods.get {
+        Some(s) => s.clone(),
+        None => format!("&self.{}", name),
+    };
+    let mt = match &methods.mt {
+        MethodKind::Custom(s) => s.clone(),
+        _ => format!("&mut self.{}", name),
+    };
+    write_item_attributes(&config, buf)?;
+    writeln!(
+        buf,
+        "impl ::std::convert::AsRef<{0}> for {1}{2} {{
+            #[inline] fn as_ref(&self) -> &{0} {{ {3} }}
+        }}",
+        ty, prefix, item.ident, get,
+    )?;
+    write_item_attributes(&config, buf)?;
+    writeln!(
+        buf,
+        "impl ::std::convert::AsMut<{0}> for {1}{2} {{
+            #[inline] fn as_mut(&mut self) -> &mut {0} {{ {3} }}
+        }}",
+        ty, prefix, item.ident, mt,
+    )
+}
+
+// The protobuf name of a message, and its type URL, as used by `Any`.
+fn generate_full_name<W>(full_name: &str, vis: &str, buf: &mut W) -> Result<(), io::Error>
+where
+    W: Write,
+{
+    writeln!(
+        buf,
+        "#[inline] {vis} fn full_name() -> &'static str {{ {:?} }}",
+        full_name
+    )?;
+    writeln!(
+        buf,
+        "#[inline] {vis} fn type_url() -> ::std::string::String {{ ::std::string::String::from({:?}) }}",
+        format!("type.googleapis.com/{}", full_name)
+    )
+}
+
+// A lightweight alternative to reflection: the name, tag, and kind of each
+// field. Oneofs don't have a single tag so they are omitted, instead there is a
+// constant listing the tags of each oneof, e.g., `FOO_TAGS` for `foo`. Fields
+// and tags are ordered by tag, so the constants don't depend on the order the
+// fields are declared in.
+fn generate_fields_const<W>(
+    item: &ItemStruct,
+    prefix: &str,
+    vis: &str,
+    buf: &mut W,
+) -> Result<(), io::Error>
+where
+    W: Write,
+{
+    let mut fields: Vec<_> = item
+        .fields
+        .iter()
+        .filter_map(|f| {
+            let attrs = FieldAttrs::from_attrs(&f.attrs, prefix);
+            Some((attrs.tag?, f.ident.as_ref()?, attrs.kind()?))
+        })
+        .collect();
+    fields.sort_by_key(|(tag, _, _)| *tag);
+    writeln!(buf, "{vis} const FIELDS: &[(&str, u32, &str)] = &[")?;
+    for (tag, name, kind) in fields {
+        writeln!(
+            buf,
+            "    (\"{}\", {}, \"{}\"),",
+            name.to_string().trim_start_matches("r#"),
+            tag,
+            kind.description(),
+        )?;
+    }
+    writeln!(buf, "    ];")?;
+    for f in &item.fields {
+        let mut attrs = FieldAttrs::from_attrs(&f.attrs, prefix);
+        if let (Some(name), Some(FieldKind::OneOf(_))) = (&f.ident, attrs.kind()) {
+            attrs.tags.sort_unstable();
+            let tags: Vec<_> = attrs.tags.iter().map(|t| t.to_string()).collect();
+            writeln!(
+                buf,
+                "{vis} const {}_TAGS: &[u32] = &[{}];",
+                name.to_string().trim_start_matches("r#").to_uppercase(),
+                tags.join(", "),
+            )?;
+        }
+    }
+    Ok(())
+}
+
+// Generates `for_each_{kind}_field_mut`, which calls a closure on each value
+// of the fields of `kind` (`string` or `bytes`). `ty` is the type of the
+// values, and the identifiers which a field's type must end with to be
+// visited, so that, e.g., boxed strings are skipped. Nothing is generated if
+// there are no fields to visit.
+fn generate_for_each_mut<W>(
+    item: &ItemStruct,
+    prefix: &str,
+    kind: &str,
+    (ty, idents): (&str, &[&str]),
+    vis: &str,
+    buf: &mut W,
+) -> Result<(), io::Error>
+where
+    W: Write,
+{
+    let marker = FieldKind::from_marker(kind).expect("Unknown field kind");
+    let is_ty = |t: &Type| {
+        let tokens: Vec<_> = t
+            .to_token_stream()
+            .into_iter()
+            .filter_map(|t| match t {
+                proc_macro2::TokenTree::Ident(i) => Some(i.to_string()),
+                _ => None,
+            })
+            .collect();
+        tokens.ends_with(&idents.iter().map(|s| s.to_string()).collect::<Vec<_>>())
+    };
+    let mut visits = Vec::new();
+    for f in &item.fields {
+        let name = match &f.ident {
+            Some(name) => name,
+            None => continue,
+        };
+        match FieldAttrs::from_attrs(&f.attrs, prefix).kind() {
+            Some(k) if k == marker && is_ty(&f.ty) => {
+                visits.push(format!("f(&mut self.{});", name))
+            }
+            Some(FieldKind::Optional(k)) if *k == marker && is_ty(unwrap_type(&f.ty, "Option")) => {
+                visits.push(format!(
+                    "if let ::std::option::Option::Some(v) = &mut self.{} {{ f(v); }}",
+                    name
+                ))
+            }
+            Some(FieldKind::Repeated {
+                element: Some(k), ..
+            }) if *k == marker && element_type(&mut f.ty.clone()).is_some_and(|t| is_ty(t)) => {
+                visits.push(format!("self.{}.iter_mut().for_each(&mut f);", name))
+            }
+            _ => {}
+        }
+    }
+    if visits.is_empty() {
+        return Ok(());
+    }
+    writeln!(
+        buf,
+        "#[inline] {vis} fn for_each_{}_field_mut(&mut self, mut f: impl ::std::ops::FnMut(&mut {})) {{",
+        kind, ty,
+    )?;
+    for visit in visits {
+        writeln!(buf, "    {}", visit)?;
+    }
+    writeln!(buf, "}}")
+}
+
+// A module of constants naming the fields of a message, e.g., for building the
+// paths of a `FieldMask`. The module is named after the message's path, since
+// Prost may already have declared a module named after the message.
+fn generate_field_names<W>(
+    item: &ItemStruct,
+    config: &WrapperConfig,
+    prefix: &str,
+    public: bool,
+    buf: &mut W,
+) -> Result<(), io::Error>
+where
+    W: Write,
+{
+    write_item_attributes(config, buf)?;
+    writeln!(
+        buf,
+        "{}mod {}{}_field_names {{",
+        if public { "pub " } else { "pub(crate) " },
+        config_path(prefix).replace("::", "_"),
+        to_snake_case(&item.ident.to_string()),
+    )?;
+    for f in item.fields.iter().filter(|f| is_prost_field(f)) {
+        if let Some(name) = &f.ident {
+            let name = name.to_string();
+            let name = name.trim_start_matches("r#");
+            writeln!(
+                buf,
+                "    pub const {}: &str = \"{}\";",
+                name.to_uppercase(),
+                name
+            )?;
+        }
+    }
+    writeln!(buf, "}}")
+}
+
+// Access to fields by name, e.g., for applying a `FieldMask`. Names are as in
+// the proto file (i.e., without `r#`), a oneof is named by its field.
+fn generate_field_mask<W>(item: &ItemStruct, vis: &str, buf: &mut W) -> Result<(), io::Error>
+where
+    W: Write,
+{
+    let names: Vec<_> = item
+        .fields
+        .iter()
+        .filter_map(|f| f.ident.as_ref())
+        .collect();
+    writeln!(
+        buf,
+        "#[inline] {vis} fn clear_field_by_name(&mut self, name: &str) -> bool {{
+            match name {{"
+    )?;
+    for name in &names {
+        writeln!(
+            buf,
+            "                \"{}\" => {{ self.{} = ::std::default::Default::default(); true }}",
+            name.to_string().trim_start_matches("r#"),
+            name,
+        )?;
+    }
+    writeln!(
+        buf,
+        "                _ => false,\n            }}\n        }}"
+    )?;
+    writeln!(
+        buf,
+        "#[inline] {vis} fn copy_field_by_name(&mut self, name: &str, other: &Self) -> bool {{
+            match name {{"
+    )?;
+    for name in &names {
+        writeln!(
+            buf,
+            "                \"{}\" => {{ self.{1}.clone_from(&other.{1}); true }}",
+            name.to_string().trim_start_matches("r#"),
+            name,
+        )?;
+    }
+    writeln!(
+        buf,
+        "                _ => false,\n            }}\n        }}"
+    )
+}
+
+// Like rust-protobuf's `CopyFrom`. Fields are copied using `clone_from` so that
+// existing allocations can be reused.
+fn generate_copy_from<W>(item: &ItemStruct, vis: &str, buf: &mut W) -> Result<(), io::Error>
+where
+    W: Write,
+{
+    writeln!(buf, "{vis} fn copy_from(&mut self, other: &Self) {{")?;
+    for name in item.fields.iter().filter_map(|f| f.ident.as_ref()) {
+        writeln!(buf, "    self.{0}.clone_from(&other.{0});", name)?;
+    }
+    writeln!(buf, "    }}")
+}
+
+// Fields of `other` which are set overwrite those of `self`, except that
+// repeated fields and maps are extended and messages are merged.
+fn generate_merge_from<W>(
+    item: &ItemStruct,
+    prefix: &str,
+    vis: &str,
+    buf: &mut W,
+) -> Result<(), io::Error>
+where
+    W: Write,
+{
+    writeln!(buf, "{vis} fn merge_from(&mut self, other: Self) {{")?;
+    for f in &item.fields {
+        let name = match &f.ident {
+            Some(name) => name,
+            None => continue,
+        };
+        let attrs = FieldAttrs::from_attrs(&f.attrs, prefix);
+        let kind = match attrs.kind() {
+            Some(kind) => kind,
+            None => continue,
+        };
+        let boxed = attrs.boxed || is_boxed(unwrap_type(&f.ty, "Option"));
+        match kind {
+            FieldKind::Repeated { .. } | FieldKind::Map { .. } => {
+                writeln!(buf, "    self.{0}.extend(other.{0});", name)?
+            }
+            // Not part of the message's contents.
+            FieldKind::Ignored => {}
+            // Types from `prost_types` don't have a `merge_from`.
+            FieldKind::Optional(fk) if *fk == FieldKind::Message && !is_prost_type(&f.ty) => {
+                writeln!(
+                    buf,
+                    "    match (&mut self.{0}, other.{0}) {{
+        (::std::option::Option::Some(m), ::std::option::Option::Some(v)) => m.merge_from({1}v),
+        (m, v @ ::std::option::Option::Some(_)) => *m = v,
+        _ => {{}}
+    }}",
+                    name,
+                    if boxed { "*" } else { "" },
+                )?
+            }
+            FieldKind::Optional(_) | FieldKind::OneOf(_) => writeln!(
+                buf,
+                "    if other.{0}.is_some() {{ self.{0} = other.{0}; }}",
+                name
+            )?,
+            FieldKind::Message if !is_prost_type(&f.ty) => writeln!(
+                buf,
+                "    self.{0}.merge_from({1}other.{0});",
+                name,
+                if boxed { "*" } else { "" },
+            )?,
+            _ => writeln!(
+                buf,
+                "    if other.{0} != ::std::default::Default::default() {{ self.{0} = other.{0}; }}",
+                name
+            )?,
+        }
+    }
+    writeln!(buf, "    }}")
+}
+
+// Whether every field has its default value, compared field by field rather
+// than with a default message. Fields which aren't encoded are ignored.
+fn generate_is_default<W>(
+    item: &ItemStruct,
+    prefix: &str,
+    vis: &str,
+    buf: &mut W,
+) -> Result<(), io::Error>
+where
+    W: Write,
+{
+    let mut checks = Vec::new();
+    for f in item.fields.iter().filter(|f| is_prost_field(f)) {
+        let name = match &f.ident {
+            Some(name) => name,
+            None => continue,
+        };
+        let attrs = FieldAttrs::from_attrs(&f.attrs, prefix);
+        let deref = if is_boxed(&f.ty) { "*" } else { "" };
+        checks.push(match attrs.kind() {
+            Some(FieldKind::Ignored) => continue,
+            Some(FieldKind::Optional(_) | FieldKind::OneOf(_)) => {
+                format!("self.{}.is_none()", name)
+            }
+            Some(
+                FieldKind::Repeated { .. }
+                | FieldKind::Map { .. }
+                | FieldKind::String
+                | FieldKind::Bytes,
+            ) => format!("self.{}.is_empty()", name),
+            Some(FieldKind::Int) if attrs.default.is_none() => {
+                format!("{}self.{} == 0", deref, name)
+            }
+            Some(FieldKind::Float) if attrs.default.is_none() => {
+                format!("{}self.{} == 0.", deref, name)
+            }
+            Some(FieldKind::Bool) if attrs.default.is_none() => {
+                format!("!{}self.{}", deref, name)
+            }
+            Some(FieldKind::Enumeration(_)) if attrs.default.is_none() && is_i32(&f.ty) => {
+                format!("self.{} == 0", name)
+            }
+            // E.g., proto2 fields with a default value.
+            _ => format!("self.{} == ::std::default::Default::default()", name),
+        });
+    }
+    if checks.is_empty() {
+        checks.push("true".to_owned());
+    }
+    writeln!(
+        buf,
+        "#[inline] {vis} fn is_default(&self) -> bool {{\n    {}\n}}",
+        checks.join("\n        && ")
+    )
+}
+
+fn generate_default_ref<W>(
+    name: &Ident,
+    prefix: &str,
+    gen_opt: GenOpt,
+    vis: &str,
+    buf: &mut W,
+) -> Result<(), io::Error>
+where
+    W: Write,
+{
+    if gen_opt.contains(GenOpt::MESSAGE) {
+        writeln!(
+            buf,
+            "#[inline] {vis} fn default_ref() -> &'static Self {{ ::protobuf::Message::default_instance() }}",
+        )
+    } else {
+        writeln!(
+            buf,
+            "#[inline] {vis} fn default_ref() -> &'static Self {{
+                ::lazy_static::lazy_static! {{
+                    static ref INSTANCE: {0}{1} = {0}{1}::default();
+                }}
+                &*INSTANCE
+            }}",
+            prefix, name,
+        )
+    }
+}
+
+// The `MessageExt` trait is declared in the generated `mod.rs`.
+fn generate_message_ext<W>(
+    name: &Ident,
+    config: &WrapperConfig,
+    prefix: &str,
+    constructors: bool,
+    buf: &mut W,
+) -> Result<(), io::Error>
+where
+    W: Write,
+{
+    let ty = format!("{}{}", prefix, name);
+    let default_instance = if constructors {
+        "Self::default_ref()".to_owned()
+    } else {
+        static_default(&ty)
+    };
+    write_item_attributes(config, buf)?;
+    writeln!(
+        buf,
+        "impl MessageExt for {} {{
+            #[inline] fn new_() -> Self {{ ::std::default::Default::default() }}
+            #[inline] fn default_instance() -> &'static Self {{ {} }}
+        }}",
+        ty, default_instance,
+    )
+}
+
+// An expression for a reference to a default value of `ty`, which must be a
+// message, without using its `default_ref`.
+fn static_default(ty: &str) -> String {
+    format!(
+        "{{
+            ::lazy_static::lazy_static! {{
+                static ref DEFAULT: {} = ::std::default::Default::default();
+            }}
+            &*DEFAULT
+        }}",
+        ty,
+    )
+}
+
+// The path to a message as used in configuration, i.e., without raw identifiers.
+fn config_path(path: &str) -> String {
+    path.split("::")
+        .map(|s| s.trim().trim_start_matches("r#"))
+        .collect::<Vec<_>>()
+        .join("::")
+}
+
+// A call to the function `name` in the `__enums` module declared in `mod.rs`
+// (see `ENUM_HELPERS`) if `config` shares enum helpers, otherwise `inline`.
+fn enum_helper(config: &WrapperConfig, name: &str, args: &str, inline: String) -> String {
+    if config.has_shared_enum_helpers() {
+        format!("__enums::{}({})", name, args)
+    } else {
+        inline
+    }
+}
+
+fn generate_message_trait<W>(
+    name: &Ident,
+    config: &WrapperConfig,
+    prefix: &str,
+    buf: &mut W,
+) -> Result<(), io::Error>
+where
+    W: Write,
+{
+    write_item_attributes(config, buf)?;
+    write!(buf, "impl ::protobuf::Clear for {}{} {{", prefix, name)?;
+    writeln!(
+        buf,
+        "fn clear(&mut self) {{ ::prost::Message::clear(self); }}",
+    )?;
+    writeln!(buf, "}}")?;
+
+    write_item_attributes(config, buf)?;
+    write!(buf, "impl ::protobuf::Message for {}{} {{", prefix, name)?;
+    // rust-protobuf's sizes are `u32`s.
+    writeln!(
+        buf,
+        "#[allow(clippy::cast_possible_truncation)]\nfn compute_size(&self) -> u32 {{ ::prost::Message::encoded_len(self) as u32 }}",
+    )?;
+    writeln!(
+        buf,
+        "#[allow(clippy::cast_possible_truncation)]\nfn get_cached_size(&self) -> u32 {{ ::prost::Message::encoded_len(self) as u32 }}",
+    )?;
+    writeln!(
+        buf,
+        "fn as_any(&self) -> &dyn ::std::any::Any {{ self as &dyn ::std::any::Any }}",
+    )?;
+    writeln!(
+        buf,
+        "fn descriptor(&self) -> &'static ::protobuf::reflect::MessageDescriptor {{ Self::descriptor_static() }}",
+    )?;
+    writeln!(buf, "fn new() -> Self {{ Self::default() }}",)?;
+    writeln!(
+        buf,
+        "fn default_instance() -> &'static {}{} {{
+        ::lazy_static::lazy_static! {{
+            static ref INSTANCE: {0}{1} = {0}{1}::default();
+        }}
+        &*INSTANCE
+    }}",
+        prefix, name,
+    )?;
+    // The only way for this to be false is if there are `required` fields, but
+    // afaict, we never use that feature. In any case rust-protobuf plans to
+    // always return `true` in 3.0.
+    writeln!(buf, "fn is_initialized(&self) -> bool {{ true }}",)?;
+    writeln!(
+        buf,
+        "#[track_caller] fn write_to_with_cached_sizes(&self, _os: &mut ::protobuf::CodedOutputStream) -> ::protobuf::ProtobufResult<()> {{ unimplemented!(); }}",
+    )?;
+    writeln!(
+        buf,
+        "#[track_caller] fn merge_from(&mut self, _is: &mut ::protobuf::CodedInputStream) -> ::protobuf::ProtobufResult<()> {{ unimplemented!(); }}",
+    )?;
+    writeln!(
+        buf,
+        "#[track_caller] fn get_unknown_fields(&self) -> &::protobuf::UnknownFields {{ unimplemented!(); }}",
+    )?;
+    writeln!(
+        buf,
+        "#[track_caller] fn mut_unknown_fields(&mut self) -> &mut ::protobuf::UnknownFields {{ unimplemented!(); }}",
+    )?;
+    writeln!(
+        buf,
+        "fn write_to_bytes(&self) -> ::protobuf::ProtobufResult<Vec<u8>> {{
+            let mut buf = Vec::new();
+            if ::prost::Message::encode(self, &mut buf).is_err() {{
+                return Err(::protobuf::ProtobufError::WireError(::protobuf::error::WireError::Other));
+            }}
+            Ok(buf)
+        }}"
+    )?;
+    writeln!(
+        buf,
+        "fn merge_from_bytes(&mut self, bytes: &[u8]) -> ::protobuf::ProtobufResult<()> {{
+            if ::prost::Message::merge(self, bytes).is_err() {{
+                return Err(::protobuf::ProtobufError::WireError(::protobuf::error::WireError::Other));
+            }}
+            Ok(())
+        }}"
+    )?;
+    writeln!(buf, "}}")
+}
+
+// The Rust type of each of these is taken from the field, e.g., `fixed32` is
+// `u32` and `sfixed32` is `i32`.
+const INT_TYPES: [&str; 10] = [
+    "int32", "int64", "uint32", "uint64", "sint32", "sint64", "fixed32", "fixed64", "sfixed32",
+    "sfixed64",
+];
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+enum FieldKind {
+    Optional(Box<FieldKind>),
+    Repeated {
+        // A module prefix of a repeated message.
+        prefix: String,
+        // The kind of the elements, if known.
+        element: Option<Box<FieldKind>>,
+    },
+    Message,
+    Int,
+    Float,
+    Bool,
+    Bytes,
+    String,
+    OneOf(String),
+    Enumeration(String),
+    Map {
+        // A module prefix of a message value.
+        prefix: String,
+        // The kind of the values, if known.
+        value: Option<Box<FieldKind>>,
+    },
+    // A field which isn't encoded, marked `#[prost(skip)]`.
+    Ignored,
+}
+
+// The tag given by `lit`, whose value is `value`, as a string or an integer
+// literal. Integer literals may have a suffix or another base, e.g., `0x10u32`.
+fn parse_tag(lit: &Lit, value: &str) -> Option<u32> {
+    match lit {
+        Lit::Int(i) => i.base10_parse().ok(),
+        _ => value.trim().parse().ok(),
+    }
+}
+
+// The contents of a field's `#[prost(...)]` attributes.
+#[derive(Clone, Debug, Default)]
+struct FieldAttrs {
+    // The module prefix of the field's message.
+    prefix: String,
+    // Markers for the field's type, e.g., `string`. A field should have
+    // exactly one.
+    types: Vec<FieldKind>,
+    // Labels which modify the field's type.
+    optional: bool,
+    repeated: bool,
+    // A proto2 `required` field is stored as its type, like a singular proto3
+    // field, so the label doesn't modify its type.
+    required: bool,
+    // Whether the field is marked `boxed`. Older Prost doesn't mark boxed
+    // fields, so they are also recognized by their type, `Box<_>` or
+    // `Option<Box<_>>`.
+    boxed: bool,
+    tag: Option<u32>,
+    // The tags of the fields of a oneof.
+    tags: Vec<u32>,
+    // Whether the field is packed, if given explicitly.
+    packed: Option<bool>,
+    // The default value of a proto2 field.
+    default: Option<String>,
+    // The enum of an enum field, as named in the attribute.
+    enumeration: Option<String>,
+    // Any other markers, e.g., `custom` is `("custom", None)`.
+    other: Vec<(String, Option<String>)>,
+}
+
+impl FieldAttrs {
+    fn from_attrs(attrs: &[Attribute], prefix: &str) -> FieldAttrs {
+        let mut result = FieldAttrs {
+            prefix: prefix.to_owned(),
+            ..FieldAttrs::default()
+        };
+        // Markers may be split across several `prost` attributes.
+        for a in attrs.iter().filter(|a| a.path.is_ident("prost")) {
+            let list = match a.parse_meta() {
+                Ok(Meta::List(list)) => list,
+                _ => continue,
+            };
+            for item in &list.nested {
+                match item {
+                    NestedMeta::Meta(Meta::Path(path)) => {
+                        let name = path.to_token_stream().to_string();
+                        match &*name {
+                            "optional" => result.optional = true,
+                            "repeated" => result.repeated = true,
+                            "required" => result.required = true,
+                            "boxed" => result.boxed = true,
+                            "skip" => result.types.push(FieldKind::Ignored),
+                            _ => match FieldKind::from_marker(&name) {
+                                Some(kind) => result.types.push(kind),
+                                None => result.other.push((name, None)),
+                            },
+                        }
+                    }
+                    NestedMeta::Meta(Meta::NameValue(mnv)) => {
+                        let name = mnv.path.to_token_stream().to_string();
+                        // Prost always uses string literals, but they may be raw or contain
+                        // escapes, so use the literal's value rather than its source.
+                        let (value, is_str) = match &mnv.lit {
+                            Lit::Str(s) => (s.value(), true),
+                            lit => (lit.to_token_stream().to_string(), false),
+                        };
+                        match &*name {
+                            // Tags may also be integer literals, e.g., `tag = 1`.
+                            "tag" => result.tag = parse_tag(&mnv.lit, &value),
+                            "tags" if is_str => {
+                                result.tags = value
+                                    .split(',')
+                                    .filter_map(|t| t.trim().parse().ok())
+                                    .collect()
+                            }
+                            "tags" => {
+                                result.tags = parse_tag(&mnv.lit, &value).into_iter().collect()
+                            }
+                            "packed" => result.packed = Some(value == "true"),
+                            "default" => result.default = Some(value),
+                            "enumeration" if is_str => {
+                                let path = qualify_path(&value, prefix);
+                                result.types.push(FieldKind::Enumeration(path));
+                                result.enumeration = Some(value);
+                            }
+                            "oneof" if is_str => result
+                                .types
+                                .push(FieldKind::OneOf(format!("{}{}", prefix, value))),
+                            // E.g., `string, message`.
+                            "map" | "btree_map" => result.types.push(FieldKind::Map {
+                                prefix: prefix.to_owned(),
+                                value: value
+                                    .split(',')
+                                    .nth(1)
+                                    .and_then(|v| FieldKind::from_marker(v.trim()))
+                                    .map(Box::new),
+                            }),
+                            _ => result.other.push((name, Some(value))),
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+        result
+    }
+
+    // Whether the field is encoded as a packed repeated field. Prost packs
+    // repeated scalar fields unless told otherwise.
+    fn is_packed(&self) -> bool {
+        let scalar = matches!(
+            &*self.types,
+            [FieldKind::Int | FieldKind::Float | FieldKind::Bool | FieldKind::Enumeration(_)]
+        );
+        self.repeated && self.packed.unwrap_or(scalar)
+    }
+
+    // Combines the field's type and labels, e.g., `bytes, optional` is stored
+    // as `Option<Vec<u8>>`. `None` if the field's kind is not supported.
+    fn kind(&self) -> Option<FieldKind> {
+        let ty = match &*self.types {
+            [ty] => Some(ty.clone()),
+            [] => None,
+            // Conflicting types.
+            _ => return None,
+        };
+        // Conflicting labels.
+        if self.required && (self.optional || self.repeated) {
+            return None;
+        }
+        // Maps, oneofs, and ignored fields can't be labelled.
+        let labelled = self.optional || self.repeated;
+        if labelled
+            && matches!(
+                ty,
+                Some(FieldKind::Map { .. } | FieldKind::OneOf(_) | FieldKind::Ignored)
+            )
+        {
+            return None;
+        }
+        match (ty, self.optional, self.repeated) {
+            (Some(ty), false, false) => Some(ty),
+            (Some(ty), true, false) => Some(FieldKind::Optional(Box::new(ty))),
+            // The elements of a repeated field may be of an unknown type.
+            (ty, false, true) => Some(FieldKind::Repeated {
+                prefix: self.prefix.clone(),
+                element: ty.map(Box::new),
+            }),
+            _ => None,
+        }
+    }
+
+    // Like `kind`, but the enum of an enum field is given by
+    // `WrapperConfig::extern_enum`, if it is mapped there.
+    fn resolved_kind(&self, config: &WrapperConfig) -> Option<FieldKind> {
+        match self
+            .enumeration
+            .as_deref()
+            .and_then(|e| config.extern_enum_path(e))
+        {
+            Some(path) => FieldAttrs {
+                types: vec![FieldKind::Enumeration(path.to_owned())],
+                ..self.clone()
+            }
+            .kind(),
+            None => self.kind(),
+        }
+    }
+}
+
+impl FieldKind {
+    // `None` if the field's kind is not supported.
+    fn try_from_attrs(attrs: &[Attribute], prefix: &str) -> Option<FieldKind> {
+        FieldAttrs::from_attrs(attrs, prefix).kind()
+    }
+
+    // The type corresponding to a marker in a `#[prost(...)]` attribute, e.g., `string`.
+    fn from_marker(marker: &str) -> Option<FieldKind> {
+        Some(match marker {
+            "message" => FieldKind::Message,
+            "bytes" => FieldKind::Bytes,
+            "string" => FieldKind::String,
+            "bool" => FieldKind::Bool,
+            "float" | "double" => FieldKind::Float,
+            _ if INT_TYPES.contains(&marker) => FieldKind::Int,
+            _ => return None,
+        })
+    }
+
+    // A human readable description of the kind.
+    fn description(&self) -> String {
+        match self {
+            FieldKind::Optional(fk) => format!("optional {}", fk.description()),
+            FieldKind::Repeated {
+                element: Some(fk), ..
+            } => format!("repeated {}", fk.description()),
+            FieldKind::Repeated { element: None, .. } => "repeated".to_owned(),
+            FieldKind::Message => "message".to_owned(),
+            FieldKind::Int => "int".to_owned(),
+            FieldKind::Float => "float".to_owned(),
+            FieldKind::Bool => "bool".to_owned(),
+            FieldKind::Bytes => "bytes".to_owned(),
+            FieldKind::String => "string".to_owned(),
+            FieldKind::OneOf(_) => "oneof".to_owned(),
+            FieldKind::Enumeration(_) => "enumeration".to_owned(),
+            FieldKind::Map { .. } => "map".to_owned(),
+            FieldKind::Ignored => "ignored".to_owned(),
+        }
+    }
+
+    // Statements which set, get, and clear the field `name` of a message `m`.
+    // Afterwards, `m` should be equal to the default message. `None` if the
+    // field's kind is not tested.
+    //
+    // `into_ty` is the type of the field's value if its setter takes an
+    // `impl Into<_>` or `impl AsRef<_>`.
+    fn accessor_test(
+        &self,
+        name: &str,
+        method_prefix: &str,
+        into_ty: Option<&str>,
+    ) -> Option<String> {
+        let (value, expected) = match self {
+            FieldKind::Optional(fk) if **fk == FieldKind::Message => {
+                let value = match into_ty {
+                    Some(ty) => format!("<{}>::default()", ty),
+                    None => "::std::default::Default::default()".to_owned(),
+                };
+                return Some(format!(
+                    "m.{0}set_{1}({2});
+                    assert!(m.{0}has_{1}());
+                    m.{0}clear_{1}();",
+                    method_prefix, name, value,
+                ));
+            }
+            FieldKind::Optional(fk) => return fk.accessor_test(name, method_prefix, into_ty),
+            FieldKind::Int => ("1", "1"),
+            FieldKind::Float => ("1.0", "1.0"),
+            FieldKind::Bool => ("true", "true"),
+            FieldKind::String if into_ty.is_some() => ("\"a\"", "\"a\""),
+            FieldKind::String => ("\"a\".into()", "\"a\""),
+            FieldKind::Bytes => ("[1u8][..].to_vec()", "&[1u8][..]"),
+            _ => return None,
+        };
+        Some(format!(
+            "m.{0}set_{1}({2});
+            assert_eq!(m.{0}get_{1}(), {3});
+            m.{0}clear_{1}();",
+            method_prefix, name, value, expected,
+        ))
+    }
+
+    // `module` is the module containing the message, relative to the generated
+    // module. `default` is the field's proto2 default, as given by Prost.
+    //
+    // Returns an error if the field's type doesn't match its kind.
+    fn methods(
+        &self,
+        ty: &Type,
+        ident: &Ident,
+        default: Option<&str>,
+        config: &InputConfig,
+        module: &str,
+    ) -> Result<Option<FieldMethods>, io::Error> {
+        let mut result = FieldMethods::new(ty, ident);
+        // The value of an enum field which is unset or unknown: the field's
+        // default variant (e.g., `High` for `[default = HIGH]`), if any,
+        // otherwise the enum's default, which Prost makes its first variant.
+        let enum_default = |enum_type: &str| match default {
+            Some(variant) => format!("{}::{}", type_in_expr_context(enum_type), variant),
+            None => format!("{}::default()", type_in_expr_context(enum_type)),
+        };
+        match self {
+            FieldKind::Optional(fk) => {
+                let unwrapped_type = unwrap_type(ty, "Option");
+                let unboxed_type = unwrap_type(unwrapped_type, "Box");
+                let nested_methods = fk
+                    .methods(unwrapped_type, ident, default, config, module)?
+                    .ok_or_else(|| unsupported_field(ident))?;
+                if let FieldKind::Message = &**fk {
+                    result.well_known = WellKnownType::from_type(unwrapped_type);
+                }
+                // An enum stored as an `i32`.
+                let raw_enum = matches!(**fk, FieldKind::Enumeration(_)) && is_i32(unwrapped_type);
+                let boxed = is_boxed(unwrapped_type);
+                let deref_bytes = derefs_bytes(config, unwrapped_type);
+                let unwrapped_type = unwrapped_type.into_token_stream().to_string();
+                let unboxed_type_str = unboxed_type.into_token_stream().to_string();
+
+                result.override_ty = Some(match nested_methods.override_ty {
+                    Some(t) => t,
+                    None => unwrapped_type.clone(),
+                });
+                result.ref_ty = nested_methods.ref_ty;
+                result.set_ty = nested_methods.set_ty;
+                result.enum_set = nested_methods.enum_set;
+                result.into_set = nested_methods.into_set;
+                result.as_ref_set = nested_methods.as_ref_set;
+                result.presence = Some(Presence::Option);
+                if raw_enum {
+                    result.set = Some(format!(
+                        "::std::option::Option::Some({})",
+                        enum_helper(config, "set", "v", "v as i32".to_owned())
+                    ));
+                    result.raw_set = Some("::std::option::Option::Some(v)".to_owned());
+                } else {
+                    // Boxed fields box the value.
+                    result.set = Some(format!(
+                        "::std::option::Option::Some({})",
+                        nested_methods.set.as_deref().unwrap_or("v")
+                    ));
+                }
+
+                let as_ref = match &result.ref_ty {
+                    RefType::Ref | RefType::Deref(_) => {
+                        result.mt = MethodKind::Custom(format!(
+                            "if self.{}.is_none() {{
+                                self.{0} = ::std::option::Option::Some(::core::default::Default::default());
+                            }}
+                            self.{0}.as_mut().unwrap()",
+                            result.name,
+                        ));
+                        match &result.ref_ty {
+                            RefType::Deref(_) => ".as_deref()",
+                            _ => ".as_ref()",
+                        }
+                    }
+                    RefType::Copy if boxed => ".as_deref().copied()",
+                    RefType::Copy => "",
+                };
+
+                let init_val = match &**fk {
+                    FieldKind::Message => {
+                        result.insert_with =
+                            Some(format!("self.{}.get_or_insert_with(f)", result.name));
+                        result.take =
+                            Some(format!("self.{}.take().unwrap_or_default()", result.name));
+                        result.clone =
+                            Some(format!("self.{}.clone().unwrap_or_default()", result.name));
+                        let message = config_path(&qualify_type(unboxed_type, module));
+                        if config.has_constructors(&message) {
+                            format!("{}::default_ref()", type_in_expr_context(&unboxed_type_str))
+                        } else {
+                            static_default(&unboxed_type_str)
+                        }
+                    }
+                    FieldKind::Bytes => {
+                        result.take =
+                            Some(format!("self.{}.take().unwrap_or_default()", result.name));
+                        if deref_bytes || boxed {
+                            "&[]".to_owned()
+                        } else {
+                            format!(
+                                "{{ const EMPTY: &{} = &{}::new(); EMPTY }}",
+                                unwrapped_type,
+                                type_in_expr_context(&unwrapped_type),
+                            )
+                        }
+                    }
+                    FieldKind::String => {
+                        result.take =
+                            Some(format!("self.{}.take().unwrap_or_default()", result.name));
+                        "\"\"".to_owned()
+                    }
+                    FieldKind::Int | FieldKind::Enumeration(_) => "0".to_owned(),
+                    FieldKind::Float => "0.".to_owned(),
+                    FieldKind::Bool => "false".to_owned(),
+                    _ => return Err(unsupported_field(ident)),
+                };
+                // Scalars are taken by value, leaving the field unset.
+                if matches!(**fk, FieldKind::Int | FieldKind::Float | FieldKind::Bool) {
+                    result.take = Some(format!("self.{}.take().unwrap_or_default()", result.name));
+                }
+
+                if let (FieldKind::Message, true) = (&**fk, config.has_option_getters()) {
+                    result.get = Some(format!("self.{}{}", result.name, as_ref));
+                    result.get_ty = Some(format!("::std::option::Option<&{}>", unboxed_type_str));
+                    return Ok(Some(result));
+                }
+                if let (FieldKind::String, true) = (&**fk, config.has_cow_string_getters()) {
+                    result.get = Some(format!(
+                        "self.{}.as_deref().map_or(::std::borrow::Cow::Owned(::std::string::String::new()), ::std::borrow::Cow::Borrowed)",
+                        result.name
+                    ));
+                    result.get_ty = Some("::std::borrow::Cow<'_, str>".to_owned());
+                    return Ok(Some(result));
+                }
+
+                if let FieldKind::Enumeration(t) = &**fk {
+                    if raw_enum {
+                        // An unset field has the default value, as for `get_*`.
+                        let try_get = format!(
+                            "match self.{} {{ ::std::option::Option::Some(v) => {}::from_i32(v).ok_or(v), ::std::option::Option::None => ::std::result::Result::Ok({}) }}",
+                            result.name,
+                            type_in_expr_context(t),
+                            enum_default(t),
+                        );
+                        result.try_get = Some(match default {
+                            Some(_) => try_get,
+                            None => enum_helper(
+                                config,
+                                "try_get_opt",
+                                &format!(
+                                    "self.{}, {}::from_i32",
+                                    result.name,
+                                    type_in_expr_context(t)
+                                ),
+                                try_get,
+                            ),
+                        });
+                    }
+                }
+                result.get = Some(match &**fk {
+                    // The shared helpers use the enum's default.
+                    FieldKind::Enumeration(t) if raw_enum && default.is_some() => format!(
+                        "self.{}.and_then({}::from_i32).unwrap_or({})",
+                        result.name,
+                        type_in_expr_context(t),
+                        enum_default(t),
+                    ),
+                    FieldKind::Enumeration(t) if raw_enum => enum_helper(
+                        config,
+                        "get_opt",
+                        &format!(
+                            "self.{}, {}::from_i32",
+                            result.name,
+                            type_in_expr_context(t)
+                        ),
+                        format!(
+                            "self.{}.and_then({}::from_i32).unwrap_or_default()",
+                            result.name,
+                            type_in_expr_context(t),
+                        ),
+                    ),
+                    FieldKind::Enumeration(_) => {
+                        format!("self.{}.unwrap_or_default()", result.name)
+                    }
+                    // The default message is only computed if needed.
+                    FieldKind::Message => format!(
+                        "self.{}{}.unwrap_or_else(|| {})",
+                        result.name, as_ref, init_val,
+                    ),
+                    _ => format!("self.{}{}.unwrap_or({})", result.name, as_ref, init_val),
+                });
+            }
+            // A message stored directly, rather than in an `Option`, e.g., by
+            // a customized Prost.
+            FieldKind::Message => {
+                // Messages don't have an inherent `clear`.
+                result.clear = Some("::std::default::Default::default()".to_owned());
+                result.into_set = config.has_into_setters();
+                result.mt = MethodKind::Standard;
+                result.take = Some(format!("::core::mem::take(&mut self.{})", result.name));
+                let unboxed_type = unwrap_type(ty, "Box");
+                if ty != unboxed_type {
+                    result.ref_ty = RefType::Deref(unboxed_type.into_token_stream().to_string());
+                }
+            }
+            FieldKind::Int => result.scalar_methods(ty, "0", "{} != 0"),
+            FieldKind::Float => result.scalar_methods(ty, "0.", "{} != 0."),
+            FieldKind::Bool => result.scalar_methods(ty, "false", "{}"),
+            FieldKind::Repeated { prefix, element } => {
+                result.mt = MethodKind::Standard;
+                result.take = Some(format!("::core::mem::take(&mut self.{})", result.name));
+                result.clone = Some(format!("self.{}.clone()", result.name));
+                result.reserve = unwrap_type(ty, "Vec") != ty;
+                let mut container = ty.clone();
+                let element_ty =
+                    element_type(&mut container).ok_or_else(|| unsupported_field(ident))?;
+                let qualified = match element.as_deref() {
+                    Some(FieldKind::Message) => qualify_type(element_ty, prefix),
+                    // Scalars, strings, and bytes are std types and should never be prefixed.
+                    _ => element_ty.to_token_stream().to_string(),
+                };
+                *element_ty = syn::parse_str(&qualified).expect("Invalid element type");
+                let element_ty = qualified;
+
+                if config.is_deref_repeated() {
+                    result.ref_ty = RefType::Deref(format!("[{}]", element_ty));
+                }
+                result.override_ty = Some(if unwrap_type(ty, "Vec") != ty {
+                    format!("::std::vec::Vec<{}>", element_ty)
+                } else {
+                    // Some other container, which must `Deref` to a slice.
+                    container.into_token_stream().to_string()
+                });
+                let enum_get = match element.as_deref() {
+                    Some(FieldKind::Enumeration(enum_type)) if element_ty == "i32" => {
+                        let from_i32 = format!("{}::from_i32", type_in_expr_context(enum_type));
+                        Some((
+                            enum_type.clone(),
+                            enum_helper(
+                                config,
+                                "get",
+                                &format!("v, {}", from_i32),
+                                format!("{}(v).unwrap_or_default()", from_i32),
+                            ),
+                        ))
+                    }
+                    _ => None,
+                };
+                result.element = element.as_ref().map(|kind| ElementMethods {
+                    kind: (**kind).clone(),
+                    ty: element_ty,
+                    panics: config.panics_on_bad_index(),
+                    enum_get,
+                });
+            }
+            FieldKind::Bytes => {
+                if derefs_bytes(config, ty) {
+                    result.ref_ty = RefType::Deref("[u8]".to_owned());
+                }
+                if is_boxed(ty) {
+                    result.ref_ty = RefType::Deref("[u8]".to_owned());
+                    result.set_ty = Some("::std::vec::Vec<u8>".to_owned());
+                    result.set = Some("v.into_boxed_slice()".to_owned());
+                    result.clear = Some("::std::default::Default::default()".to_owned());
+                }
+                result.into_set = config.has_into_setters();
+                result.mt = MethodKind::Standard;
+                result.scalar_has = Some(format!("!self.{}.is_empty()", result.name));
+                result.take = Some(format!("::core::mem::take(&mut self.{})", result.name));
+                result.reserve = unwrap_type(ty, "Vec") != ty;
+            }
+            FieldKind::String => {
+                result.ref_ty = RefType::Deref("str".to_owned());
+                if config.has_cow_string_getters() {
+                    result.get = Some(format!(
+                        "::std::borrow::Cow::Borrowed(&*self.{})",
+                        result.name
+                    ));
+                    result.get_ty = Some("::std::borrow::Cow<'_, str>".to_owned());
+                }
+                if is_boxed(ty) {
+                    result.set_ty = Some("::std::string::String".to_owned());
+                    result.set = Some("v.into_boxed_str()".to_owned());
+                    result.clear = Some("::std::default::Default::default()".to_owned());
+                }
+                result.into_set = config.has_into_setters();
+                result.as_ref_set = config.has_as_ref_str_setters();
+                result.mt = MethodKind::Standard;
+                result.scalar_has = Some(format!("!self.{}.is_empty()", result.name));
+                result.take = Some(format!("::core::mem::take(&mut self.{})", result.name));
+                result.reserve = !is_boxed(ty);
+            }
+            // Prost stores enums as `i32`, but in case it doesn't, no conversion
+            // is needed.
+            FieldKind::Enumeration(_) if !is_i32(ty) => {
+                result.ref_ty = RefType::Copy;
+                result.clear = Some(format!("{}::default()", type_in_expr_context(&result.ty)));
+                result.scalar_has = Some(format!(
+                    "self.{} != {}::default()",
+                    result.name,
+                    type_in_expr_context(&result.ty)
+                ));
+            }
+            FieldKind::Enumeration(enum_type) => {
+                result.override_ty = Some(enum_type.clone());
+                result.ref_ty = RefType::Copy;
+                let default_value = enum_default(enum_type);
+                result.clear = Some(enum_helper(
+                    config,
+                    "set",
+                    &default_value,
+                    format!("{} as i32", default_value),
+                ));
+                result.set = Some(enum_helper(config, "set", "v", "v as i32".to_owned()));
+                result.raw_set = Some("v".to_owned());
+                result.enum_set = true;
+                result.scalar_has = Some(format!("self.{} != 0", result.name));
+                let from_i32 = format!("{}::from_i32", type_in_expr_context(enum_type));
+                result.get = Some(match default {
+                    // The shared helpers use the enum's default.
+                    Some(_) => format!(
+                        "{}(self.{}).unwrap_or({})",
+                        from_i32, result.name, default_value
+                    ),
+                    None => enum_helper(
+                        config,
+                        "get",
+                        &format!("self.{}, {}", result.name, from_i32),
+                        format!("{}(self.{}).unwrap_or_default()", from_i32, result.name),
+                    ),
+                });
+                result.try_get = Some(enum_helper(
+                    config,
+                    "try_get",
+                    &format!("self.{}, {}", result.name, from_i32),
+                    format!("{}(self.{1}).ok_or(self.{1})", from_i32, result.name),
+                ));
+            }
+            FieldKind::Map { prefix, value } => {
+                result.mt = MethodKind::Standard;
+                // Works for both `HashMap`s and `BTreeMap`s.
+                result.take = Some(format!("::core::mem::take(&mut self.{})", result.name));
+                result.clone = Some(format!("self.{}.clone()", result.name));
+                if let (Some(FieldKind::Message), Type::Path(p)) = (value.as_deref(), ty) {
+                    let args = match &p.path.segments.iter().last().unwrap().arguments {
+                        PathArguments::AngleBracketed(args) => args
+                            .args
+                            .iter()
+                            .filter_map(|arg| match arg {
+                                GenericArgument::Type(ty) => Some(ty),
+                                _ => None,
+                            })
+                            .collect(),
+                        _ => Vec::new(),
+                    };
+                    if let [key_ty, value_ty] = &*args {
+                        let message = config_path(&qualify_type(value_ty, module));
+                        let value_ty = qualify_type(value_ty, prefix);
+                        let default = if config.has_constructors(&message) {
+                            format!("{}::default_ref()", type_in_expr_context(&value_ty))
+                        } else {
+                            static_default(&value_ty)
+                        };
+                        result.entry = Some(EntryMethods {
+                            key_ty: key_ty.into_token_stream().to_string(),
+                            string_key: matches!(key_ty, Type::Path(p)
+                                if p.path.segments.last().is_some_and(|s| s.ident == "String")),
+                            value_ty,
+                            default,
+                        });
+                    }
+                }
+            }
+            // Plain accessors, the type may be anything with a `Default`.
+            FieldKind::Ignored => {
+                result.mt = MethodKind::Standard;
+                result.clear = Some("::std::default::Default::default()".to_owned());
+                result.take = Some(format!("::core::mem::take(&mut self.{})", result.name));
+            }
+            // There's only a few `oneof`s and they are a bit complex, so easier to
+            // handle manually.
+            FieldKind::OneOf(_) => return Ok(None),
+        }
+
+        Ok(Some(result))
+    }
+}
+
+// The path to `ty` from the wrapper code, where `ty` is written in the module
+// `prefix`.
+//
+// The point of the prefix is to account for nesting of modules. However, `ty`
+// may start with `super` so if we just smoosh the two together we get an
+// invalid type. So we pop a segment of the prefix for every leading `super`,
+// while there are both `super`s and segments of the prefix.
+fn qualify_type(ty: &Type, prefix: &str) -> String {
+    if let Type::Path(p) = ty {
+        if p.path.leading_colon.is_some() {
+            return ty.into_token_stream().to_string();
+        }
+    }
+    let mut ty = ty.clone();
+    let mut segments: Vec<_> = prefix.split("::").filter(|s| !s.is_empty()).collect();
+    if let Type::Path(p) = &mut ty {
+        while !segments.is_empty() && p.path.segments.first().is_some_and(|s| s.ident == "super") {
+            segments.pop();
+            p.path.segments = p.path.segments.iter().skip(1).cloned().collect();
+        }
+    }
+    let mut prefix = segments.join("::");
+    if !prefix.is_empty() {
+        prefix += "::"
+    }
+    format!("{}{}", prefix, ty.into_token_stream())
+}
+
+// Like `qualify_type`, but for a path written as a string, e.g., the enum of
+// an `enumeration` attribute. Absolute paths, e.g., from Prost's
+// `extern_path`, aren't relative to the module.
+fn qualify_path(path: &str, prefix: &str) -> String {
+    if path.starts_with("::") {
+        return path.to_owned();
+    }
+    let mut segments: Vec<_> = prefix.split("::").filter(|s| !s.is_empty()).collect();
+    let mut path = path.strip_prefix("self::").unwrap_or(path);
+    while !segments.is_empty() {
+        match path.strip_prefix("super::") {
+            Some(rest) => {
+                segments.pop();
+                path = rest;
+            }
+            None => break,
+        }
+    }
+    segments.push(path);
+    segments.join("::")
+}
+
+// Converts the name of a Prost oneof variant back to the name of its field.
+fn to_snake_case(name: &str) -> String {
+    let mut result = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if i > 0 {
+                result.push('_');
+            }
+            result.extend(c.to_lowercase());
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+// The type wrapped by `ty`, if it is `type_ctor<T>`, otherwise `ty` itself.
+fn unwrap_type<'a>(ty: &'a Type, type_ctor: &str) -> &'a Type {
+    if let Type::Path(p) = ty {
+        let seg = p.path.segments.iter().last().unwrap();
+        if seg.ident == type_ctor {
+            if let PathArguments::AngleBracketed(args) = &seg.arguments {
+                if let Some(GenericArgument::Type(ty)) = args.args.first() {
+                    return ty;
+                }
+            }
+        }
+    }
+    ty
+}
+
+// `ty`, the type of a field of kind `kind`, with the type of a message qualified
+// by `prefix`, e.g., `Option<outer::Inner>` rather than `Option<Inner>` for a
+// message field of a message in the module `outer`. `None` for other fields,
+// and if there is no prefix.
+fn qualify_message_field(ty: &Type, kind: &FieldKind, prefix: &str) -> Option<Type> {
+    let is_message = match kind {
+        FieldKind::Message => true,
+        FieldKind::Optional(fk) => **fk == FieldKind::Message,
+        _ => false,
+    };
+    if !is_message || prefix.is_empty() {
+        return None;
+    }
+    let mut ty = ty.clone();
+    let mut message = &mut ty;
+    for type_ctor in ["Option", "Box"] {
+        if unwrap_type(message, type_ctor) != message {
+            message = element_type(message).unwrap();
+        }
+    }
+    *message = syn::parse_str(&qualify_type(message, prefix)).expect("Invalid message type");
+    Some(ty)
+}
+
+// Whether `ty` is `Box<T>`, however the box is qualified, e.g.,
+// `::prost::alloc::boxed::Box<T>` in `no_std` code.
+fn is_boxed(ty: &Type) -> bool {
+    unwrap_type(ty, "Box") != ty
+}
+
+// Whether getters for a bytes field of type `ty` return `&[u8]`, see
+// `WrapperConfig::deref_bytes` and `WrapperConfig::bytes_handles`.
+fn derefs_bytes(config: &WrapperConfig, ty: &Type) -> bool {
+    let is_bytes = matches!(ty, Type::Path(p)
+        if p.path.segments.last().is_some_and(|s| s.ident == "Bytes"));
+    config.is_deref_bytes() && !(is_bytes && config.has_bytes_handles())
+}
+
+// For a field whose type doesn't match its Prost attributes.
+fn unsupported_field(ident: &Ident) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("Field `{}` has an unsupported type", ident),
+    )
+}
+
+// The type of the elements of a repeated field of type `ty`, e.g., `T` for
+// `Vec<T>` or `SmallVec<[T; 4]>`. `None` if `ty` isn't generic.
+fn element_type(ty: &mut Type) -> Option<&mut Type> {
+    let element = match ty {
+        Type::Path(p) => match &mut p.path.segments.iter_mut().last().unwrap().arguments {
+            PathArguments::AngleBracketed(args) => args.args.iter_mut().find_map(|arg| match arg {
+                GenericArgument::Type(ty) => Some(ty),
+                _ => None,
+            }),
+            _ => None,
+        },
+        _ => None,
+    }?;
+    match element {
+        Type::Array(array) => Some(&mut array.elem),
+        element => Some(element),
+    }
+}
+
+struct FieldMethods {
+    ty: String,
+    ref_ty: RefType,
+    override_ty: Option<String>,
+    name: Ident,
+    unesc_base: String,
+    // Some = the field is optional, and how its presence is stored.
+    presence: Option<Presence>,
+    // Some = expression which is true if a singular scalar field is not the
+    // default value.
+    scalar_has: Option<String>,
+    // None = delegate to field's `clear`
+    // Some = default value
+    clear: Option<String>,
+    // None = set to `v`
+    // Some = expression to set.
+    set: Option<String>,
+    // Some = the setter's argument type, if not the field's type, e.g., for a
+    // boxed field the setter boxes its argument.
+    set_ty: Option<String>,
+    // Some = expression to set an enum field from a raw `i32`, `v`.
+    raw_set: Option<String>,
+    enum_set: bool,
+    // If true, the setter takes an `impl Into<_>` rather than the field's type.
+    into_set: bool,
+    // If true, the setter of a string field takes an `impl AsRef<str>`, this
+    // takes precedence over `into_set`.
+    as_ref_set: bool,
+    // Some = custom getter expression.
+    get: Option<String>,
+    // Some = expression converting an enum field from a raw `i32`, which is
+    // a `Result` of the enum or the raw value if it isn't a known value.
+    try_get: Option<String>,
+    // Some = the getter's return type, if not derived from `ref_ty`.
+    get_ty: Option<String>,
+    mt: MethodKind,
+    // Some = expression which inserts `f()` into an unset optional message
+    // field and returns a mutable reference to its value, as for `mut_*`.
+    insert_with: Option<String>,
+    take: Option<String>,
+    // Some = expression which clones the field's value, as returned by `take_*`.
+    clone: Option<String>,
+    // If true, the field's type has a `reserve` method, i.e., it's a `Vec`
+    // or `String`.
+    reserve: bool,
+    // Some = the field is repeated.
+    element: Option<ElementMethods>,
+    // Some = the field is a map of messages.
+    entry: Option<EntryMethods>,
+    well_known: Option<WellKnownType>,
+}
+
+impl FieldMethods {
+    fn new(ty: &Type, ident: &Ident) -> FieldMethods {
+        let mut unesc_base = ident.to_string();
+        if unesc_base.starts_with("r#") {
+            unesc_base = unesc_base[2..].to_owned();
+        }
+        FieldMethods {
+            ty: ty.to_token_stream().to_string(),
+            ref_ty: RefType::Ref,
+            override_ty: None,
+            name: ident.clone(),
+            unesc_base,
+            presence: None,
+            scalar_has: None,
+            clear: None,
+            set: None,
+            set_ty: None,
+            raw_set: None,
+            enum_set: false,
+            into_set: false,
+            as_ref_set: false,
+            get: None,
+            try_get: None,
+            get_ty: None,
+            mt: MethodKind::None,
+            insert_with: None,
+            take: None,
+            clone: None,
+            reserve: false,
+            element: None,
+            entry: None,
+            well_known: None,
+        }
+    }
+
+    // Methods for a singular scalar field, with the default value `zero`.
+    // `is_set` formats an expression which is true if the field, its
+    // argument, isn't the default. A boxed field is read and written unboxed.
+    fn scalar_methods(&mut self, ty: &Type, zero: &str, is_set: &str) {
+        self.ref_ty = RefType::Copy;
+        let field = if is_boxed(ty) {
+            self.override_ty = Some(unwrap_type(ty, "Box").into_token_stream().to_string());
+            self.get = Some(format!("*self.{}", self.name));
+            self.set = Some("::std::boxed::Box::new(v)".to_owned());
+            self.clear = Some(format!("::std::boxed::Box::new({})", zero));
+            format!("*self.{}", self.name)
+        } else {
+            self.clear = Some(zero.to_owned());
+            format!("self.{}", self.name)
+        };
+        self.scalar_has = Some(is_set.replace("{}", &field));
+    }
+
+    fn write_methods<W>(&self, buf: &mut W, gen_opt: GenOpt, decl: &str) -> Result<(), io::Error>
+    where
+        W: Write,
+    {
+        // has_*
+        if let (Some(presence), true) = (self.presence, gen_opt.contains(GenOpt::HAS)) {
+            writeln!(
+                buf,
+                "#[inline] {decl}has_{}(&self) -> bool {{ {} }}",
+                self.unesc_base,
+                presence.is_set(&self.name)
+            )?;
+        } else if let Some(s) = &self.scalar_has {
+            if gen_opt.contains(GenOpt::SCALAR_HAS) {
+                writeln!(
+                    buf,
+                    "#[inline] {decl}has_{}(&self) -> bool {{ {} }}",
+                    self.unesc_base, s
+                )?;
+            }
+        }
+        let ty = self.override_ty.as_deref().unwrap_or(&self.ty);
+        let ref_ty = match &self.ref_ty {
+            RefType::Copy => ty.to_owned(),
+            RefType::Ref => format!("&{}", ty),
+            RefType::Deref(s) => format!("&{}", s),
+        };
+        // clear_*
+        if gen_opt.contains(GenOpt::CLEAR) {
+            match (self.presence, &self.clear) {
+                (Some(presence), _) => writeln!(
+                    buf,
+                    "#[inline] {decl}clear_{}(&mut self) {{ {} }}",
+                    self.unesc_base,
+                    presence.clear(&self.name)
+                )?,
+                (None, Some(s)) => writeln!(
+                    buf,
+                    "#[inline] {decl}clear_{}(&mut self) {{ self.{} = {} }}",
+                    self.unesc_base, self.name, s
+                )?,
+                (None, None) => writeln!(
+                    buf,
+                    "#[inline] {decl}clear_{}(&mut self) {{ self.{}.clear(); }}",
+                    self.unesc_base, self.name
+                )?,
+            }
+        }
+        // set_*
+        let set_ty = self.set_ty.as_deref().unwrap_or(ty);
+        let (set_ty, convert) = if self.as_ref_set {
+            (
+                "impl ::std::convert::AsRef<str>".to_owned(),
+                "let v = ::std::string::String::from(v.as_ref()); ",
+            )
+        } else if self.into_set {
+            (
+                format!("impl ::std::convert::Into<{}>", set_ty),
+                "let v = v.into(); ",
+            )
+        } else {
+            (set_ty.to_owned(), "")
+        };
+        match &self.set {
+            Some(s) if !self.enum_set => writeln!(
+                buf,
+                "#[inline] {decl}set_{}(&mut self, v: {}) {{ {}self.{} = {}; }}",
+                self.unesc_base, set_ty, convert, self.name, s
+            )?,
+            None if gen_opt.contains(GenOpt::TRIVIAL_SET) => writeln!(
+                buf,
+                "#[inline] {decl}set_{}(&mut self, v: {}) {{ {}self.{} = v; }}",
+                self.unesc_base, set_ty, convert, self.name
+            )?,
+            _ => {}
+        }
+        // set_*_i32
+        if let Some(s) = &self.raw_set {
+            if gen_opt.contains(GenOpt::TRIVIAL_SET) {
+                writeln!(
+                    buf,
+                    "#[inline] {decl}set_{}_i32(&mut self, v: i32) {{ self.{} = {}; }}",
+                    self.unesc_base, self.name, s
+                )?;
+            }
+        }
+        // get_*
+        match &self.get {
+            Some(s) => writeln!(
+                buf,
+                "#[inline] {decl}get_{}(&self) -> {} {{ {} }}",
+                self.unesc_base,
+                self.get_ty.as_deref().unwrap_or(&ref_ty),
+                s
+            )?,
+            None => {
+                if gen_opt.contains(GenOpt::TRIVIAL_GET) {
+                    let rf = match &self.ref_ty {
+                        RefType::Copy => "",
+                        _ => "&",
+                    };
+                    writeln!(
+                        buf,
+                        "#[inline] {decl}get_{}(&self) -> {} {{ {}self.{} }}",
+                        self.unesc_base, ref_ty, rf, self.name
+                    )?
+                }
+            }
+        }
+        // try_get_*
+        if let Some(s) = &self.try_get {
+            writeln!(
+                buf,
+                "#[inline] {decl}try_get_{}(&self) -> ::std::result::Result<{}, i32> {{ {} }}",
+                self.unesc_base, ty, s
+            )?;
+        }
+        // mut_*
+        if gen_opt.contains(GenOpt::MUT) {
+            match &self.mt {
+                MethodKind::Standard => {
+                    writeln!(
+                        buf,
+                        "#[inline] {decl}mut_{}(&mut self) -> &mut {} {{ &mut self.{} }}",
+                        self.unesc_base, ty, self.name
+                    )?;
+                }
+                MethodKind::Custom(s) => {
+                    writeln!(
+                        buf,
+                        "#[inline] {decl}mut_{}(&mut self) -> &mut {} {{ {} }} ",
+                        self.unesc_base, ty, s
+                    )?;
+                    // Only optional fields have custom `mut_`s, these are the
+                    // companions which don't insert a default. `get_*_mut_opt`
+                    // predates `mut_*_opt` and is kept for compatibility.
+                    writeln!(
+                        buf,
+                        "#[inline] {decl}mut_{}_opt(&mut self) -> ::std::option::Option<&mut {}> {{ self.{}.as_mut() }}",
+                        self.unesc_base, ty, self.name
+                    )?;
+                    writeln!(
+                        buf,
+                        "#[inline] {decl}get_{}_mut_opt(&mut self) -> ::std::option::Option<&mut {}> {{ self.{}.as_mut() }}",
+                        self.unesc_base, ty, self.name
+                    )?;
+                }
+                MethodKind::None => {}
+            }
+            if let Some(s) = &self.insert_with {
+                writeln!(
+                    buf,
+                    "#[inline] {decl}mut_{0}_or_insert_with(&mut self, f: impl ::std::ops::FnOnce() -> {1}) -> &mut {1} {{ {2} }}",
+                    self.unesc_base, ty, s
+                )?;
+            }
+            if self.reserve {
+                writeln!(
+                    buf,
+                    "#[inline] {decl}reserve_{}(&mut self, additional: usize) {{ self.{}.reserve(additional) }}",
+                    self.unesc_base, self.name
+                )?;
+            }
+        }
+
+        // take_*
+        if gen_opt.contains(GenOpt::TAKE) {
+            if let Some(s) = &self.take {
+                writeln!(
+                    buf,
+                    "#[inline] {decl}take_{}(&mut self) -> {} {{ {} }}",
+                    self.unesc_base, ty, s
+                )?;
+            }
+        }
+
+        // clone_*
+        if gen_opt.contains(GenOpt::CLONE) {
+            if let Some(s) = &self.clone {
+                writeln!(
+                    buf,
+                    "#[inline] {decl}clone_{}(&self) -> {} {{ {} }}",
+                    self.unesc_base, ty, s
+                )?;
+            }
+        }
+
+        // Element accessors for repeated fields.
+        if let Some(element) = &self.element {
+            element.write_enum_getter(decl, &self.unesc_base, &self.name, buf)?;
+            if gen_opt.contains(GenOpt::INDEXED) {
+                element.write_methods(decl, &self.unesc_base, &self.name, buf)?;
+            }
+            if gen_opt.contains(GenOpt::ITER) {
+                element.write_iter(decl, &self.unesc_base, &self.name, buf)?;
+            }
+        }
+
+        // Entry accessors for maps of messages.
+        if let Some(entry) = &self.entry {
+            if gen_opt.contains(GenOpt::INDEXED) {
+                entry.write_methods(decl, &self.unesc_base, &self.name, buf)?;
+            }
+        }
+
+        // Conversions for well-known types.
+        if let Some(wkt) = self.well_known {
+            if gen_opt.contains(GenOpt::TRIVIAL_GET) {
+                wkt.write_getters(decl, &self.unesc_base, &self.name, buf)?;
+            }
+            if gen_opt.contains(GenOpt::WELL_KNOWN_TYPES) {
+                wkt.write_methods(decl, &self.unesc_base, &self.name, buf)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Accessors for individual values of a map field whose values are messages.
+struct EntryMethods {
+    key_ty: String,
+    // Whether keys are `String`s, which are looked up by `&str`.
+    string_key: bool,
+    value_ty: String,
+    // An expression for a reference to the default value.
+    default: String,
+}
+
+impl EntryMethods {
+    fn write_methods<W>(
+        &self,
+        decl: &str,
+        base: &str,
+        name: &Ident,
+        buf: &mut W,
+    ) -> Result<(), io::Error>
+    where
+        W: Write,
+    {
+        let (key_ty, key) = if self.string_key {
+            ("&str", "key")
+        } else {
+            (&*self.key_ty, "&key")
+        };
+        writeln!(
+            buf,
+            "#[inline] {decl}get_{}_or_default(&self, key: {}) -> &{} {{ self.{}.get({}).unwrap_or_else(|| {}) }}",
+            base, key_ty, self.value_ty, name, key, self.default,
+        )?;
+        writeln!(
+            buf,
+            "#[inline] {decl}mut_{}_or_default(&mut self, key: {}) -> &mut {} {{ self.{}.entry(key).or_default() }}",
+            base, self.key_ty, self.value_ty, name,
+        )
+    }
+}
+
+// Accessors for individual elements of a repeated field.
+struct ElementMethods {
+    kind: FieldKind,
+    ty: String,
+    // If true, indexed accessors panic if the index is out of bounds, rather
+    // than returning an `Option`.
+    panics: bool,
+    // For elements which are enums stored as `i32`s, the enum's type and an
+    // expression which converts an element `v` to it.
+    enum_get: Option<(String, String)>,
+}
+
+impl ElementMethods {
+    // Converts every element to its enum, unknown values become the default.
+    // Prost generates `push_*`, which appends an enum.
+    fn write_enum_getter<W>(
+        &self,
+        decl: &str,
+        base: &str,
+        name: &Ident,
+        buf: &mut W,
+    ) -> Result<(), io::Error>
+    where
+        W: Write,
+    {
+        if let Some((enum_ty, get)) = &self.enum_get {
+            writeln!(
+                buf,
+                "#[inline] {decl}get_{}_enums(&self) -> ::std::vec::Vec<{}> {{ self.{}.iter().map(|&v| {}).collect() }}",
+                base, enum_ty, name, get
+            )?;
+        }
+        Ok(())
+    }
+
+    fn write_iter<W>(
+        &self,
+        decl: &str,
+        base: &str,
+        name: &Ident,
+        buf: &mut W,
+    ) -> Result<(), io::Error>
+    where
+        W: Write,
+    {
+        writeln!(
+            buf,
+            "#[inline] {decl}iter_{}(&self) -> impl ::std::iter::Iterator<Item = &{}> {{ self.{}.iter() }}",
+            base, self.ty, name
+        )?;
+        writeln!(
+            buf,
+            "#[inline] {decl}iter_{}_mut(&mut self) -> impl ::std::iter::Iterator<Item = &mut {}> {{ self.{}.iter_mut() }}",
+            base, self.ty, name
+        )
+    }
+
+    fn write_methods<W>(
+        &self,
+        decl: &str,
+        base: &str,
+        name: &Ident,
+        buf: &mut W,
+    ) -> Result<(), io::Error>
+    where
+        W: Write,
+    {
+        if let FieldKind::Message = self.kind {
+            if self.panics {
+                writeln!(
+                    buf,
+                    "#[track_caller]\n#[inline] {decl}get_{}_at(&self, index: usize) -> &{} {{ &self.{}[index] }}",
+                    base, self.ty, name
+                )?;
+                writeln!(
+                    buf,
+                    "#[track_caller]\n#[inline] {decl}mut_{}_at(&mut self, index: usize) -> &mut {} {{ &mut self.{}[index] }}",
+                    base, self.ty, name
+                )?;
+            } else {
+                writeln!(
+                    buf,
+                    "#[inline] {decl}get_{}_at(&self, index: usize) -> ::std::option::Option<&{}> {{ self.{}.get(index) }}",
+                    base, self.ty, name
+                )?;
+                writeln!(
+                    buf,
+                    "#[inline] {decl}mut_{}_at(&mut self, index: usize) -> ::std::option::Option<&mut {}> {{ self.{}.get_mut(index) }}",
+                    base, self.ty, name
+                )?;
+            }
+            writeln!(
+                buf,
+                "#[inline] {decl}add_{}(&mut self) -> &mut {} {{
+                    self.{2}.push(::std::default::Default::default());
+                    self.{2}.last_mut().unwrap()
+                }}",
+                base, self.ty, name
+            )?;
+        }
+        if let Some((enum_ty, get)) = &self.enum_get {
+            if self.panics {
+                writeln!(
+                    buf,
+                    "#[track_caller]\n#[inline] {decl}get_{}_at(&self, index: usize) -> {} {{ let v = self.{}[index]; {} }}",
+                    base, enum_ty, name, get
+                )?;
+            } else {
+                writeln!(
+                    buf,
+                    "#[inline] {decl}get_{}_at(&self, index: usize) -> ::std::option::Option<{}> {{ self.{}.get(index).map(|&v| {}) }}",
+                    base, enum_ty, name, get
+                )?;
+            }
+        }
+        if let FieldKind::Int | FieldKind::Float | FieldKind::Bool = self.kind {
+            if self.panics {
+                writeln!(
+                    buf,
+                    "#[track_caller]\n#[inline] {decl}get_{}_at(&self, index: usize) -> {} {{ self.{}[index] }}",
+                    base, self.ty, name
+                )?;
+            } else {
+                writeln!(
+                    buf,
+                    "#[inline] {decl}get_{}_at(&self, index: usize) -> ::std::option::Option<{}> {{ self.{}.get(index).copied() }}",
+                    base, self.ty, name
+                )?;
+            }
+        }
+        if let FieldKind::Bytes | FieldKind::String = self.kind {
+            // Elements are returned as `&str` or `&[u8]`.
+            let target = match self.kind {
+                FieldKind::String => "str",
+                _ => "[u8]",
+            };
+            if self.panics {
+                writeln!(
+                    buf,
+                    "#[track_caller]\n#[inline] {decl}get_{}_at(&self, index: usize) -> &{} {{ &self.{}[index] }}",
+                    base, target, name
+                )?;
+            } else {
+                writeln!(
+                    buf,
+                    "#[inline] {decl}get_{}_at(&self, index: usize) -> ::std::option::Option<&{}> {{ self.{}.get(index).map(|v| &**v) }}",
+                    base, target, name
+                )?;
+            }
+            writeln!(
+                buf,
+                "#[inline] {decl}push_{}(&mut self, v: impl ::std::convert::Into<{}>) {{ self.{}.push(v.into()); }}",
+                base, self.ty, name
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Well-known protobuf types which Prost maps to types from `prost_types`, and
+/// which have natural equivalents in `std::time`, or, for `Any`, which wrap
+/// another message.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum WellKnownType {
+    Timestamp,
+    Duration,
+    Any,
+}
+
+impl WellKnownType {
+    fn from_type(ty: &Type) -> Option<WellKnownType> {
+        let path = match ty {
+            Type::Path(p) => &p.path,
+            _ => return None,
+        };
+        let mut segments = path.segments.iter().rev();
+        let name = segments.next()?;
+        if segments.next()?.ident != "prost_types" {
+            return None;
+        }
+        if name.ident == "Timestamp" {
+            Some(WellKnownType::Timestamp)
+        } else if name.ident == "Duration" {
+            Some(WellKnownType::Duration)
+        } else if name.ident == "Any" {
+            Some(WellKnownType::Any)
+        } else {
+            None
+        }
+    }
+
+    // The components of timestamps and durations, without conversions, so
+    // callers don't need `prost_types` or `std`. Zero if the field isn't set.
+    fn write_getters<W>(
+        self,
+        decl: &str,
+        base: &str,
+        name: &Ident,
+        buf: &mut W,
+    ) -> Result<(), io::Error>
+    where
+        W: Write,
+    {
+        if self == WellKnownType::Any {
+            return Ok(());
+        }
+        writeln!(
+            buf,
+            "#[inline] {decl}get_{}_seconds(&self) -> i64 {{ self.{}.as_ref().map_or(0, |v| v.seconds) }}",
+            base, name,
+        )?;
+        writeln!(
+            buf,
+            "#[inline] {decl}get_{}_nanos(&self) -> i32 {{ self.{}.as_ref().map_or(0, |v| v.nanos) }}",
+            base, name,
+        )
+    }
+
+    // Uses the conversions provided by `prost_types`. Values which can't be
+    // represented in `std::time` (i.e., timestamps before the Unix epoch and
+    // negative durations) are returned as `None`.
+    fn write_methods<W>(
+        self,
+        decl: &str,
+        base: &str,
+        name: &Ident,
+        buf: &mut W,
+    ) -> Result<(), io::Error>
+    where
+        W: Write,
+    {
+        let (suffix, std_ty) = match self {
+            WellKnownType::Timestamp => ("system_time", "::std::time::SystemTime"),
+            WellKnownType::Duration => ("duration", "::std::time::Duration"),
+            WellKnownType::Any => return write_any_methods(decl, base, name, buf),
+        };
+        writeln!(
+            buf,
+            "#[inline] {decl}get_{0}_as_{1}(&self) -> ::std::option::Option<{2}> {{
+                self.{3}.clone().and_then(|v| ::std::convert::TryFrom::try_from(v).ok())
+            }}",
+            base, suffix, std_ty, name,
+        )?;
+        writeln!(
+            buf,
+            "#[inline] {decl}set_{0}_from_{1}(&mut self, v: {2}) {{ self.{3} = ::std::option::Option::Some(v.into()); }}",
+            base, suffix, std_ty, name,
+        )
+    }
+}
+
+// Prost doesn't know the names of messages, so the type URL (by convention,
+// `type.googleapis.com/` followed by the message's full name) is passed in.
+// Unpacking a message with a different type URL returns `None`.
+fn write_any_methods<W>(decl: &str, base: &str, name: &Ident, buf: &mut W) -> Result<(), io::Error>
+where
+    W: Write,
+{
+    writeln!(
+        buf,
+        "#[inline] {decl}pack_{}<T: ::prost::Message>(&mut self, type_url: impl ::std::convert::Into<::std::string::String>, msg: &T) {{
+            let mut value = ::std::vec::Vec::with_capacity(msg.encoded_len());
+            msg.encode(&mut value).expect(\"Vec has sufficient capacity\");
+            self.{} = ::std::option::Option::Some(::prost_types::Any {{ type_url: type_url.into(), value }});
+        }}",
+        base, name,
+    )?;
+    writeln!(
+        buf,
+        "#[inline] {decl}unpack_{}<T: ::prost::Message + ::std::default::Default>(&self, type_url: &str) -> ::std::option::Option<T> {{
+            match &self.{} {{
+                ::std::option::Option::Some(any) if any.type_url == type_url => T::decode(&*any.value).ok(),
+                _ => ::std::option::Option::None,
+            }}
+        }}",
+        base, name,
+    )
+}
+
+enum RefType {
+    Copy,
+    Ref,
+    Deref(String),
+}
+
+// How the presence of an optional field is stored. Prost stores each optional
+// field in an `Option`, `has_*` and `clear_*` are generated from this, so
+// another representation, e.g., a bitfield, only needs another variant.
+#[derive(Clone, Copy)]
+enum Presence {
+    Option,
+}
+
+impl Presence {
+    // An expression which is true if the field `name` is set.
+    fn is_set(self, name: &Ident) -> String {
+        match self {
+            Presence::Option => format!("self.{}.is_some()", name),
+        }
+    }
+
+    // An expression which unsets the field `name`.
+    fn clear(self, name: &Ident) -> String {
+        match self {
+            Presence::Option => format!("self.{} = ::std::option::Option::None", name),
+        }
+    }
+}
+
+enum MethodKind {
+    None,
+    Standard,
+    Custom(String),
+}
+
+fn is_message(attrs: &[Attribute], config: &WrapperConfig) -> bool {
+    derives_prost(attrs, "Message")
+        || derived_paths(attrs).any(|path| {
+            path.segments
+                .last()
+                .is_some_and(|s| config.is_message_derive(&s.ident.to_string()))
+        })
+}
+
+// Whether `item` is a message which can be wrapped. Wrappers are generated
+// outside the message's module (at the top level of the input, or in a module
+// of their own which is a child of it), so a message `depth` modules deep in
+// the input must be visible there.
+// `prefix` is the path of the module containing `item`, relative to the input.
+fn is_wrapped(item: &ItemStruct, config: &WrapperConfig, prefix: &str) -> bool {
+    is_message(&item.attrs, config)
+        && is_visible(&item.vis, prefix.matches("::").count())
+        && !is_marked(item, config)
+        && config.wraps_module(&config_path(prefix))
+}
+
+// Whether `item`'s documentation contains the marker of messages to skip, see
+// `WrapperConfig::skip_marker`.
+fn is_marked(item: &ItemStruct, config: &WrapperConfig) -> bool {
+    item.attrs
+        .iter()
+        .filter(|a| a.path.is_ident("doc"))
+        .any(|a| match a.parse_meta() {
+            Ok(Meta::NameValue(nv)) => match nv.lit {
+                Lit::Str(s) => config.is_skip_marked(&s.value()),
+                _ => false,
+            },
+            _ => false,
+        })
+}
+
+// The message named `name` (as in configuration) in `items`, if it is wrapped,
+// and the prefix of the module containing it.
+fn find_message<'a>(
+    items: &'a [Item],
+    name: &str,
+    prefix: &str,
+    config: &WrapperConfig,
+) -> Option<(&'a ItemStruct, String)> {
+    items.iter().find_map(|item| match item {
+        Item::Struct(item)
+            if config_path(&format!("{}{}", prefix, item.ident)) == name
+                && is_wrapped(item, config, prefix) =>
+        {
+            Some((item, prefix.to_owned()))
+        }
+        Item::Mod(m) => m.content.as_ref().and_then(|content| {
+            find_message(
+                &content.1,
+                name,
+                &format!("{}{}::", prefix, m.ident),
+                config,
+            )
+        }),
+        _ => None,
+    })
+}
+
+// Skips the constructors of marked messages in `items`, see `is_marked`, and
+// of messages outside the wrapped modules, see `WrapperConfig::wrap_module`.
+fn skip_marked_constructors(items: &[Item], prefix: &str, config: &mut InputConfig) {
+    for item in items {
+        if let Item::Struct(item) = item {
+            if is_message(&item.attrs, config)
+                && (is_marked(item, config) || !config.wraps_module(&config_path(prefix)))
+            {
+                config
+                    .skipped_constructors
+                    .insert(config_path(&format!("{}{}", prefix, item.ident)));
+            }
+        } else if let Item::Mod(m) = item {
+            if let Some(ref content) = m.content {
+                let prefix = format!("{}{}::", prefix, m.ident);
+                skip_marked_constructors(&content.1, &prefix, config);
+            }
+        }
+    }
+}
+
+// Records the fully-qualified protobuf names of the messages in `items`, see
+// `GenOpt::FULL_NAME`. `proto_prefix` is the protobuf name of the package or
+// message containing them, e.g., `foo.Bar.`. Prost generates the types nested
+// in a message in a module named after it, e.g., `bar` for `Bar`.
+fn collect_full_names(items: &[Item], prefix: &str, proto_prefix: &str, config: &mut InputConfig) {
+    for item in items {
+        if let Item::Struct(item) = item {
+            if is_message(&item.attrs, config) {
+                let name = item.ident.to_string();
+                config.full_names.insert(
+                    config_path(&format!("{}{}", prefix, name)),
+                    format!("{}{}", proto_prefix, name.trim_start_matches("r#")),
+                );
+            }
+        } else if let Item::Mod(m) = item {
+            if let Some(ref content) = m.content {
+                let module = m.ident.to_string();
+                let parent = items.iter().find_map(|item| match item {
+                    Item::Struct(s) if is_prost_name(&s.ident, &module) => Some(&s.ident),
+                    _ => None,
+                });
+                let proto_prefix = match parent {
+                    Some(parent) => {
+                        format!(
+                            "{}{}.",
+                            proto_prefix,
+                            parent.to_string().trim_start_matches("r#")
+                        )
+                    }
+                    None => format!("{}{}.", proto_prefix, module.trim_start_matches("r#")),
+                };
+                let prefix = format!("{}{}::", prefix, module);
+                collect_full_names(&content.1, &prefix, &proto_prefix, config);
+            }
+        }
+    }
+}
+
+// Documents and deprecates the fields of messages in `items` as in the
+// descriptor of the `.proto` file they were generated from, and records the
+// messages' full names, see `WrapperGen::descriptor_set`.
+fn apply_descriptor(items: &[Item], file: &FileDescriptorProto, config: &mut InputConfig) {
+    // Leading comments, by the path of the item they are attached to.
+    let comments: HashMap<&[i32], &str> = file
+        .source_code_info
+        .iter()
+        .flat_map(|info| &info.location)
+        .filter_map(|l| Some((&*l.path, l.leading_comments.as_deref()?)))
+        .collect();
+    // 4 is the number of `FileDescriptorProto::message_type`.
+    for (i, message) in file.message_type.iter().enumerate() {
+        let full_name = match file.package() {
+            "" => message.name().to_owned(),
+            package => format!("{}.{}", package, message.name()),
+        };
+        apply_message_descriptor(
+            items,
+            "",
+            message,
+            &full_name,
+            &[4, i as i32],
+            &comments,
+            config,
+        );
+    }
+}
+
+// `full_name` is the fully-qualified protobuf name of `message`, e.g.,
+// `foo.Bar.Baz`. `path` is the path of `message` in its file's descriptor, as
+// used for source locations.
+fn apply_message_descriptor(
+    items: &[Item],
+    prefix: &str,
+    message: &DescriptorProto,
+    full_name: &str,
+    path: &[i32],
+    comments: &HashMap<&[i32], &str>,
+    config: &mut InputConfig,
+) {
+    for item in items {
+        match item {
+            Item::Struct(item) if is_prost_name(&item.ident, message.name()) => {
+                let name = config_path(&format!("{}{}", prefix, item.ident));
+                config.full_names.insert(name.clone(), full_name.to_owned());
+                for f in &item.fields {
+                    let (ident, tag) =
+                        match (&f.ident, FieldAttrs::from_attrs(&f.attrs, prefix).tag) {
+                            (Some(ident), Some(tag)) => (ident, tag),
+                            _ => continue,
+                        };
+                    let field = ident.to_string();
+                    let field = field.trim_start_matches("r#");
+                    let (i, descriptor) = match message
+                        .field
+                        .iter()
+                        .enumerate()
+                        .find(|(_, d)| d.number() as u32 == tag)
+                    {
+                        Some(found) => found,
+                        None => continue,
+                    };
+                    // 2 is the number of `DescriptorProto::field`.
+                    let field_path = [path, &[2, i as i32]].concat();
+                    if let Some(comment) = comments.get(&*field_path) {
+                        config
+                            .field_docs
+                            .entry(name.clone())
+                            .or_default()
+                            .insert(field.to_owned(), comment.trim_end().to_owned());
+                    }
+                    if descriptor.options.as_ref().is_some_and(|o| o.deprecated()) {
+                        config
+                            .deprecated_fields
+                            .entry(name.clone())
+                            .or_default()
+                            .insert(field.to_owned());
+                    }
+                }
+            }
+            // Nested messages are in a module named after their parent.
+            Item::Mod(m) if is_prost_name(&m.ident, message.name()) => {
+                if let Some(ref content) = m.content {
+                    let prefix = format!("{}{}::", prefix, m.ident);
+                    for (i, nested) in message.nested_type.iter().enumerate() {
+                        // 3 is the number of `DescriptorProto::nested_type`.
+                        let path = [path, &[3, i as i32]].concat();
+                        let full_name = format!("{}.{}", full_name, nested.name());
+                        apply_message_descriptor(
+                            &content.1, &prefix, nested, &full_name, &path, comments, config,
+                        );
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+// Whether `ident` is an identifier Prost would generate for the message
+// `name`, i.e., its struct or the module of its nested types. Prost changes
+// the case of names, so they are compared ignoring case and underscores.
+fn is_prost_name(ident: &Ident, name: &str) -> bool {
+    let normalize = |s: &str| -> String {
+        s.trim_start_matches("r#")
+            .chars()
+            .filter(|c| *c != '_')
+            .flat_map(char::to_lowercase)
+            .collect()
+    };
+    normalize(&ident.to_string()) == normalize(name)
+}
+
+// Whether an item with visibility `vis`, `depth` modules deep in the input, is
+// visible at the top level of the input. `pub(in path)` is assumed to be
+// visible, unless `path` is `self`.
+fn is_visible(vis: &syn::Visibility, depth: usize) -> bool {
+    if depth == 0 {
+        return true;
+    }
+    match vis {
+        syn::Visibility::Public(_) | syn::Visibility::Crate(_) => true,
+        syn::Visibility::Restricted(r) if r.path.is_ident("self") => false,
+        syn::Visibility::Restricted(r) if r.path.is_ident("super") => depth == 1,
+        syn::Visibility::Restricted(_) => true,
+        syn::Visibility::Inherited => false,
+    }
+}
+
+// The enum at `path` in `items`, e.g., `foo::Bar`, whether or not it is a Prost
+// enumeration.
+fn find_enum<'a>(items: &'a [Item], path: &str) -> Option<&'a ItemEnum> {
+    match path.split_once("::") {
+        Some((module, rest)) => items.iter().find_map(|item| match item {
+            Item::Mod(m) if m.ident == module => m
+                .content
+                .as_ref()
+                .and_then(|content| find_enum(&content.1, rest)),
+            _ => None,
+        }),
+        None => items.iter().find_map(|item| match item {
+            Item::Enum(e) if e.ident == path => Some(e),
+            _ => None,
+        }),
+    }
+}
+
+fn is_enum(attrs: &[Attribute]) -> bool {
+    derives_prost(attrs, "Enumeration")
+}
+
+// Whether `attrs` derive the Prost macro `name`, however it is imported, e.g.,
+// `Message`, `prost::Message`, or `::prost_derive::Message`.
+fn derives_prost(attrs: &[Attribute], name: &str) -> bool {
+    derived_paths(attrs).any(|path| {
+        let mut segments = path.segments.iter().rev();
+        segments.next().is_some_and(|s| s.ident == name)
+            && segments.all(|s| s.ident.to_string().starts_with("prost"))
+    })
+}
+
+// The paths of all macros derived by `attrs`.
+fn derived_paths(attrs: &[Attribute]) -> impl Iterator<Item = syn::Path> + '_ {
+    attrs
+        .iter()
+        .filter(|a| a.path.is_ident("derive"))
+        .filter_map(|a| match a.parse_meta() {
+            Ok(Meta::List(list)) => Some(list.nested),
+            _ => None,
+        })
+        .flatten()
+        .filter_map(|item| match item {
+            NestedMeta::Meta(Meta::Path(path)) => Some(path),
+            _ => None,
+        })
+}
+
+// Whether `ty` is, or contains, a type from `prost_types`.
+fn is_prost_type(ty: &Type) -> bool {
+    ty.to_token_stream()
+        .into_iter()
+        .any(|t| matches!(t, proc_macro2::TokenTree::Ident(i) if i == "prost_types"))
+}
+
+// Whether a field has a `#[prost(...)]` attribute, rather than being added to
+// the message by hand.
+fn is_prost_field(field: &syn::Field) -> bool {
+    field.attrs.iter().any(|a| a.path.is_ident("prost"))
+}
+
+fn is_i32(ty: &Type) -> bool {
+    matches!(ty, Type::Path(p) if p.qself.is_none() && p.path.is_ident("i32"))
+}
+
+// When a generic type is used in expression context, it might need to be adjusted.
+// For example, `Box<Foo>` becomes `Box::<Foo>`
+fn type_in_expr_context(s: &str) -> String {
+    let mut parsed: TypePath = syn::parse_str(s).expect("Not a type?");
+    let last_segment = parsed.path.segments.last_mut().unwrap();
+    if !last_segment.arguments.is_empty() {
+        if let PathArguments::AngleBracketed(ref mut a) = last_segment.arguments {
+            if a.colon2_token.is_none() {
+                a.colon2_token = Some(Token![::](Span::call_site()));
+            }
+        }
+    }
+    parsed.to_token_stream().to_string()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    fn wrapper_gen(input: &str, gen_opt: GenOpt) -> WrapperGen {
+        WrapperGen::from_source(
+            PathBuf::from("test.rs"),
+            input.to_owned(),
+            gen_opt,
+            WrapperConfig::default(),
+        )
+    }
+
+    fn generate_str(input: &str, gen_opt: GenOpt) -> String {
+        let gen = wrapper_gen(input, gen_opt);
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn test_clashing_helpers() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(uint64, repeated, tag = "1")]
+                pub items: ::std::vec::Vec<u64>,
+                #[prost(uint64, tag = "2")]
+                pub items_at: u64,
+                #[prost(bytes, optional, tag = "3")]
+                pub data: ::std::option::Option<::std::vec::Vec<u8>>,
+                #[prost(string, tag = "4")]
+                pub data_opt: ::std::string::String,
+            }
+        "#;
+        let output = generate_str(input, GenOpt::all());
+        let file = syn::parse_file(&output).unwrap();
+        let mut names = HashSet::new();
+        for item in &file.items {
+            if let Item::Impl(item) = item {
+                if item.trait_.is_none() {
+                    for item in &item.items {
+                        if let syn::ImplItem::Method(m) = item {
+                            assert!(names.insert(m.sig.ident.to_string()), "{}", m.sig.ident);
+                        }
+                    }
+                }
+            }
+        }
+        assert!(output.contains("pub fn get_items_at(&self) -> u64 { self.items_at }"));
+        assert!(
+            output.contains("pub fn mut_data_opt(&mut self) -> &mut :: std :: string :: String { &mut self.data_opt }")
+        );
+        assert!(output.contains("pub fn get_data_mut_opt(&mut self)"));
+
+        let mut gen = wrapper_gen(input, GenOpt::all());
+        gen.config.method_prefix("pb_");
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("pub fn pb_get_items_at(&self) -> u64 { self.items_at }"));
+        assert_eq!(output.matches("fn pb_get_items_at(").count(), 1);
+
+        // `clear_all_oneofs` is dropped in favour of the field's `clear_`.
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(uint64, tag = "1")]
+                pub all_oneofs: u64,
+                #[prost(oneof = "foo::Value", tags = "2")]
+                pub value: ::std::option::Option<foo::Value>,
+                #[prost(oneof = "foo::Key", tags = "3")]
+                pub key: ::std::option::Option<foo::Key>,
+            }
+        "#;
+        let output = generate_str(input, GenOpt::default());
+        syn::parse_file(&output).unwrap();
+        assert_eq!(output.matches("fn clear_all_oneofs(").count(), 1);
+        assert!(output.contains("pub fn clear_all_oneofs(&mut self) { self.all_oneofs = 0 }"));
+
+        // Unindented lines which don't declare an item belong to the method
+        // before them.
+        let code = "#[inline] pub fn foo(&self) {\n}\n#[track_caller]\npub fn bar(&self) {}\n";
+        assert_eq!(
+            "#[track_caller]\npub fn bar(&self) {}\n",
+            filter_methods(code, |m| m == "foo")
+        );
+    }
+
+    #[test]
+    fn test_optional_bytes() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(bytes, optional, tag = "1")]
+                pub data: ::std::option::Option<std::vec::Vec<u8>>,
+            }
+        "#;
+        let output = generate_str(input, GenOpt::default());
+        assert!(output.contains("pub fn has_data(&self) -> bool { self.data.is_some() }"));
+        assert!(output
+            .contains("pub fn get_data(&self) -> &[u8] { self.data.as_deref().unwrap_or(&[]) }"));
+        assert!(output.contains(
+            "pub fn set_data(&mut self, v: std :: vec :: Vec < u8 >) { self.data = ::std::option::Option::Some(v); }"
+        ));
+        assert!(output.contains(
+            "pub fn take_data(&mut self) -> std :: vec :: Vec < u8 > { self.data.take().unwrap_or_default() }"
+        ));
+        assert!(output
+            .contains("pub fn clear_data(&mut self) { self.data = ::std::option::Option::None }"));
+        assert!(output.contains(
+            "pub fn mut_data_opt(&mut self) -> ::std::option::Option<&mut std :: vec :: Vec < u8 >> { self.data.as_mut() }"
+        ));
+        assert!(output.contains(
+            "pub fn get_data_mut_opt(&mut self) -> ::std::option::Option<&mut std :: vec :: Vec < u8 >> { self.data.as_mut() }"
+        ));
+    }
+
+    #[test]
+    fn test_optional_scalars() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(string, optional, tag = "1")]
+                pub name: ::std::option::Option<std::string::String>,
+                #[prost(uint64, optional, tag = "2")]
+                pub size: ::std::option::Option<u64>,
+                #[prost(message, optional, tag = "3")]
+                pub bar: ::std::option::Option<Bar>,
+            }
+        "#;
+        let output = generate_str(input, GenOpt::default());
+        assert!(output
+            .contains("pub fn get_name(&self) -> &str { self.name.as_deref().unwrap_or(\"\") }"));
+        assert!(output.contains("pub fn get_size(&self) -> u64 { self.size.unwrap_or(0) }"));
+        assert!(output.contains(
+            "pub fn get_bar(&self) -> &Bar { self.bar.as_ref().unwrap_or_else(|| Bar::default_ref()) }"
+        ));
+    }
+
+    #[test]
+    fn test_direct_message() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(message, tag = "1")]
+                pub bar: Bar,
+            }
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Bar {}
+        "#;
+        let output = generate_str(input, GenOpt::default());
+        assert!(output.contains("pub fn get_bar(&self) -> &Bar { &self.bar }"));
+        assert!(output.contains("pub fn set_bar(&mut self, v: Bar) { self.bar = v; }"));
+        assert!(output.contains("pub fn mut_bar(&mut self) -> &mut Bar { &mut self.bar }"));
+        assert!(output.contains(
+            "pub fn clear_bar(&mut self) { self.bar = ::std::default::Default::default() }"
+        ));
+        assert!(output
+            .contains("pub fn take_bar(&mut self) -> Bar { ::core::mem::take(&mut self.bar) }"));
+        assert!(!output.contains("has_bar"));
+    }
+
+    #[test]
+    fn test_bytes_handles() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(bytes, tag = "1")]
+                pub data: ::prost::bytes::Bytes,
+                #[prost(bytes, optional, tag = "2")]
+                pub extra: ::std::option::Option<::prost::bytes::Bytes>,
+                #[prost(bytes, tag = "3")]
+                pub raw: ::std::vec::Vec<u8>,
+            }
+        "#;
+        let output = generate_str(input, GenOpt::default());
+        assert!(output.contains("pub fn get_data(&self) -> &[u8] { &self.data }"));
+
+        let mut gen = wrapper_gen(input, GenOpt::default());
+        gen.config.bytes_handles(true);
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(
+            output.contains("pub fn get_data(&self) -> &:: prost :: bytes :: Bytes { &self.data }")
+        );
+        assert!(output.contains("pub fn get_extra(&self) -> &:: prost :: bytes :: Bytes {"));
+        assert!(output.contains("const EMPTY: &:: prost :: bytes :: Bytes = &"));
+        assert!(output.contains("pub fn get_raw(&self) -> &[u8] { &self.raw }"));
+    }
+
+    #[test]
+    fn test_reserve() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(uint64, repeated, tag = "1")]
+                pub ids: ::std::vec::Vec<u64>,
+                #[prost(string, tag = "2")]
+                pub name: ::std::string::String,
+                #[prost(bytes, tag = "3")]
+                pub data: ::std::vec::Vec<u8>,
+                #[prost(string, tag = "4")]
+                pub boxed: ::std::boxed::Box<str>,
+                #[prost(string, optional, tag = "5")]
+                pub label: ::std::option::Option<::std::string::String>,
+            }
+        "#;
+        let output = generate_str(input, GenOpt::MUT);
+        assert!(output.contains(
+            "pub fn reserve_ids(&mut self, additional: usize) { self.ids.reserve(additional) }"
+        ));
+        assert!(output.contains(
+            "pub fn reserve_name(&mut self, additional: usize) { self.name.reserve(additional) }"
+        ));
+        assert!(output.contains(
+            "pub fn reserve_data(&mut self, additional: usize) { self.data.reserve(additional) }"
+        ));
+        assert!(!output.contains("reserve_boxed") && !output.contains("reserve_label"));
+        assert!(!generate_str(input, GenOpt::TRIVIAL_GET).contains("reserve_"));
+    }
+
+    #[test]
+    fn test_optional_floats() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(double, optional, tag = "1")]
+                pub weight: ::std::option::Option<f64>,
+                #[prost(float, optional, tag = "2")]
+                pub ratio: ::std::option::Option<f32>,
+            }
+        "#;
+        let output = generate_str(input, GenOpt::default());
+        assert!(output.contains("pub fn has_weight(&self) -> bool { self.weight.is_some() }"));
+        assert!(output.contains("pub fn get_weight(&self) -> f64 { self.weight.unwrap_or(0.) }"));
+        assert!(output.contains(
+            "pub fn set_weight(&mut self, v: f64) { self.weight = ::std::option::Option::Some(v); }"
+        ));
+        assert!(output.contains(
+            "pub fn clear_weight(&mut self) { self.weight = ::std::option::Option::None }"
+        ));
+        assert!(output.contains(
+            "pub fn take_weight(&mut self) -> f64 { self.weight.take().unwrap_or_default() }"
+        ));
+        assert!(output.contains("pub fn get_ratio(&self) -> f32 { self.ratio.unwrap_or(0.) }"));
+        assert!(output.contains(
+            "pub fn take_ratio(&mut self) -> f32 { self.ratio.take().unwrap_or_default() }"
+        ));
+    }
+
+    #[test]
+    fn test_mem_sink() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(uint64, tag = "1")]
+                pub id: u64,
+            }
+        "#;
+        let mut config = WrapperConfig::new();
+        config.file_name(|name| format!("{}.wrapper", name));
+        let gen = WrapperGen::from_source(
+            PathBuf::from("out/foo.rs"),
+            input.to_owned(),
+            GenOpt::default(),
+            config,
+        );
+        let mut sink = MemSink::default();
+        gen.write_to(&mut sink).unwrap();
+        assert_eq!(1, sink.files.len());
+        let output =
+            String::from_utf8(sink.files[Path::new("out/foo.rs.wrapper")].clone()).unwrap();
+        assert!(output.starts_with("// Generated file, please don't edit manually."));
+        assert!(output.contains("pub fn get_id(&self) -> u64 { self.id }"));
+    }
+
+    #[test]
+    fn test_write_dir() {
+        let dir = std::env::temp_dir().join(format!("protobuf-build-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {}
+        "#;
+        fs::write(dir.join("foo.rs"), input).unwrap();
+        fs::write(dir.join("wrapper_bar.rs"), input).unwrap();
+        fs::write(dir.join("bar.rs"), input).unwrap();
+        fs::write(dir.join("mod.rs"), input).unwrap();
+        fs::write(dir.join("baz.txt"), input).unwrap();
+
+        let wrapped =
+            WrapperGen::write_dir(&dir, GenOpt::default(), &WrapperConfig::default()).unwrap();
+        assert_eq!(vec![dir.join("bar.rs"), dir.join("foo.rs")], wrapped);
+        let wrapper = fs::read_to_string(dir.join("wrapper_foo.rs")).unwrap();
+        assert!(wrapper.contains("impl Foo {"));
+        assert!(!dir.join("wrapper_wrapper_bar.rs").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_incremental() {
+        let dir = std::env::temp_dir().join(format!("protobuf-build-inc-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let input = dir.join("foo.rs");
+        let output = dir.join("wrapper_foo.rs");
+        fs::write(&input, "").unwrap();
+        fs::write(&output, "// stale").unwrap();
+        let set_modified = |path: &Path, secs| {
+            let time = std::time::SystemTime::UNIX_EPOCH + std::time::Duration::from_secs(secs);
+            File::options()
+                .write(true)
+                .open(path)
+                .unwrap()
+                .set_modified(time)
+                .unwrap();
+        };
+        set_modified(&input, 1_000);
+        set_modified(&output, 2_000);
+
+        let mut config = WrapperConfig::default();
+        config.incremental(true);
+        let gen = WrapperGen::new(input.clone(), GenOpt::default(), config.clone()).unwrap();
+        assert!(gen.write().unwrap().up_to_date);
+        assert_eq!("// stale", fs::read_to_string(&output).unwrap());
+
+        set_modified(&input, 3_000);
+        assert!(!gen.write().unwrap().up_to_date);
+        assert!(fs::read_to_string(&output).unwrap().starts_with(HEADER));
+
+        // Forced.
+        set_modified(&input, 1_000);
+        fs::write(&output, "// stale").unwrap();
+        config.incremental(false);
+        let gen = WrapperGen::new(input.clone(), GenOpt::default(), config.clone()).unwrap();
+        assert!(!gen.write().unwrap().up_to_date);
+        assert!(fs::read_to_string(&output).unwrap().starts_with(HEADER));
+
+        // Written elsewhere, the wrapper there is checked.
+        struct OutDir(PathBuf);
+        impl Sink for OutDir {
+            fn create(&mut self, path: &Path) -> Result<Box<dyn Write + '_>, io::Error> {
+                let file = File::create(self.0.join(path.file_name().unwrap()))?;
+                Ok(Box::new(file))
+            }
+            fn modified(&mut self, path: &Path) -> Result<Option<SystemTime>, io::Error> {
+                FileSink.modified(&self.0.join(path.file_name().unwrap()))
+            }
+        }
+        let out_dir = dir.join("out");
+        fs::create_dir_all(&out_dir).unwrap();
+        config.incremental(true);
+        let gen = WrapperGen::new(input, GenOpt::default(), config).unwrap();
+        assert!(
+            !gen.write_to(&mut OutDir(out_dir.clone()))
+                .unwrap()
+                .up_to_date
+        );
+        set_modified(&out_dir.join("wrapper_foo.rs"), 2_000);
+        assert!(gen.write_to(&mut OutDir(out_dir)).unwrap().up_to_date);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_generate_message() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(uint64, tag = "1")]
+                pub id: u64,
+            }
+            pub mod r#foo {
+                #[derive(Clone, PartialEq, ::prost::Message)]
+                pub struct Bar {
+                    #[prost(string, tag = "1")]
+                    pub name: ::std::string::String,
+                }
+            }
+        "#;
+        let gen = wrapper_gen(input, GenOpt::default());
+        let mut buf = Vec::new();
+        assert!(gen.generate_message("foo::Bar", &mut buf).unwrap());
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.starts_with("impl r#foo::Bar {"));
+        assert!(output.contains("pub fn get_name(&self) -> &str"));
+        assert!(!output.contains("impl Foo {") && !output.contains(HEADER));
+
+        let mut buf = Vec::new();
+        assert!(!gen.generate_message("Bar", &mut buf).unwrap());
+        assert!(!gen.generate_message("foo::Baz", &mut buf).unwrap());
+        assert!(buf.is_empty());
+    }
+
+    #[test]
+    fn test_from_ast() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(string, tag = "1")]
+                pub name: ::std::string::String,
+            }
+        "#;
+        let gen = WrapperGen::from_ast(
+            PathBuf::from("test.rs"),
+            syn::parse_file(input).unwrap(),
+            GenOpt::default(),
+            WrapperConfig::default(),
+        );
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        assert_eq!(
+            generate_str(input, GenOpt::default()),
+            String::from_utf8(buf).unwrap()
+        );
+        assert!(gen.validate().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_split_files() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {}
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Bar {}
+            #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+            #[repr(i32)]
+            pub enum Baz { A = 0 }
+        "#;
+        let mut config = WrapperConfig::default();
+        config.split_files(2).prelude(true);
+        let gen = WrapperGen::from_source(
+            PathBuf::from("out/foo.rs"),
+            input.to_owned(),
+            GenOpt::default(),
+            config.clone(),
+        );
+        let mut sink = MemSink::default();
+        let summary = gen.write_to(&mut sink).unwrap();
+        assert_eq!(2, summary.messages);
+        let file =
+            |name: &str| String::from_utf8(sink.files[&PathBuf::from(name)].clone()).unwrap();
+        let (wrapper, first, second) = (
+            file("out/wrapper_foo.rs"),
+            file("out/wrapper_foo_0.rs"),
+            file("out/wrapper_foo_1.rs"),
+        );
+        assert_eq!(3, sink.files.len());
+        assert!(wrapper.contains("use ::std as __std;"));
+        assert!(
+            wrapper.contains("include!(\"wrapper_foo_0.rs\");\ninclude!(\"wrapper_foo_1.rs\");")
+        );
+        assert!(first.starts_with(HEADER));
+        assert!(!first.contains("use ::std as __std;"));
+        assert!(first.contains("impl Foo {") && first.contains("impl Baz {"));
+        assert!(!first.contains("impl Bar {"));
+        assert!(second.contains("impl Bar {"));
+
+        config.module("wrappers");
+        let gen = WrapperGen::from_source(
+            PathBuf::from("out/foo.rs"),
+            input.to_owned(),
+            GenOpt::default(),
+            config,
+        );
+        assert!(gen.write_to(&mut MemSink::default()).is_err());
+    }
+
+    #[test]
+    fn test_splice_markers() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(uint64, tag = "1")]
+                pub id: u64,
+            }
+        "#;
+        let mut config = WrapperConfig::default();
+        config.splice_markers(true).overwrite_guard(true);
+        let gen = WrapperGen::from_source(
+            PathBuf::from("out/foo.rs"),
+            input.to_owned(),
+            GenOpt::TRIVIAL_GET,
+            config,
+        );
+        let path = PathBuf::from("out/wrapper_foo.rs");
+        let mut sink = MemSink::default();
+        assert!(gen.write_to(&mut sink).is_err());
+
+        let existing =
+            "use foo::*;\n// BEGIN generated\nold code\n// END generated\nfn main() {}\n";
+        sink.files
+            .insert(path.clone(), existing.as_bytes().to_vec());
+        let summary = gen.write_to(&mut sink).unwrap();
+        assert_eq!(1, summary.messages);
+        let output = String::from_utf8(sink.files[&path].clone()).unwrap();
+        assert!(output.starts_with(&format!("use foo::*;\n// BEGIN generated\n{}", HEADER)));
+        assert!(output.contains("pub fn get_id(&self) -> u64 { self.id }"));
+        assert!(output.ends_with("\n// END generated\nfn main() {}\n"));
+        assert!(!output.contains("old code"));
+
+        // Splicing again gives the same file.
+        gen.write_to(&mut sink).unwrap();
+        assert_eq!(output.as_bytes(), &*sink.files[&path]);
+
+        sink.files.insert(path, b"// BEGIN generated\n".to_vec());
+        assert!(gen.write_to(&mut sink).is_err());
+    }
+
+    #[test]
+    fn test_mod_file() {
+        let files = [
+            PathBuf::from("/out/foo.rs"),
+            PathBuf::from("/out/foo.bar-baz.rs"),
+            PathBuf::from("/elsewhere/qux.rs"),
+        ];
+        let output = mod_file(
+            &files,
+            GenOpt::default(),
+            &WrapperConfig::default(),
+            Some(Path::new("/out")),
+        )
+        .unwrap();
+        assert_eq!(
+            format!(
+                "{}\n
+pub mod foo {{
+include!(concat!(env!(\"OUT_DIR\"), \"/foo.rs\"));
+include!(concat!(env!(\"OUT_DIR\"), \"/wrapper_foo.rs\"));
+pub mod bar_baz {{
+include!(concat!(env!(\"OUT_DIR\"), \"/foo.bar-baz.rs\"));
+include!(concat!(env!(\"OUT_DIR\"), \"/wrapper_foo.bar-baz.rs\"));
+}}
+}}
+pub mod qux {{
+include!(\"/elsewhere/qux.rs\");
+include!(\"/elsewhere/wrapper_qux.rs\");
+}}
+",
+                HEADER
+            ),
+            output
+        );
+
+        let files = [PathBuf::from("foo.bar.rs")];
+        let output =
+            mod_file(&files, GenOpt::MESSAGE_EXT, &WrapperConfig::default(), None).unwrap();
+        assert!(output.contains("pub trait MessageExt: Sized {"));
+        assert!(output.contains(
+            "pub mod bar {\n#[allow(unused_imports)]\nuse super::super::MessageExt;\ninclude!(\"foo.bar.rs\");"
+        ));
+    }
+
+    #[test]
+    fn test_overwrite_guard() {
+        let mut gen = wrapper_gen("", GenOpt::default());
+        let mut sink = MemSink::default();
+        let path = PathBuf::from("wrapper_test.rs");
+        sink.files.insert(path.clone(), b"// hand-written".to_vec());
+        gen.write_to(&mut sink).unwrap();
+
+        sink.files.insert(path.clone(), b"// hand-written".to_vec());
+        gen.config.overwrite_guard(true);
+        let err = gen.write_to(&mut sink).unwrap_err();
+        assert_eq!(io::ErrorKind::AlreadyExists, err.kind());
+        assert_eq!(b"// hand-written", &*sink.files[&path]);
+
+        // Generated files and new files are written.
+        sink.files.remove(&path);
+        gen.write_to(&mut sink).unwrap();
+        gen.write_to(&mut sink).unwrap();
+        assert!(sink.files[&path].starts_with(HEADER.as_bytes()));
+    }
+
+    #[test]
+    fn test_output_file() {
+        let gen = |path: &str| {
+            WrapperGen::from_source(
+                PathBuf::from(path),
+                String::new(),
+                GenOpt::default(),
+                WrapperConfig::default(),
+            )
+        };
+        assert_eq!(
+            PathBuf::from("out/wrapper_foo.rs"),
+            gen("out/foo.rs").output_file().unwrap()
+        );
+        assert_eq!(
+            PathBuf::from("out/wrapper_ünïcödé.rs"),
+            gen("out/ünïcödé.rs").output_file().unwrap()
+        );
+        assert_eq!(
+            PathBuf::from("ü/wrapper_foo.rs"),
+            gen("ü/foo.rs").output_file().unwrap()
+        );
+        assert!(gen("").output_file().is_err());
+        assert!(gen("out/..").output_file().is_err());
+        assert!(gen("out/").output_file().is_err());
+        assert!(gen("out/foo.rs/").output_file().is_err());
+    }
+
+    #[test]
+    fn test_first_difference() {
+        assert_eq!(None, first_difference("a\nb\n", "a\nb\n"));
+        assert_eq!(
+            Some("2: expected `b`, found `c`".to_owned()),
+            first_difference("a\nb\n", "a\nc\n")
+        );
+        assert_eq!(
+            Some("2: expected `b`, found `<end of file>`".to_owned()),
+            first_difference("a\nb\n", "a\n")
+        );
+    }
+
+    #[test]
+    fn test_int_types() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(fixed32, tag = "1")]
+                pub hash: u32,
+                #[prost(sint64, tag = "2")]
+                pub offset: i64,
+                #[prost(sfixed32, optional, tag = "3")]
+                pub delta: ::std::option::Option<i32>,
+                #[prost(fixed64, repeated, tag = "4")]
+                pub marks: ::std::vec::Vec<u64>,
+            }
+        "#;
+        let output = generate_str(input, GenOpt::default());
+        assert!(output.contains("pub fn get_hash(&self) -> u32 { self.hash }"));
+        assert!(output.contains("pub fn set_offset(&mut self, v: i64) { self.offset = v; }"));
+        assert!(output.contains("pub fn get_delta(&self) -> i32 { self.delta.unwrap_or(0) }"));
+        assert!(output.contains("pub fn get_marks(&self) -> &[u64] { &self.marks }"));
+    }
+
+    #[test]
+    fn test_validate() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(uint64, tag = "1")]
+                pub id: u64,
+                #[prost(custom, tag = "2")]
+                pub hash: u32,
+            }
+        "#;
+        let warnings = wrapper_gen(input, GenOpt::default()).validate().unwrap();
+        let warnings: Vec<_> = warnings.iter().map(|w| w.to_string()).collect();
+        assert_eq!(vec!["Foo.hash: unsupported field type"], warnings);
+
+        assert!(wrapper_gen("pub struct {", GenOpt::default())
+            .validate()
+            .is_err());
+    }
+
+    #[test]
+    fn test_hand_added_fields() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(uint64, tag = "1")]
+                pub id: u64,
+                pub cache: ::std::option::Option<u64>,
+            }
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::default());
+        gen.config.strict(true);
+        let mut buf = Vec::new();
+        let summary = gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("pub fn get_id(&self) -> u64"));
+        assert!(!output.contains("self.cache"));
+        let skipped: Vec<_> = summary
+            .skipped_fields
+            .iter()
+            .map(|w| w.to_string())
+            .collect();
+        assert_eq!(vec!["Foo.cache: not a Prost field"], skipped);
+    }
+
+    #[test]
+    fn test_ignored_fields() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(uint64, tag = "1")]
+                pub id: u64,
+                #[prost(skip)]
+                pub cache: ::std::option::Option<u64>,
+            }
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::default() | GenOpt::MERGE_FROM);
+        let mut buf = Vec::new();
+        let summary = gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(summary.skipped_fields.is_empty());
+        assert!(output.contains(
+            "pub fn get_cache(&self) -> &:: std :: option :: Option < u64 > { &self.cache }"
+        ));
+        assert!(output.contains(
+            "pub fn set_cache(&mut self, v: :: std :: option :: Option < u64 >) { self.cache = v; }"
+        ));
+        assert!(output.contains(
+            "pub fn clear_cache(&mut self) { self.cache = ::std::default::Default::default() }"
+        ));
+        assert!(!output.contains("other.cache"));
+
+        gen.config.skip_ignored_fields(true);
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        assert!(!String::from_utf8(buf).unwrap().contains("self.cache"));
+    }
+
+    #[test]
+    fn test_extension_fields() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(uint32, tag = "1")]
+                pub id: u32,
+                #[prost(extension = "bar", optional, tag = "100")]
+                pub bar: ::std::option::Option<(u32, u32)>,
+            }
+        "#;
+        let err = wrapper_gen(input, GenOpt::default())
+            .generate(&mut Vec::new())
+            .unwrap_err();
+        assert_eq!(
+            "Field `bar` of message `Foo` has an unsupported type",
+            err.to_string()
+        );
+
+        let ty: Type = syn::parse_quote!((u32, u32));
+        assert!(std::ptr::eq(&ty, unwrap_type(&ty, "Option")));
+    }
+
+    #[test]
+    fn test_clear_message() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(message, required, tag = "1")]
+                pub bar: Bar,
+                #[prost(message, required, boxed, tag = "2")]
+                pub next: ::std::boxed::Box<Foo>,
+            }
+        "#;
+        let mut buf = Vec::new();
+        wrapper_gen(input, GenOpt::CLEAR)
+            .generate(&mut buf)
+            .unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains(
+            "pub fn clear_bar(&mut self) { self.bar = ::std::default::Default::default() }"
+        ));
+        assert!(output.contains(
+            "pub fn clear_next(&mut self) { self.next = ::std::default::Default::default() }"
+        ));
+    }
+
+    #[test]
+    fn test_required_scalars() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(int32, required, tag = "1")]
+                pub id: i32,
+                #[prost(int32, required, repeated, tag = "2")]
+                pub ids: ::std::vec::Vec<i32>,
+            }
+        "#;
+        let item: ItemStruct = syn::parse_str(input).unwrap();
+        let mut fields = item.fields.iter();
+        let attrs = FieldAttrs::from_attrs(&fields.next().unwrap().attrs, "");
+        assert!(attrs.required);
+        assert!(attrs.other.is_empty());
+        assert_eq!(Some(FieldKind::Int), attrs.kind());
+        let attrs = FieldAttrs::from_attrs(&fields.next().unwrap().attrs, "");
+        assert_eq!(None, attrs.kind());
+
+        let mut gen = wrapper_gen(input, GenOpt::default());
+        gen.config.skip_unknown_fields(true);
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("pub fn get_id(&self) -> i32 { self.id }"));
+        assert!(output.contains("pub fn set_id(&mut self, v: i32) { self.id = v; }"));
+        assert!(output.contains("pub fn clear_id(&mut self) { self.id = 0 }"));
+        assert!(output.contains("// Skipped field `ids`: unsupported field type."));
+    }
+
+    #[test]
+    fn test_skip_unknown_fields() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(custom, tag = "1")]
+                pub hash: u32,
+                #[prost(uint64, tag = "2")]
+                pub id: u64,
+            }
+        "#;
+        let err = wrapper_gen(input, GenOpt::default())
+            .generate(&mut Vec::new())
+            .unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+        assert_eq!(
+            "Field `hash` of message `Foo` has an unsupported type",
+            err.to_string()
+        );
+
+        let mut config = WrapperConfig::new();
+        config.skip_unknown_fields(true);
+        let gen = WrapperGen::from_source(
+            PathBuf::from("test.rs"),
+            input.to_owned(),
+            GenOpt::default() | GenOpt::FIELDS,
+            config,
+        );
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("// Skipped field `hash`: unsupported field type."));
+        assert!(!output.contains("fn get_hash("));
+        assert!(output.contains("fn get_id("));
+        assert!(!output.contains("(\"hash\""));
+    }
+
+    #[test]
+    fn test_labelled_maps() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(map = "string, string", optional, tag = "1")]
+                pub m: ::std::option::Option<::std::collections::HashMap<String, String>>,
+                #[prost(uint64, tag = "2")]
+                pub id: u64,
+            }
+        "#;
+        let err = wrapper_gen(input, GenOpt::default())
+            .generate(&mut Vec::new())
+            .unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+        assert_eq!(
+            "Field `m` of message `Foo` has an unsupported type",
+            err.to_string()
+        );
+
+        let mut gen = wrapper_gen(input, GenOpt::default());
+        gen.config.skip_unknown_fields(true);
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("// Skipped field `m`: unsupported field type."));
+        assert!(output.contains("fn get_id("));
+
+        // A type which doesn't match the field's attributes.
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(string, repeated, tag = "1")]
+                pub names: String,
+            }
+        "#;
+        let err = wrapper_gen(input, GenOpt::default())
+            .generate(&mut Vec::new())
+            .unwrap_err();
+        assert_eq!(
+            "Field `names` of message `Foo` has an unsupported type",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn test_summary() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(uint64, tag = "1")]
+                pub id: u64,
+                #[prost(custom, tag = "2")]
+                pub hash: u32,
+            }
+            pub mod foo {
+                #[derive(Clone, PartialEq, ::prost::Message)]
+                pub struct Bar {}
+            }
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::TRIVIAL_GET | GenOpt::TRIVIAL_SET);
+        gen.config.skip_unknown_fields(true);
+        let summary = gen.write_to(&mut MemSink::default()).unwrap();
+        assert_eq!(2, summary.messages);
+        // `get_id`, `set_id`, and a `default_ref` for each message.
+        assert_eq!(4, summary.methods);
+        let skipped: Vec<_> = summary
+            .skipped_fields
+            .iter()
+            .map(|w| w.to_string())
+            .collect();
+        assert_eq!(vec!["Foo.hash: unsupported field type"], skipped);
+    }
+
+    #[test]
+    fn test_summary_methods() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(uint64, tag = "1")]
+                pub id: u64,
+            }
+            #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+            #[repr(i32)]
+            pub enum Kind {
+                A = 0,
+            }
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::TRIVIAL_GET);
+        gen.config
+            .field_doc("Foo", "id", " Like `pub fn id()`.")
+            .field_methods(|_| {
+                Some(
+                    "/// Not `pub fn id_u32()`.\n\
+                     pub fn id_u32(&self) -> u32 { self.id as u32 }\n\
+                     fn id_is_zero(&self) -> bool { self.id == 0 }"
+                        .to_owned(),
+                )
+            });
+        let summary = gen.write_to(&mut MemSink::default()).unwrap();
+        // `get_id`, `default_ref`, and the two custom methods, but neither the
+        // docs nor the enum's `values`.
+        assert_eq!(4, summary.methods);
+    }
+
+    #[test]
+    fn test_pretty_print() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(uint64, tag = "1")]
+                pub id: u64,
+            }
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::TRIVIAL_GET);
+        gen.config.pretty_print(true);
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.starts_with(HEADER));
+        assert!(output.contains(
+            "    #[inline]
+    pub fn get_id(&self) -> u64 {
+        self.id
+    }
+"
+        ));
+    }
+
+    #[test]
+    fn test_post_process() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(uint64, tag = "1")]
+                pub id: u64,
+            }
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::TRIVIAL_GET);
+        gen.config
+            .post_process(|code| code.insert_str(0, "// Licensed under Apache-2.0.\n"));
+        let mut buf = Vec::new();
+        let summary = gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.starts_with(&format!("// Licensed under Apache-2.0.\n{}", HEADER)));
+        assert!(output.contains("pub fn get_id(&self) -> u64 { self.id }"));
+        assert_eq!(summary.methods, 2);
+    }
+
+    #[test]
+    fn test_log() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(uint64, tag = "1")]
+                pub id: u64,
+                #[prost(skip)]
+                pub cache: u64,
+                pub extra: u64,
+            }
+        "#;
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let mut gen = wrapper_gen(input, GenOpt::TRIVIAL_GET);
+        let log = events.clone();
+        gen.config.skip_ignored_fields(true).log(move |event| {
+            log.lock().unwrap().push(match event {
+                Event::Message(message) => format!("message {}", message),
+                Event::Field(info) => {
+                    format!("field {}.{}: {}", info.message, info.field, info.kind)
+                }
+                Event::SkippedField {
+                    message,
+                    field,
+                    reason,
+                } => format!("skipped {}.{}: {}", message, field, reason),
+            })
+        });
+        gen.generate(&mut Vec::new()).unwrap();
+        assert_eq!(
+            *events.lock().unwrap(),
+            [
+                "message Foo",
+                "field Foo.id: int",
+                "skipped Foo.cache: ignored field",
+                "skipped Foo.extra: not a Prost field",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_line_endings() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(uint64, tag = "1")]
+                pub id: u64,
+            }
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::TRIVIAL_GET);
+        gen.config
+            .post_process(|code| code.insert_str(0, "\u{feff}// Licensed under Apache-2.0.\r\n"));
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.starts_with(&format!("// Licensed under Apache-2.0.\n{}", HEADER)));
+        assert!(!output.contains('\r'));
+
+        gen.config.crlf_line_endings(true);
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.starts_with("// Licensed under Apache-2.0.\r\n"));
+        assert_eq!(output.matches('\n').count(), output.matches("\r\n").count());
+
+        // Without rewriting, the output is converted as it is streamed.
+        let mut gen = wrapper_gen(input, GenOpt::TRIVIAL_GET);
+        gen.config.crlf_line_endings(true);
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("pub fn get_id(&self) -> u64 { self.id }\r\n"));
+        assert_eq!(output.matches('\n').count(), output.matches("\r\n").count());
+    }
+
+    #[test]
+    fn test_check_hashable() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(uint64, tag = "1")]
+                pub id: u64,
+                #[prost(double, tag = "2")]
+                pub weight: f64,
+            }
+            pub mod foo {
+                #[derive(Clone, PartialEq, ::prost::Message)]
+                pub struct Bar {
+                    #[prost(float, optional, tag = "1")]
+                    pub x: ::std::option::Option<f32>,
+                    #[prost(float, repeated, tag = "2")]
+                    pub ys: ::std::vec::Vec<f32>,
+                }
+            }
+        "#;
+        let warnings = wrapper_gen(input, GenOpt::default())
+            .check_hashable()
+            .unwrap();
+        let warnings: Vec<_> = warnings.iter().map(|w| w.to_string()).collect();
+        assert_eq!(
+            vec![
+                "Foo.weight: floats don't implement `Hash` or `Eq`",
+                "foo::Bar.x: floats don't implement `Hash` or `Eq`",
+                "foo::Bar.ys: floats don't implement `Hash` or `Eq`",
+            ],
+            warnings
+        );
+    }
+
+    #[test]
+    fn test_message_names() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(uint64, tag = "1")]
+                pub id: u64,
+            }
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            struct Hidden {}
+            pub mod r#type {
+                #[derive(Clone, PartialEq, ::prost::Message)]
+                pub struct Bar {}
+                #[derive(Clone, PartialEq, ::prost::Message)]
+                struct Private {}
+                #[derive(Clone, PartialEq, Custom)]
+                pub struct Baz {}
+                pub struct Plain {}
+            }
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::default());
+        assert_eq!(
+            vec!["Foo", "Hidden", "type::Bar"],
+            gen.message_names().unwrap()
+        );
+        gen.config.message_derive("Custom");
+        assert_eq!(
+            vec!["Foo", "Hidden", "type::Bar", "type::Baz"],
+            gen.message_names().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_strict() {
+        let input = r#"
+            const _: () = {
+                #[derive(Clone, PartialEq, ::prost::Message)]
+                pub struct Foo {
+                    #[prost(uint64, tag = "1")]
+                    pub id: u64,
+                }
+            };
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::default());
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+
+        gen.config.strict(true);
+        let err = gen.generate(&mut Vec::new()).unwrap_err();
+        assert_eq!(
+            "test.rs:4:28: message `Foo` is not in a module and won't be wrapped",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn test_strict_enums() {
+        let input = r#"
+            pub mod foo {
+                #[derive(Clone, PartialEq, ::prost::Message)]
+                pub struct Bar {
+                    #[prost(enumeration = "super::Color", tag = "1")]
+                    pub color: i32,
+                    #[prost(enumeration = "super::common::Kind", repeated, tag = "2")]
+                    pub kinds: ::std::vec::Vec<i32>,
+                }
+            }
+            #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+            #[repr(i32)]
+            pub enum Color { Red = 0 }
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::default());
+        gen.generate(&mut Vec::new()).unwrap();
+
+        gen.config.strict(true);
+        let err = gen.generate(&mut Vec::new()).unwrap_err();
+        assert_eq!(
+            "test.rs:8:25: enum `super::common::Kind` of field `kinds` is not in the input \
+             (see `WrapperConfig::extern_enum`)",
+            err.to_string()
+        );
+        gen.config
+            .extern_enum("super::common::Kind", "::common::Kind");
+        gen.generate(&mut Vec::new()).unwrap();
+
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Bar {
+                #[prost(enumeration = "Color", tag = "1")]
+                pub color: i32,
+            }
+            pub enum Color { Red }
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::default());
+        gen.config.strict(true);
+        let err = gen.generate(&mut Vec::new()).unwrap_err();
+        assert_eq!(
+            "test.rs:5:21: enum `Color` of field `color` is not a Prost enumeration \
+             (see `WrapperConfig::extern_enum`)",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn test_unexpanded_input() {
+        let input = r#"
+            #[my_message]
+            pub struct Foo {
+                #[prost(uint64, tag = "1")]
+                pub id: u64,
+            }
+        "#;
+        let gen = wrapper_gen(input, GenOpt::default());
+        let err = gen.generate(&mut Vec::new()).unwrap_err();
+        assert!(err
+            .to_string()
+            .starts_with("test.rs: no Prost messages or enums found"));
+
+        // Nothing to wrap isn't an error.
+        let gen = wrapper_gen("pub const FOO: u32 = 0;", GenOpt::default());
+        gen.generate(&mut Vec::new()).unwrap();
+    }
+
+    #[test]
+    fn test_skip_marker() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(message, optional, tag = "1")]
+                pub bar: ::std::option::Option<foo::Bar>,
+            }
+            pub mod foo {
+                /// Hand-written accessors. @protobuf-build:skip
+                #[derive(Clone, PartialEq, ::prost::Message)]
+                pub struct Bar {
+                    #[prost(uint64, tag = "1")]
+                    pub id: u64,
+                }
+            }
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::default());
+        gen.config.skip_marker("@protobuf-build:skip");
+        let mut buf = Vec::new();
+        let summary = gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert_eq!(summary.messages, 1);
+        assert!(output.contains("impl Foo {"));
+        assert!(!output.contains("foo::Bar {"));
+        assert!(!output.contains("get_id"));
+        assert!(!output.contains("Bar::default_ref()"));
+        assert!(output.contains("static ref DEFAULT: foo :: Bar ="));
+    }
+
+    #[test]
+    fn test_wrap_module() {
+        let input = r#"
+            pub mod raft_cmdpb {
+                #[derive(Clone, PartialEq, ::prost::Message)]
+                pub struct Request {
+                    #[prost(message, optional, tag = "1")]
+                    pub header: ::std::option::Option<super::metapb::Header>,
+                }
+            }
+            pub mod metapb {
+                #[derive(Clone, PartialEq, ::prost::Message)]
+                pub struct Header {
+                    #[prost(uint64, tag = "1")]
+                    pub id: u64,
+                }
+                #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+                #[repr(i32)]
+                pub enum PeerRole {
+                    Voter = 0,
+                    Learner = 1,
+                }
+            }
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::default());
+        gen.config.wrap_module("raft_cmdpb::");
+        let mut buf = Vec::new();
+        let summary = gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert_eq!(summary.messages, 1);
+        assert!(output.contains("impl raft_cmdpb::Request {"));
+        assert!(!output.contains("impl metapb::Header {"));
+        assert!(!output.contains("Header::default_ref()"));
+        assert!(!output.contains("impl metapb::PeerRole {"));
+
+        gen.config.wrap_module("metapb");
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        assert!(String::from_utf8(buf)
+            .unwrap()
+            .contains("impl metapb::PeerRole {"));
+
+        // Modules are generated as such, enums in them are skipped too.
+        let mut gen = wrapper_gen(input, GenOpt::default());
+        gen.config.wrap_module("raft_cmdpb").module("wrappers");
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("impl Request {"));
+        assert!(!output.contains("impl PeerRole {"));
+
+        let mut config = WrapperConfig::new();
+        config.wrap_module("kvproto::raft_cmdpb");
+        assert!(config.wraps_module("kvproto::raft_cmdpb::"));
+        assert!(config.wraps_module("kvproto::raft_cmdpb::request::"));
+        assert!(!config.wraps_module("kvproto::raft_cmdpb_v2::"));
+        assert!(!config.wraps_module(""));
+    }
+
+    #[test]
+    fn test_bytes_conversions() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(uint64, tag = "1")]
+                pub id: u64,
+            }
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::default());
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        assert!(!String::from_utf8(buf)
+            .unwrap()
+            .contains("::std::convert::From<Foo>"));
+
+        gen.config.bytes_conversions(true);
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("impl ::std::convert::From<Foo> for ::std::vec::Vec<u8> {"));
+        assert!(output.contains("impl ::std::convert::From<Foo> for ::prost::bytes::Bytes {"));
+    }
+
+    #[test]
+    fn test_restricted_visibility() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            struct Top {}
+            pub mod foo {
+                #[derive(Clone, PartialEq, ::prost::Message)]
+                pub(super) struct Parent {}
+                #[derive(Clone, PartialEq, ::prost::Message)]
+                pub(in crate::protos) struct Somewhere {}
+                pub mod bar {
+                    #[derive(Clone, PartialEq, ::prost::Message)]
+                    pub(super) struct Hidden {}
+                }
+                #[derive(Clone, PartialEq, ::prost::Message)]
+                struct Private {}
+            }
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::default());
+        let mut buf = Vec::new();
+        assert_eq!(3, gen.generate(&mut buf).unwrap().messages);
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("impl Top {"));
+        assert!(output.contains("impl foo::Parent {"));
+        assert!(output.contains("impl foo::Somewhere {"));
+        assert!(!output.contains("Hidden"));
+        assert!(!output.contains("Private"));
+
+        gen.config.module("wrappers");
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("impl Parent {"));
+        assert!(!output.contains("Hidden"));
+
+        gen.config.strict(true);
+        let err = gen.generate(&mut Vec::new()).unwrap_err();
+        assert_eq!(
+            "test.rs:11:39: message `Hidden` is not visible outside its module and won't be wrapped",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn test_other_items() {
+        let input = r#"
+            use std::fmt;
+            const LIMIT: usize = 10;
+            impl fmt::Display for Foo {
+                fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                    write!(f, "foo")
+                }
+            }
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(uint64, tag = "1")]
+                pub id: u64,
+            }
+            some_macro!();
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Bar {
+                #[prost(uint64, tag = "1")]
+                pub id: u64,
+            }
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::default());
+        gen.config.strict(true);
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("impl Foo {"));
+        assert!(output.contains("impl Bar {"));
+
+        // Messages inside a macro invocation can't be wrapped.
+        let input = r#"
+            some_macro! {
+                #[derive(Clone, PartialEq, ::prost::Message)]
+                pub struct Foo {
+                    #[prost(uint64, tag = "1")]
+                    pub id: u64,
+                }
+            }
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::default());
+        gen.config.strict(true);
+        let err = gen.generate(&mut Vec::new()).unwrap_err();
+        assert_eq!(
+            "test.rs:4:28: message `Foo` is not in a module and won't be wrapped",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn test_nested_modules() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(message, repeated, tag = "1")]
+                pub bars: ::std::vec::Vec<foo::Bar>,
+            }
+            pub mod foo {
+                #[derive(Clone, PartialEq, ::prost::Message)]
+                pub struct Bar {
+                    #[prost(uint64, tag = "1")]
+                    pub id: u64,
+                }
+            }
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::default());
+        gen.config
+            .module("wrappers")
+            .display_field("foo::Bar", "id");
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(
+            output.contains("pub mod wrappers {\n#[allow(unused_imports)]\npub use super::*;\n")
+        );
+        assert!(output.contains("impl Foo {"));
+        assert!(output
+            .contains("pub mod foo {\n#[allow(unused_imports)]\npub use super::super::foo::*;\n"));
+        assert!(output.contains("impl Bar {"));
+        assert!(output.contains("impl ::std::fmt::Display for Bar {"));
+        assert!(!output.contains("impl foo::Bar"));
+    }
+
+    #[test]
+    fn test_derive_paths() {
+        let input = r#"
+            #[derive(Clone, PartialEq, Message)]
+            pub struct A {}
+            #[derive(Clone, PartialEq, prost::Message)]
+            pub struct B {}
+            #[derive(Clone, PartialEq, ::prost_derive::Message)]
+            pub struct C {}
+            #[derive(Clone, PartialEq, other::Message)]
+            pub struct D {}
+            #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, Enumeration)]
+            #[repr(i32)]
+            pub enum E {
+                Unknown = 0,
+            }
+        "#;
+        let output = generate_str(input, GenOpt::default());
+        assert!(output.contains("impl A {"));
+        assert!(output.contains("impl B {"));
+        assert!(output.contains("impl C {"));
+        assert!(!output.contains("impl D {"));
+        assert!(output.contains("impl E {"));
+    }
+
+    #[test]
+    fn test_accessor_tests() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(uint64, tag = "1")]
+                pub id: u64,
+                #[prost(string, tag = "2")]
+                pub name: std::string::String,
+                #[prost(message, optional, tag = "3")]
+                pub bar: ::std::option::Option<Bar>,
+                #[prost(uint64, repeated, tag = "4")]
+                pub ids: ::std::vec::Vec<u64>,
+            }
+        "#;
+        let output = generate_str(input, GenOpt::default());
+        assert!(!output.contains("#[cfg(test)]"));
+
+        let output = generate_str(input, GenOpt::default() | GenOpt::TESTS);
+        assert!(output.contains("mod test_Foo {"));
+        assert!(output.contains("let mut m = Foo::default();"));
+        assert!(output.contains("m.set_id(1);"));
+        assert!(output.contains("assert_eq!(m.get_id(), 1);"));
+        assert!(output.contains("m.clear_id();"));
+        assert!(output.contains("assert_eq!(m.get_name(), \"a\");"));
+        assert!(output.contains("assert!(m.has_bar());"));
+        assert!(output.contains("assert_eq!(m, Foo::default());"));
+        assert!(!output.contains("m.set_ids("));
+    }
+
+    #[test]
+    fn test_skip_method() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(message, optional, tag = "1")]
+                pub bar: ::std::option::Option<Bar>,
+                #[prost(uint64, tag = "2")]
+                pub id: u64,
+            }
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::default() | GenOpt::ENCODE_DECODE);
+        gen.config
+            .skip_method("Foo", "get_bar")
+            .skip_method("Foo", "new_")
+            .skip_method("Foo", "to_vec")
+            .skip_method("Bar", "get_id");
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(!output.contains("pub fn get_bar("));
+        assert!(!output.contains("pub fn new_("));
+        assert!(!output.contains("pub fn to_vec("));
+        assert!(!output.contains("Vec::with_capacity(::prost::Message::encoded_len(self))"));
+        assert!(output.contains("pub fn mut_bar("));
+        assert!(output.contains("pub fn get_id("));
+        assert!(output.contains("pub fn from_bytes("));
+    }
+
+    #[test]
+    fn test_tuple_struct() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo(#[prost(uint64, tag = "1")] pub u64);
+        "#;
+        let gen = wrapper_gen(input, GenOpt::default());
+        let err = gen.generate(&mut Vec::new()).unwrap_err();
+        assert_eq!(
+            "Message `Foo` is a tuple struct, accessors can only be generated for named fields",
+            err.to_string()
+        );
+        let warnings: Vec<_> = gen
+            .validate()
+            .unwrap()
+            .iter()
+            .map(|w| w.to_string())
+            .collect();
+        assert_eq!(
+            vec!["Foo.0: fields of tuple structs are not supported"],
+            warnings
+        );
+    }
+
+    #[test]
+    fn test_message_derive() {
+        let input = r#"
+            #[derive(Clone, PartialEq, my_macros::ProtoMessage)]
+            pub struct A {}
+            #[derive(Clone, PartialEq, Message)]
+            pub struct B {}
+            #[derive(Clone, PartialEq, Other)]
+            pub struct C {}
+        "#;
+        assert!(!generate_str(input, GenOpt::default()).contains("impl A {"));
+
+        let mut gen = wrapper_gen(input, GenOpt::default());
+        gen.config.message_derive("ProtoMessage");
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("impl A {"));
+        assert!(output.contains("impl B {"));
+        assert!(!output.contains("impl C {"));
+    }
+
+    #[test]
+    fn test_field_attrs() {
+        let item: ItemStruct = syn::parse_quote! {
+            pub struct Foo {
+                #[prost(tag = "7", uint32, packed = "false", repeated, default = "3", custom)]
+                pub ids: ::std::vec::Vec<u32>,
+            }
+        };
+        let attrs = FieldAttrs::from_attrs(&item.fields.iter().next().unwrap().attrs, "");
+        assert_eq!(Some(7), attrs.tag);
+        assert_eq!(Some(false), attrs.packed);
+        assert!(!attrs.is_packed());
+        assert_eq!(Some("3"), attrs.default.as_deref());
+        assert_eq!(vec![("custom".to_owned(), None)], attrs.other);
+        assert!(attrs.tags.is_empty());
+        assert_eq!("repeated int", attrs.kind().unwrap().description());
+
+        let item: ItemStruct = syn::parse_quote! {
+            pub struct Foo {
+                #[prost(tag = "2", enumeration = "Color", optional)]
+                pub color: ::std::option::Option<i32>,
+            }
+        };
+        let attrs = FieldAttrs::from_attrs(&item.fields.iter().next().unwrap().attrs, "foo::");
+        assert_eq!(
+            Some(FieldKind::Optional(Box::new(FieldKind::Enumeration(
+                "foo::Color".to_owned()
+            )))),
+            attrs.kind()
+        );
+
+        let item: ItemStruct = syn::parse_quote! {
+            pub struct Foo {
+                #[prost(enumeration = r"bar::Color", tag = "1")]
+                pub a: i32,
+                #[prost(oneof = "bar::\x56alue", tags = "2, 3")]
+                pub b: ::std::option::Option<bar::Value>,
+                #[prost(enumeration = 5, tag = "4")]
+                pub c: i32,
+            }
+        };
+        let kinds: Vec<_> = item
+            .fields
+            .iter()
+            .map(|f| FieldAttrs::from_attrs(&f.attrs, "").kind())
+            .collect();
+        assert_eq!(
+            vec![
+                Some(FieldKind::Enumeration("bar::Color".to_owned())),
+                Some(FieldKind::OneOf("bar::Value".to_owned())),
+                None,
+            ],
+            kinds
+        );
+        let attrs = FieldAttrs::from_attrs(&item.fields.iter().nth(1).unwrap().attrs, "");
+        assert_eq!(vec![2, 3], attrs.tags);
+
+        let item: ItemStruct = syn::parse_quote! {
+            pub struct Foo {
+                #[prost(uint32, tag = 7)]
+                pub a: u32,
+                #[prost(uint32, tag = 0x10u32)]
+                pub b: u32,
+                #[prost(uint32, tag = " 3 ")]
+                pub c: u32,
+                #[prost(uint32, tag = "x")]
+                pub d: u32,
+            }
+        };
+        let tags: Vec<_> = item
+            .fields
+            .iter()
+            .map(|f| FieldAttrs::from_attrs(&f.attrs, "").tag)
+            .collect();
+        assert_eq!(vec![Some(7), Some(16), Some(3), None], tags);
+
+        let item: ItemStruct = syn::parse_quote! {
+            pub struct Foo {
+                #[prost(enumeration = "Color")]
+                /// The color.
+                #[prost(optional, tag = "1")]
+                #[prost(default = "Red")]
+                pub color: ::std::option::Option<i32>,
+            }
+        };
+        let attrs = FieldAttrs::from_attrs(&item.fields.iter().next().unwrap().attrs, "");
+        assert_eq!(Some(1), attrs.tag);
+        assert_eq!(Some("Red"), attrs.default.as_deref());
+        assert_eq!(
+            Some(FieldKind::Optional(Box::new(FieldKind::Enumeration(
+                "Color".to_owned()
+            )))),
+            attrs.kind()
+        );
+
+        let item: ItemStruct = syn::parse_quote! {
+            pub struct Foo {
+                #[prost(optional, int32, tag = "1")]
+                pub a: ::std::option::Option<i32>,
+                #[prost(message, repeated, tag = "2")]
+                pub b: ::std::vec::Vec<Bar>,
+                #[prost(repeated, custom, tag = "3")]
+                pub c: ::std::vec::Vec<Baz>,
+                #[prost(string, bytes, tag = "4")]
+                pub d: ::std::string::String,
+                #[prost(optional, repeated, int32, tag = "5")]
+                pub e: ::std::vec::Vec<i32>,
+                #[prost(optional, tag = "6")]
+                pub f: ::std::option::Option<i32>,
+            }
+        };
+        let kinds: Vec<_> = item
+            .fields
+            .iter()
+            .map(|f| FieldAttrs::from_attrs(&f.attrs, "").kind())
+            .collect();
+        assert_eq!(
+            vec![
+                Some(FieldKind::Optional(Box::new(FieldKind::Int))),
+                Some(FieldKind::Repeated {
+                    prefix: String::new(),
+                    element: Some(Box::new(FieldKind::Message)),
+                }),
+                Some(FieldKind::Repeated {
+                    prefix: String::new(),
+                    element: None,
+                }),
+                None,
+                None,
+                None,
+            ],
+            kinds
+        );
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let gen = wrapper_gen(
+            "pub struct Foo {\n    bar: u32,\n    baz\n}",
+            GenOpt::default(),
+        );
+        let err = gen.generate(&mut Vec::new()).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+        assert!(err
+            .to_string()
+            .starts_with("test.rs:4:1: could not parse Prost output: "));
+
+        // Files which can't be read are reported rather than panicking.
+        let dir = std::env::temp_dir().join(format!("protobuf-build-read-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let missing = dir.join("missing.rs");
+        let err = WrapperGen::new(missing.clone(), GenOpt::default(), WrapperConfig::default())
+            .err()
+            .unwrap();
+        assert_eq!(io::ErrorKind::NotFound, err.kind());
+        assert!(err
+            .to_string()
+            .starts_with(&format!("{}: ", missing.display())));
+        let binary = dir.join("binary.rs");
+        fs::write(&binary, [0xff, 0xfe]).unwrap();
+        let err = WrapperGen::new(binary.clone(), GenOpt::default(), WrapperConfig::default())
+            .err()
+            .unwrap();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+        assert!(err
+            .to_string()
+            .starts_with(&format!("{}: ", binary.display())));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_well_known_types() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Event {
+                #[prost(message, optional, tag = "1")]
+                pub created: ::std::option::Option<::prost_types::Timestamp>,
+                #[prost(message, optional, tag = "2")]
+                pub timeout: ::std::option::Option<::prost_types::Duration>,
+            }
+        "#;
+        let output = generate_str(input, GenOpt::default());
+        assert!(!output.contains("get_created_as_system_time"));
+        assert!(output.contains(
+            "pub fn get_created_seconds(&self) -> i64 { self.created.as_ref().map_or(0, |v| v.seconds) }"
+        ));
+        assert!(output.contains(
+            "pub fn get_timeout_nanos(&self) -> i32 { self.timeout.as_ref().map_or(0, |v| v.nanos) }"
+        ));
+
+        let output = generate_str(input, GenOpt::default() | GenOpt::WELL_KNOWN_TYPES);
+        assert!(output.contains("pub fn get_created_as_system_time(&self) -> ::std::option::Option<::std::time::SystemTime>"));
+        assert!(output.contains(
+            "pub fn set_created_from_system_time(&mut self, v: ::std::time::SystemTime)"
+        ));
+        assert!(output.contains(
+            "pub fn get_timeout_as_duration(&self) -> ::std::option::Option<::std::time::Duration>"
+        ));
+        assert!(output
+            .contains("pub fn set_timeout_from_duration(&mut self, v: ::std::time::Duration)"));
+    }
+
+    #[test]
+    fn test_any() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Envelope {
+                #[prost(message, optional, tag = "1")]
+                pub payload: ::std::option::Option<::prost_types::Any>,
+            }
+        "#;
+        let output = generate_str(input, GenOpt::default());
+        assert!(!output.contains("pack_payload"));
+
+        let output = generate_str(input, GenOpt::default() | GenOpt::WELL_KNOWN_TYPES);
+        assert!(output.contains("pub fn pack_payload<T: ::prost::Message>(&mut self, type_url: impl ::std::convert::Into<::std::string::String>, msg: &T)"));
+        assert!(output.contains("pub fn unpack_payload<T: ::prost::Message + ::std::default::Default>(&self, type_url: &str) -> ::std::option::Option<T>"));
+        assert!(!output.contains("get_payload_as_"));
+        assert!(!output.contains("get_payload_seconds"));
+    }
+
+    #[test]
+    fn test_copy_from() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(string, tag = "1")]
+                pub name: std::string::String,
+                #[prost(uint64, repeated, tag = "2")]
+                pub ids: ::std::vec::Vec<u64>,
+            }
+        "#;
+        let output = generate_str(input, GenOpt::default());
+        assert!(!output.contains("copy_from"));
+
+        let output = generate_str(input, GenOpt::default() | GenOpt::COPY_FROM);
+        assert!(output.contains(
+            "pub fn copy_from(&mut self, other: &Self) {
+    self.name.clone_from(&other.name);
+    self.ids.clone_from(&other.ids);
+    }"
+        ));
+
+        // The body is indented, so the method is skipped as a whole.
+        let mut gen = wrapper_gen(
+            input,
+            GenOpt::default() | GenOpt::COPY_FROM | GenOpt::MERGE_FROM,
+        );
+        gen.config.skip_method("Foo", "copy_from");
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(!output.contains("copy_from"));
+        assert!(!output.contains("clone_from"));
+        assert!(output.contains("pub fn merge_from(&mut self, other: Self) {"));
+        assert_eq!(output.matches('{').count(), output.matches('}').count());
+    }
+
+    #[test]
+    fn test_merge_from() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(string, tag = "1")]
+                pub name: std::string::String,
+                #[prost(uint64, repeated, tag = "2")]
+                pub ids: ::std::vec::Vec<u64>,
+                #[prost(message, optional, boxed, tag = "3")]
+                pub next: ::std::option::Option<::std::boxed::Box<Foo>>,
+                #[prost(message, optional, tag = "4")]
+                pub created: ::std::option::Option<::prost_types::Timestamp>,
+                #[prost(uint32, optional, tag = "5")]
+                pub size: ::std::option::Option<u32>,
+            }
+        "#;
+        let output = generate_str(input, GenOpt::default());
+        assert!(!output.contains("pub fn merge_from"));
+
+        let output = generate_str(input, GenOpt::default() | GenOpt::MERGE_FROM);
+        assert!(output.contains(
+            "pub fn merge_from(&mut self, other: Self) {
+    if other.name != ::std::default::Default::default() { self.name = other.name; }
+    self.ids.extend(other.ids);
+    match (&mut self.next, other.next) {
+        (::std::option::Option::Some(m), ::std::option::Option::Some(v)) => m.merge_from(*v),
+        (m, v @ ::std::option::Option::Some(_)) => *m = v,
+        _ => {}
+    }
+    if other.created.is_some() { self.created = other.created; }
+    if other.size.is_some() { self.size = other.size; }
+    }"
+        ));
+    }
+
+    #[test]
+    fn test_boxed_messages() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(message, optional, boxed, tag = "1")]
+                pub next: ::std::option::Option<::std::boxed::Box<Foo>>,
+            }
+        "#;
+        let item: ItemStruct = syn::parse_str(input).unwrap();
+        let attrs = FieldAttrs::from_attrs(&item.fields.iter().next().unwrap().attrs, "");
+        assert!(attrs.boxed);
+        assert!(attrs.other.is_empty());
+        assert_eq!(
+            Some("optional message".to_owned()),
+            attrs.kind().map(|k| k.description())
+        );
+
+        // The getter derefs the box, other accessors keep it.
+        let output = generate_str(input, GenOpt::default());
+        assert!(output.contains(
+            "pub fn get_next(&self) -> &Foo { self.next.as_deref().unwrap_or_else(|| Foo::default_ref()) }"
+        ));
+        assert!(output.contains(
+            "pub fn set_next(&mut self, v: :: std :: boxed :: Box < Foo >) { self.next = ::std::option::Option::Some(v); }"
+        ));
+        assert!(
+            output.contains("pub fn mut_next(&mut self) -> &mut :: std :: boxed :: Box < Foo > {")
+        );
+        assert!(output.contains(
+            "pub fn mut_next_or_insert_with(&mut self, f: impl ::std::ops::FnOnce() -> :: std :: boxed :: Box < Foo >) -> &mut :: std :: boxed :: Box < Foo > { self.next.get_or_insert_with(f) }"
+        ));
+    }
+
+    #[test]
+    fn test_boxed_scalars() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(string, tag = "1")]
+                pub name: ::std::boxed::Box<str>,
+                #[prost(string, optional, tag = "2")]
+                pub nick: ::std::option::Option<::std::boxed::Box<str>>,
+                #[prost(bytes, tag = "3")]
+                pub data: ::std::boxed::Box<[u8]>,
+                #[prost(uint64, tag = "4")]
+                pub id: ::std::boxed::Box<u64>,
+                #[prost(uint64, optional, tag = "5")]
+                pub maybe_id: ::std::option::Option<::std::boxed::Box<u64>>,
+            }
+        "#;
+        let output = generate_str(input, GenOpt::default());
+        assert!(output.contains("pub fn get_name(&self) -> &str { &self.name }"));
+        assert!(output.contains(
+            "pub fn set_name(&mut self, v: ::std::string::String) { self.name = v.into_boxed_str(); }"
+        ));
+        assert!(output.contains(
+            "pub fn clear_name(&mut self) { self.name = ::std::default::Default::default() }"
+        ));
+        assert!(output.contains(
+            "pub fn set_nick(&mut self, v: ::std::string::String) { self.nick = ::std::option::Option::Some(v.into_boxed_str()); }"
+        ));
+        assert!(output.contains("pub fn get_data(&self) -> &[u8] { &self.data }"));
+        assert!(output.contains(
+            "pub fn set_data(&mut self, v: ::std::vec::Vec<u8>) { self.data = v.into_boxed_slice(); }"
+        ));
+        assert!(output.contains("pub fn get_id(&self) -> u64 { *self.id }"));
+        assert!(output
+            .contains("pub fn set_id(&mut self, v: u64) { self.id = ::std::boxed::Box::new(v); }"));
+        assert!(output.contains(
+            "pub fn get_maybe_id(&self) -> u64 { self.maybe_id.as_deref().copied().unwrap_or(0) }"
+        ));
+    }
+
+    #[test]
+    fn test_alloc_boxes() {
+        // No `boxed` markers, so boxes are recognized by their type alone.
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(message, optional, tag = "1")]
+                pub next: ::core::option::Option<::prost::alloc::boxed::Box<Foo>>,
+                #[prost(uint64, tag = "2")]
+                pub id: ::prost::alloc::boxed::Box<u64>,
+            }
+        "#;
+        let output = generate_str(input, GenOpt::default() | GenOpt::MERGE_FROM);
+        assert!(output.contains(
+            "pub fn get_next(&self) -> &Foo { self.next.as_deref().unwrap_or_else(|| Foo::default_ref()) }"
+        ));
+        assert!(output.contains(
+            "(::std::option::Option::Some(m), ::std::option::Option::Some(v)) => m.merge_from(*v),"
+        ));
+        assert!(output.contains("pub fn get_id(&self) -> u64 { *self.id }"));
+        assert!(output
+            .contains("pub fn set_id(&mut self, v: u64) { self.id = ::std::boxed::Box::new(v); }"));
+    }
+
+    #[test]
+    fn test_clone() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(message, optional, tag = "1")]
+                pub bar: ::std::option::Option<Bar>,
+                #[prost(uint64, repeated, tag = "2")]
+                pub ids: ::std::vec::Vec<u64>,
+                #[prost(string, tag = "3")]
+                pub name: ::std::string::String,
+            }
+        "#;
+        let output = generate_str(input, GenOpt::default());
+        assert!(!output.contains("pub fn clone_"));
+        let output = generate_str(input, GenOpt::default() | GenOpt::CLONE);
+        assert!(output
+            .contains("pub fn clone_bar(&self) -> Bar { self.bar.clone().unwrap_or_default() }"));
+        assert!(
+            output.contains("pub fn clone_ids(&self) -> ::std::vec::Vec<u64> { self.ids.clone() }")
+        );
+        assert!(!output.contains("pub fn clone_name"));
+    }
+
+    #[test]
+    fn test_visit_mut() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(string, tag = "1")]
+                pub name: ::prost::alloc::string::String,
+                #[prost(string, optional, tag = "2")]
+                pub alias: ::core::option::Option<::prost::alloc::string::String>,
+                #[prost(string, repeated, tag = "3")]
+                pub tags: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+                #[prost(string, tag = "4")]
+                pub boxed: ::std::boxed::Box<str>,
+                #[prost(bytes, tag = "5")]
+                pub data: ::prost::alloc::vec::Vec<u8>,
+            }
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Bar {
+                #[prost(uint64, tag = "1")]
+                pub id: u64,
+            }
+        "#;
+        let output = generate_str(input, GenOpt::default());
+        assert!(!output.contains("for_each_"));
+        let output = generate_str(input, GenOpt::default() | GenOpt::VISIT_MUT);
+        assert!(output.contains(
+            "#[inline] pub fn for_each_string_field_mut(&mut self, mut f: impl ::std::ops::FnMut(&mut ::std::string::String)) {
+    f(&mut self.name);
+    if let ::std::option::Option::Some(v) = &mut self.alias { f(v); }
+    self.tags.iter_mut().for_each(&mut f);
+}"
+        ));
+        assert!(output.contains(
+            "#[inline] pub fn for_each_bytes_field_mut(&mut self, mut f: impl ::std::ops::FnMut(&mut ::std::vec::Vec<u8>)) {
+    f(&mut self.data);
+}"
+        ));
+        assert_eq!(2, output.matches("for_each_").count());
+    }
+
+    #[test]
+    fn test_setters_move() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(uint64, tag = "1")]
+                pub id: u64,
+                #[prost(uint64, optional, tag = "2")]
+                pub maybe_id: ::std::option::Option<u64>,
+                #[prost(enumeration = "Kind", tag = "3")]
+                pub kind: i32,
+                #[prost(enumeration = "Kind", optional, tag = "4")]
+                pub maybe_kind: ::std::option::Option<i32>,
+                #[prost(message, optional, tag = "5")]
+                pub bar: ::std::option::Option<Bar>,
+                #[prost(string, repeated, tag = "6")]
+                pub names: ::std::vec::Vec<::std::string::String>,
+                #[prost(map = "string, uint64", tag = "7")]
+                pub counts: ::std::collections::HashMap<::std::string::String, u64>,
+            }
+        "#;
+        let output = generate_str(input, GenOpt::all());
+        let setters: Vec<_> = output
+            .lines()
+            .filter(|l| l.contains("pub fn set_"))
+            .collect();
+        // Prost generates `set_kind` and `set_maybe_kind`, only the `_i32` setters are ours.
+        assert_eq!(7, setters.len());
+        for setter in setters {
+            assert!(!setter.contains("clone"), "{}", setter);
+        }
+        assert!(output.contains("pub fn set_id(&mut self, v: u64) { self.id = v; }"));
+        assert!(output.contains(
+            "pub fn set_maybe_id(&mut self, v: u64) { self.maybe_id = ::std::option::Option::Some(v); }"
+        ));
+    }
+
+    #[test]
+    fn test_accessor_families() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(string, tag = "1")]
+                pub name: ::std::string::String,
+                #[prost(message, optional, tag = "2")]
+                pub bar: ::std::option::Option<Bar>,
+            }
+        "#;
+        let output = generate_str(input, GenOpt::TRIVIAL_GET | GenOpt::TRIVIAL_SET);
+        assert!(output.contains("pub fn get_name(&self) -> &str {"));
+        assert!(output.contains("pub fn set_name(&mut self, v: :: std :: string :: String) {"));
+        assert!(output.contains("pub fn get_bar(&self) -> &Bar {"));
+        assert!(output.contains("pub fn set_bar(&mut self, v: Bar) {"));
+        for family in ["has_", "clear_", "mut_", "take_"] {
+            assert!(!output.contains(&format!("pub fn {}", family)));
+        }
+    }
+
+    #[test]
+    fn test_enum_accessors() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(enumeration = "Color", tag = "1")]
+                pub color: i32,
+                #[prost(enumeration = "Color", optional, tag = "2")]
+                pub maybe_color: ::std::option::Option<i32>,
+            }
+        "#;
+        let output = generate_str(input, GenOpt::default());
+        assert!(output.contains(
+            "pub fn get_color(&self) -> Color { Color::from_i32(self.color).unwrap_or_default() }"
+        ));
+        assert!(output.contains("pub fn set_color_i32(&mut self, v: i32) { self.color = v; }"));
+        assert!(output
+            .contains("pub fn clear_color(&mut self) { self.color = Color::default() as i32 }"));
+        assert!(output.contains(
+            "pub fn get_maybe_color(&self) -> Color { self.maybe_color.and_then(Color::from_i32).unwrap_or_default() }"
+        ));
+        assert!(output.contains(
+            "pub fn set_maybe_color_i32(&mut self, v: i32) { self.maybe_color = ::std::option::Option::Some(v); }"
+        ));
+        assert!(output.contains(
+            "pub fn try_get_color(&self) -> ::std::result::Result<Color, i32> { Color::from_i32(self.color).ok_or(self.color) }"
+        ));
+        assert!(output.contains(
+            "pub fn try_get_maybe_color(&self) -> ::std::result::Result<Color, i32> { match self.maybe_color { ::std::option::Option::Some(v) => Color::from_i32(v).ok_or(v), ::std::option::Option::None => ::std::result::Result::Ok(Color::default()) } }"
+        ));
+        // The typed setters are generated by Prost.
+        assert!(!output.contains("pub fn set_color("));
+        assert!(!output.contains("pub fn set_maybe_color("));
+    }
+
+    #[test]
+    fn test_extern_enum() {
+        let input = r#"
+            pub mod foo {
+                #[derive(Clone, PartialEq, ::prost::Message)]
+                pub struct Bar {
+                    #[prost(enumeration = "super::common::Kind", tag = "1")]
+                    pub kind: i32,
+                    #[prost(enumeration = "::other::Color", tag = "2")]
+                    pub color: i32,
+                    #[prost(oneof = "bar::Value", tags = "3")]
+                    pub value: ::std::option::Option<bar::Value>,
+                }
+                pub mod bar {
+                    #[derive(Clone, PartialEq, ::prost::Oneof)]
+                    pub enum Value {
+                        #[prost(enumeration = "super::super::common::Kind", tag = "3")]
+                        OtherKind(i32),
+                    }
+                }
+            }
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::default());
+        gen.config
+            .extern_enum("super::common::Kind", "::common::Kind")
+            .extern_enum("super::super::common::Kind", "::common::Kind");
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains(
+            "pub fn get_kind(&self) -> ::common::Kind { :: common :: Kind::from_i32(self.kind).unwrap_or_default() }"
+        ));
+        // Absolute paths aren't relative to the message's module.
+        assert!(output.contains(
+            "pub fn get_color(&self) -> ::other::Color { :: other :: Color::from_i32(self.color).unwrap_or_default() }"
+        ));
+        assert!(output.contains(
+            "::std::option::Option::Some(foo::bar::Value::OtherKind(v)) => :: common :: Kind::from_i32(*v).unwrap_or_default(),"
+        ));
+        assert!(!output.contains("super"));
+
+        // A malformed path is an error, rather than a panic.
+        gen.config
+            .extern_enum("super::common::Kind", "::common::Kind")
+            .extern_enum("super::super::common::Kind", "::common::Kind<");
+        let err = gen.generate(&mut Vec::new()).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+        assert_eq!(
+            "Invalid enum `::common::Kind<` for field `value.other_kind` of message `foo::Bar`",
+            err.to_string()
+        );
+        gen.config
+            .extern_enum("super::common::Kind", "common Kind")
+            .extern_enum("super::super::common::Kind", "::common::Kind");
+        let err = gen.generate(&mut Vec::new()).unwrap_err();
+        assert_eq!(
+            "Invalid enum `common Kind` for field `kind` of message `foo::Bar`",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn test_shared_enum_helpers() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(enumeration = "Color", tag = "1")]
+                pub color: i32,
+                #[prost(enumeration = "Color", optional, tag = "2")]
+                pub maybe_color: ::std::option::Option<i32>,
+            }
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::default());
+        gen.config.shared_enum_helpers(true);
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains(
+            "pub fn get_color(&self) -> Color { __enums::get(self.color, Color::from_i32) }"
+        ));
+        assert!(output.contains(
+            "pub fn clear_color(&mut self) { self.color = __enums::set(Color::default()) }"
+        ));
+        assert!(output.contains(
+            "pub fn try_get_maybe_color(&self) -> ::std::result::Result<Color, i32> { __enums::try_get_opt(self.maybe_color, Color::from_i32) }"
+        ));
+        assert!(!output.contains("from_i32("));
+
+        let files = [PathBuf::from("/out/foo.bar.rs")];
+        let output = mod_file(&files, GenOpt::default(), &gen.config, None).unwrap();
+        assert!(output.contains("pub mod __enums {"));
+        assert!(output.contains(
+            "pub mod bar {\n#[allow(unused_imports)]\nuse super::super::__enums;\ninclude!"
+        ));
+    }
+
+    #[test]
+    fn test_proto3_optional() {
+        // Prost generates the same fields for proto3 `optional` fields as for
+        // proto2 ones.
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(int32, optional, tag = "1")]
+                pub count: ::core::option::Option<i32>,
+                #[prost(string, optional, tag = "2")]
+                pub name: ::core::option::Option<::prost::alloc::string::String>,
+            }
+        "#;
+        let output = generate_str(input, GenOpt::default());
+        assert!(output.contains("pub fn has_count(&self) -> bool { self.count.is_some() }"));
+        assert!(output.contains("pub fn get_count(&self) -> i32 { self.count.unwrap_or(0) }"));
+        assert!(output.contains(
+            "pub fn clear_count(&mut self) { self.count = ::std::option::Option::None }"
+        ));
+        assert!(output.contains(
+            "pub fn set_count(&mut self, v: i32) { self.count = ::std::option::Option::Some(v); }"
+        ));
+        assert!(output.contains("pub fn has_name(&self) -> bool { self.name.is_some() }"));
+        assert!(output
+            .contains("pub fn get_name(&self) -> &str { self.name.as_deref().unwrap_or(\"\") }"));
+        assert!(output
+            .contains("pub fn clear_name(&mut self) { self.name = ::std::option::Option::None }"));
+    }
+
+    #[test]
+    fn test_proto2_optional_enum() {
+        // The first value of a proto2 enum is its default, and it needn't be
+        // zero, so unset and unknown values are both read as `default()`.
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(enumeration = "Level", optional, tag = "1")]
+                pub level: ::std::option::Option<i32>,
+            }
+        "#;
+        let output = generate_str(input, GenOpt::default());
+        assert!(output.contains("pub fn has_level(&self) -> bool { self.level.is_some() }"));
+        assert!(output.contains(
+            "pub fn get_level(&self) -> Level { self.level.and_then(Level::from_i32).unwrap_or_default() }"
+        ));
+        assert!(output.contains(
+            "pub fn clear_level(&mut self) { self.level = ::std::option::Option::None }"
+        ));
+        assert!(output.contains(
+            "pub fn set_level_i32(&mut self, v: i32) { self.level = ::std::option::Option::Some(v); }"
+        ));
+    }
+
+    #[test]
+    fn test_typed_enum_accessors() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(enumeration = "Color", tag = "1")]
+                pub color: Color,
+                #[prost(enumeration = "Color", optional, tag = "2")]
+                pub maybe_color: ::std::option::Option<Color>,
+            }
+        "#;
+        let output = generate_str(input, GenOpt::default() | GenOpt::SCALAR_HAS);
+        assert!(output.contains("pub fn get_color(&self) -> Color { self.color }"));
+        assert!(output.contains("pub fn set_color(&mut self, v: Color) { self.color = v; }"));
+        assert!(output.contains("pub fn clear_color(&mut self) { self.color = Color::default() }"));
+        assert!(
+            output.contains("pub fn has_color(&self) -> bool { self.color != Color::default() }")
+        );
+        assert!(output.contains(
+            "pub fn get_maybe_color(&self) -> Color { self.maybe_color.unwrap_or_default() }"
+        ));
+        assert!(output.contains(
+            "pub fn set_maybe_color(&mut self, v: Color) { self.maybe_color = ::std::option::Option::Some(v); }"
+        ));
+        assert!(!output.contains("i32"));
+    }
+
+    #[test]
+    fn test_scalar_has() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(uint64, tag = "1")]
+                pub id: u64,
+                #[prost(string, tag = "2")]
+                pub name: std::string::String,
+                #[prost(bool, tag = "3")]
+                pub done: bool,
+                #[prost(message, optional, tag = "4")]
+                pub bar: ::std::option::Option<Bar>,
+                #[prost(uint64, repeated, tag = "5")]
+                pub ids: ::std::vec::Vec<u64>,
+            }
+        "#;
+        let output = generate_str(input, GenOpt::default());
+        assert!(!output.contains("has_id"));
+        assert!(output.contains("pub fn has_bar(&self) -> bool { self.bar.is_some() }"));
+
+        let output = generate_str(input, GenOpt::default() | GenOpt::SCALAR_HAS);
+        assert!(output.contains("pub fn has_id(&self) -> bool { self.id != 0 }"));
+        assert!(output.contains("pub fn has_name(&self) -> bool { !self.name.is_empty() }"));
+        assert!(output.contains("pub fn has_done(&self) -> bool { self.done }"));
+        assert!(output.contains("pub fn has_bar(&self) -> bool { self.bar.is_some() }"));
+        assert!(!output.contains("has_ids"));
+    }
+
+    #[test]
+    fn test_is_default() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(uint64, tag = "1")]
+                pub id: u64,
+                #[prost(double, tag = "2")]
+                pub weight: f64,
+                #[prost(bool, tag = "3")]
+                pub ok: bool,
+                #[prost(string, tag = "4")]
+                pub name: ::std::string::String,
+                #[prost(enumeration = "Color", tag = "5")]
+                pub color: i32,
+                #[prost(message, optional, tag = "6")]
+                pub bar: ::std::option::Option<Bar>,
+                #[prost(uint32, repeated, tag = "7")]
+                pub ids: ::std::vec::Vec<u32>,
+                #[prost(int32, required, tag = "8", default = "5")]
+                pub size: i32,
+                #[prost(uint64, tag = "9")]
+                pub boxed: ::std::boxed::Box<u64>,
+                pub cached: u64,
+            }
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Bar {}
+        "#;
+        let output = generate_str(input, GenOpt::default());
+        assert!(!output.contains("is_default"));
+
+        let output = generate_str(input, GenOpt::default() | GenOpt::IS_DEFAULT);
+        assert!(output.contains(
+            "#[inline] pub fn is_default(&self) -> bool {
+    self.id == 0
+        && self.weight == 0.
+        && !self.ok
+        && self.name.is_empty()
+        && self.color == 0
+        && self.bar.is_none()
+        && self.ids.is_empty()
+        && self.size == ::std::default::Default::default()
+        && *self.boxed == 0
+}"
+        ));
+        assert!(output.contains("#[inline] pub fn is_default(&self) -> bool {\n    true\n}"));
+    }
+
+    #[test]
+    fn test_full_name() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct HttpRequest {}
+            pub mod http_request {
+                #[derive(Clone, PartialEq, ::prost::Message)]
+                pub struct Header {}
+            }
+        "#;
+        let output = generate_str(input, GenOpt::default());
+        assert!(!output.contains("full_name"));
+
+        let output = generate_str(input, GenOpt::default() | GenOpt::FULL_NAME);
+        assert!(output.contains("pub fn full_name() -> &'static str { \"test.HttpRequest\" }"));
+        assert!(output.contains(
+            "pub fn type_url() -> ::std::string::String { ::std::string::String::from(\"type.googleapis.com/test.HttpRequest\") }"
+        ));
+        assert!(
+            output.contains("pub fn full_name() -> &'static str { \"test.HttpRequest.Header\" }")
+        );
+
+        // Prost names the output for files without a package `_.rs`.
+        let gen = WrapperGen::from_source(
+            PathBuf::from("_.rs"),
+            input.to_owned(),
+            GenOpt::FULL_NAME,
+            WrapperConfig::default(),
+        );
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("pub fn full_name() -> &'static str { \"HttpRequest.Header\" }"));
+
+        // Prost changes the case of names, so they're taken from descriptors.
+        let file = FileDescriptorProto {
+            package: Some("test".to_owned()),
+            message_type: vec![DescriptorProto {
+                name: Some("HTTPRequest".to_owned()),
+                nested_type: vec![DescriptorProto {
+                    name: Some("HEADER".to_owned()),
+                    ..DescriptorProto::default()
+                }],
+                ..DescriptorProto::default()
+            }],
+            ..FileDescriptorProto::default()
+        };
+        let mut bytes = Vec::new();
+        prost::Message::encode(&FileDescriptorSet { file: vec![file] }, &mut bytes).unwrap();
+        let mut gen = wrapper_gen(input, GenOpt::FULL_NAME);
+        gen.descriptor_set(&bytes).unwrap();
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("pub fn full_name() -> &'static str { \"test.HTTPRequest\" }"));
+        assert!(output.contains("\"type.googleapis.com/test.HTTPRequest\""));
+        assert!(
+            output.contains("pub fn full_name() -> &'static str { \"test.HTTPRequest.HEADER\" }")
+        );
+        assert!(!output.contains("test.HttpRequest"));
+    }
+
+    #[test]
+    fn test_encode_decode() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(uint64, tag = "1")]
+                pub id: u64,
+            }
+        "#;
+        let output = generate_str(input, GenOpt::default());
+        assert!(!output.contains("to_vec"));
+
+        let output = generate_str(input, GenOpt::default() | GenOpt::ENCODE_DECODE);
+        assert!(output.contains("pub fn to_vec(&self) -> ::std::vec::Vec<u8> {"));
+        assert!(output.contains(
+            "pub fn from_bytes(bytes: &[u8]) -> ::std::result::Result<Self, ::prost::DecodeError> {"
+        ));
+        assert!(output.contains(
+            "pub fn encoded_len(&self) -> usize { ::prost::Message::encoded_len(self) }"
+        ));
+        assert!(output.contains(
+            "pub fn merge_length_delimited(&mut self, buf: &mut impl ::prost::bytes::Buf) -> ::std::result::Result<(), ::prost::DecodeError> {"
+        ));
+    }
+
+    #[test]
+    fn test_message_ext() {
+        let input = r#"
+            pub mod foo {
+                #[derive(Clone, PartialEq, ::prost::Message)]
+                pub struct Bar {
+                    #[prost(uint64, tag = "1")]
+                    pub id: u64,
+                }
+            }
+        "#;
+        let output = generate_str(input, GenOpt::default());
+        assert!(!output.contains("MessageExt"));
+
+        let output = generate_str(input, GenOpt::default() | GenOpt::MESSAGE_EXT);
+        assert!(output.contains("impl MessageExt for foo::Bar {"));
+    }
+
+    #[test]
+    fn test_repeated_message() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(message, repeated, tag = "1")]
+                pub bars: ::std::vec::Vec<Bar>,
+            }
+        "#;
+        let output = generate_str(input, GenOpt::default());
+        assert!(output.contains("pub fn get_bars(&self) -> &[Bar] { &self.bars }"));
+        assert!(!output.contains("get_bars_at"));
+
+        let output = generate_str(input, GenOpt::default() | GenOpt::INDEXED);
+        assert!(output.contains(
+            "pub fn get_bars_at(&self, index: usize) -> ::std::option::Option<&Bar> { self.bars.get(index) }"
+        ));
+        assert!(output.contains(
+            "pub fn mut_bars_at(&mut self, index: usize) -> ::std::option::Option<&mut Bar> { self.bars.get_mut(index) }"
+        ));
+        assert!(output.contains("pub fn add_bars(&mut self) -> &mut Bar {"));
+        assert!(!output.contains("iter_bars"));
+
+        let output = generate_str(input, GenOpt::default() | GenOpt::ITER);
+        assert!(output.contains(
+            "pub fn iter_bars(&self) -> impl ::std::iter::Iterator<Item = &Bar> { self.bars.iter() }"
+        ));
+        assert!(output.contains(
+            "pub fn iter_bars_mut(&mut self) -> impl ::std::iter::Iterator<Item = &mut Bar> { self.bars.iter_mut() }"
+        ));
+    }
+
+    #[test]
+    fn test_repeated_enums() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(enumeration = "foo::Color", repeated, packed = "true", tag = "1")]
+                pub colors: ::std::vec::Vec<i32>,
+            }
+        "#;
+        let item: ItemStruct = syn::parse_str(input).unwrap();
+        let attrs = FieldAttrs::from_attrs(&item.fields.iter().next().unwrap().attrs, "");
+        assert!(attrs.is_packed());
+        assert_eq!(
+            Some("repeated enumeration".to_owned()),
+            attrs.kind().map(|k| k.description())
+        );
+
+        let output = generate_str(input, GenOpt::default());
+        assert!(output.contains(
+            "pub fn get_colors_enums(&self) -> ::std::vec::Vec<foo::Color> { self.colors.iter().map(|&v| foo :: Color::from_i32(v).unwrap_or_default()).collect() }"
+        ));
+        // Prost generates `push_colors`.
+        assert!(!output.contains("push_colors"));
+        assert!(!output.contains("get_colors_at"));
+
+        let output = generate_str(input, GenOpt::default() | GenOpt::INDEXED);
+        assert!(output.contains(
+            "pub fn get_colors_at(&self, index: usize) -> ::std::option::Option<foo::Color> { self.colors.get(index).map(|&v| foo :: Color::from_i32(v).unwrap_or_default()) }"
+        ));
+        assert!(!output.contains("push_colors"));
+    }
+
+    #[test]
+    fn test_repeated_containers() {
+        let input = r#"
+            pub mod foo {
+                #[derive(Clone, PartialEq, ::prost::Message)]
+                pub struct Bar {
+                    #[prost(message, repeated, tag = "1")]
+                    pub quxes: ::smallvec::SmallVec<[Qux; 4]>,
+                    #[prost(uint32, repeated, tag = "2")]
+                    pub ids: ::std::vec::Vec<u32>,
+                }
+            }
+        "#;
+        let output = generate_str(input, GenOpt::default());
+        assert!(output.contains("pub fn get_quxes(&self) -> &[foo::Qux] { &self.quxes }"));
+        assert!(output.contains(
+            "pub fn set_quxes(&mut self, v: :: smallvec :: SmallVec < [foo :: Qux ; 4] >)"
+        ));
+
+        let mut gen = wrapper_gen(input, GenOpt::default());
+        gen.config.deref_repeated(false);
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains(
+            "pub fn get_quxes(&self) -> &:: smallvec :: SmallVec < [foo :: Qux ; 4] > { &self.quxes }"
+        ));
+        assert!(output.contains("pub fn get_ids(&self) -> &::std::vec::Vec<u32> { &self.ids }"));
+    }
+
+    #[test]
+    fn test_repeated_scalar_at() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(uint64, repeated, tag = "1")]
+                pub ids: ::std::vec::Vec<u64>,
+                #[prost(message, repeated, tag = "2")]
+                pub bars: ::std::vec::Vec<Bar>,
+            }
+        "#;
+        let output = generate_str(input, GenOpt::default() | GenOpt::INDEXED);
+        assert!(output.contains(
+            "pub fn get_ids_at(&self, index: usize) -> ::std::option::Option<u64> { self.ids.get(index).copied() }"
+        ));
+
+        let mut gen = wrapper_gen(input, GenOpt::default() | GenOpt::INDEXED);
+        gen.config.panic_on_bad_index(true);
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(
+            output.contains("pub fn get_ids_at(&self, index: usize) -> u64 { self.ids[index] }")
+        );
+        assert!(output
+            .contains("pub fn get_bars_at(&self, index: usize) -> &Bar { &self.bars[index] }"));
+        assert!(output.contains(
+            "pub fn mut_bars_at(&mut self, index: usize) -> &mut Bar { &mut self.bars[index] }"
+        ));
+        assert!(output.contains("#[track_caller]\n#[inline] pub fn get_ids_at("));
+        assert!(output.contains("#[track_caller]\n#[inline] pub fn mut_bars_at("));
+    }
+
+    #[test]
+    fn test_no_std() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(message, optional, tag = "1")]
+                pub bar: ::core::option::Option<Bar>,
+                #[prost(uint64, repeated, tag = "2")]
+                pub ids: ::prost::alloc::vec::Vec<u64>,
+            }
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::default());
+        gen.config.no_std(true);
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("self.bar = ::core::option::Option::None"));
+        assert!(output.contains(
+            "pub fn take_ids(&mut self) -> ::alloc::vec::Vec<u64> { ::core::mem::take(&mut self.ids) }"
+        ));
+        assert!(!output.contains("::std::option::"));
+        assert!(!output.contains("::std::vec::"));
+    }
+
+    #[test]
+    fn test_prelude() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(message, optional, tag = "1")]
+                pub bar: ::core::option::Option<Bar>,
+                #[prost(uint64, repeated, tag = "2")]
+                pub ids: ::prost::alloc::vec::Vec<u64>,
+            }
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::default());
+        gen.config.prelude(true);
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("use ::std as __std;"));
+        assert!(output.contains("use ::core as __core;"));
+        assert!(output.contains("self.bar = __std::option::Option::None"));
+        assert!(output.contains(
+            "pub fn take_ids(&mut self) -> __std::vec::Vec<u64> { __core::mem::take(&mut self.ids) }"
+        ));
+        assert!(output.contains("pub fn get_bar(&self) -> &Bar {"));
+
+        gen.config.no_std(true);
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("use ::alloc as __alloc;"));
+        assert!(!output.contains("__std"));
+        assert!(output.contains("pub fn take_ids(&mut self) -> __alloc::vec::Vec<u64> {"));
+    }
+
+    #[test]
+    fn test_alloc_paths() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(bytes, tag = "1")]
+                pub data: ::prost::alloc::vec::Vec<u8>,
+                #[prost(string, optional, tag = "2")]
+                pub name: ::core::option::Option<::prost::alloc::string::String>,
+            }
+        "#;
+        let output = generate_str(input, GenOpt::default());
+        assert!(output.contains(
+            "pub fn take_data(&mut self) -> :: prost :: alloc :: vec :: Vec < u8 > { ::core::mem::take(&mut self.data) }"
+        ));
+        assert!(output.contains(
+            "pub fn take_name(&mut self) -> :: prost :: alloc :: string :: String { self.name.take().unwrap_or_default() }"
+        ));
+    }
+
+    #[test]
+    fn test_bytes_as_vec() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(bytes, tag = "1")]
+                pub data: std::vec::Vec<u8>,
+                #[prost(bytes, optional, tag = "2")]
+                pub maybe_data: ::std::option::Option<std::vec::Vec<u8>>,
+            }
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::default());
+        gen.config.deref_bytes(false);
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(
+            output.contains("pub fn get_data(&self) -> &std :: vec :: Vec < u8 > { &self.data }")
+        );
+        assert!(output.contains(
+            "pub fn get_maybe_data(&self) -> &std :: vec :: Vec < u8 > { self.maybe_data.as_ref().unwrap_or("
+        ));
+    }
+
+    #[test]
+    fn test_repeated_bytes_and_strings() {
+        let input = r#"
+            pub mod foo {
+                #[derive(Clone, PartialEq, ::prost::Message)]
+                pub struct Bar {
+                    #[prost(bytes, repeated, tag = "1")]
+                    pub chunks: ::std::vec::Vec<std::vec::Vec<u8>>,
+                    #[prost(string, repeated, tag = "2")]
+                    pub names: ::std::vec::Vec<std::string::String>,
+                    #[prost(int64, repeated, tag = "3")]
+                    pub ids: ::std::vec::Vec<i64>,
+                }
+            }
+        "#;
+        let output = generate_str(input, GenOpt::default() | GenOpt::INDEXED);
+        assert!(output
+            .contains("pub fn get_chunks(&self) -> &[std :: vec :: Vec < u8 >] { &self.chunks }"));
+        assert!(output.contains(
+            "pub fn push_chunks(&mut self, v: impl ::std::convert::Into<std :: vec :: Vec < u8 >>) { self.chunks.push(v.into()); }"
+        ));
+        assert!(output
+            .contains("pub fn get_names(&self) -> &[std :: string :: String] { &self.names }"));
+        assert!(output.contains(
+            "pub fn push_names(&mut self, v: impl ::std::convert::Into<std :: string :: String>) { self.names.push(v.into()); }"
+        ));
+        assert!(output.contains(
+            "pub fn get_names_at(&self, index: usize) -> ::std::option::Option<&str> { self.names.get(index).map(|v| &**v) }"
+        ));
+        assert!(output.contains(
+            "pub fn get_chunks_at(&self, index: usize) -> ::std::option::Option<&[u8]> { self.chunks.get(index).map(|v| &**v) }"
+        ));
+        assert!(output.contains("pub fn get_ids(&self) -> &[i64] { &self.ids }"));
+        assert!(!output.contains("push_ids"));
+    }
+
+    #[test]
+    fn test_repeated_super() {
+        let input = r#"
+            pub mod foo {
+                #[derive(Clone, PartialEq, ::prost::Message)]
+                pub struct Bar {
+                    #[prost(message, repeated, tag = "1")]
+                    pub bazs: ::std::vec::Vec<super::Baz>,
+                    #[prost(message, repeated, tag = "2")]
+                    pub quxes: ::std::vec::Vec<Qux>,
+                }
+            }
+        "#;
+        let output = generate_str(input, GenOpt::default());
+        assert!(output.contains("pub fn get_bazs(&self) -> &[Baz] { &self.bazs }"));
+        assert!(output.contains("pub fn get_quxes(&self) -> &[foo::Qux] { &self.quxes }"));
+    }
+
+    #[test]
+    fn test_oneof_messages() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(oneof = "foo::Value", tags = "1, 2")]
+                pub value: ::std::option::Option<foo::Value>,
+            }
+            pub mod foo {
+                #[derive(Clone, PartialEq, ::prost::Oneof)]
+                pub enum Value {
+                    #[prost(message, tag = "1")]
+                    Bar(super::Bar),
+                    #[prost(message, tag = "2")]
+                    NextFoo(::std::boxed::Box<super::Foo>),
+                }
+            }
+        "#;
+        let output = generate_str(input, GenOpt::default());
+        assert!(output.contains(
+            "pub fn which_value(&self) -> ::std::option::Option<&foo::Value> { self.value.as_ref() }"
+        ));
+        assert!(output.contains(
+            "pub fn has_bar(&self) -> bool { matches!(self.value, ::std::option::Option::Some(foo::Value::Bar(_))) }"
+        ));
+        assert!(output.contains(
+            "pub fn set_bar(&mut self, v: Bar) { self.value = ::std::option::Option::Some(foo::Value::Bar(v)); }"
+        ));
+        assert!(output.contains("pub fn get_bar(&self) -> &Bar {"));
+        assert!(output.contains("_ => Bar::default_ref(),"));
+        assert!(output.contains("pub fn mut_bar(&mut self) -> &mut Bar {"));
+        assert!(output.contains("pub fn take_bar(&mut self) -> Bar {"));
+        assert!(output.contains("::std::option::Option::Some(foo::Value::Bar(v)) => v,"));
+
+        // Boxed variants are unboxed.
+        assert!(output.contains(
+            "pub fn set_next_foo(&mut self, v: Foo) { self.value = ::std::option::Option::Some(foo::Value::NextFoo(::std::boxed::Box::new(v))); }"
+        ));
+        assert!(output.contains("pub fn get_next_foo(&self) -> &Foo {"));
+        assert!(output.contains("pub fn mut_next_foo(&mut self) -> &mut Foo {"));
+        assert!(output.contains("pub fn take_next_foo(&mut self) -> Foo {"));
+        assert!(output.contains("::std::option::Option::Some(foo::Value::NextFoo(v)) => *v,"));
+    }
+
+    #[test]
+    fn test_clear_oneofs() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(oneof = "foo::Value", tags = "1")]
+                pub value: ::std::option::Option<foo::Value>,
+                #[prost(oneof = "foo::Key", tags = "2")]
+                pub r#key: ::std::option::Option<foo::Key>,
+            }
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Bar {
+                #[prost(oneof = "bar::Value", tags = "1")]
+                pub value: ::std::option::Option<bar::Value>,
+            }
+        "#;
+        let output = generate_str(input, GenOpt::default());
+        assert!(output.contains(
+            "pub fn clear_value(&mut self) { self.value = ::std::option::Option::None }"
+        ));
+        assert!(output
+            .contains("pub fn clear_key(&mut self) { self.r#key = ::std::option::Option::None }"));
+        assert!(output.contains(
+            "#[inline] pub fn clear_all_oneofs(&mut self) {
+    self.value = ::std::option::Option::None;
+    self.r#key = ::std::option::Option::None;
+    }"
+        ));
+        // Only for messages with several oneofs.
+        assert_eq!(1, output.matches("clear_all_oneofs").count());
+
+        let output = generate_str(input, GenOpt::default() - GenOpt::CLEAR);
+        assert!(!output.contains("pub fn clear_"));
+    }
+
+    #[test]
+    fn test_nested_oneof() {
+        // A oneof of a message nested in `Foo`, so its enum is in `foo::bar`.
+        let input = r#"
+            pub mod foo {
+                #[derive(Clone, PartialEq, ::prost::Message)]
+                pub struct Bar {
+                    #[prost(oneof = "bar::Value", tags = "1, 2, 3")]
+                    pub value: ::std::option::Option<bar::Value>,
+                }
+                pub mod bar {
+                    #[derive(Clone, PartialEq, ::prost::Oneof)]
+                    pub enum Value {
+                        #[prost(message, tag = "1")]
+                        Baz(super::Baz),
+                        #[prost(enumeration = "super::Kind", tag = "2")]
+                        Kind(i32),
+                        #[prost(message, tag = "3")]
+                        Qux(super::super::Qux),
+                    }
+                }
+            }
+        "#;
+        let output = generate_str(input, GenOpt::default());
+        assert!(output.contains("impl foo::Bar {"));
+        assert!(output.contains(
+            "pub fn which_value(&self) -> ::std::option::Option<&foo::bar::Value> { self.value.as_ref() }"
+        ));
+        assert!(output.contains(
+            "pub fn set_baz(&mut self, v: foo::Baz) { self.value = ::std::option::Option::Some(foo::bar::Value::Baz(v)); }"
+        ));
+        assert!(output.contains("pub fn get_baz(&self) -> &foo::Baz {"));
+        assert!(output.contains("pub fn set_kind(&mut self, v: foo::Kind) {"));
+        assert!(output.contains("pub fn get_qux(&self) -> &Qux {"));
+
+        // In nested modules, paths are relative to the message's module.
+        let mut gen = wrapper_gen(input, GenOpt::default());
+        gen.config.module("wrappers");
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("impl Bar {"));
+        assert!(output.contains(
+            "pub fn set_baz(&mut self, v: Baz) { self.value = ::std::option::Option::Some(bar::Value::Baz(v)); }"
+        ));
+        assert!(output.contains("pub fn set_kind(&mut self, v: Kind) {"));
+        assert!(output.contains("pub fn get_qux(&self) -> &super :: Qux {"));
+    }
+
+    #[test]
+    fn test_sibling_enums() {
+        let input = r#"
+            pub mod foo {
+                #[derive(Clone, PartialEq, ::prost::Message)]
+                pub struct Bar {
+                    #[prost(enumeration = "super::Color", tag = "1")]
+                    pub color: i32,
+                    #[prost(enumeration = "super::baz::Shape", repeated, tag = "2")]
+                    pub shapes: ::std::vec::Vec<i32>,
+                }
+            }
+            pub mod baz {
+                #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+                #[repr(i32)]
+                pub enum Shape { Circle = 0 }
+            }
+            #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+            #[repr(i32)]
+            pub enum Color { Red = 0 }
+        "#;
+        let output = generate_str(input, GenOpt::default());
+        assert!(output.contains(
+            "pub fn get_color(&self) -> Color { Color::from_i32(self.color).unwrap_or_default() }"
+        ));
+        assert!(output.contains("pub fn get_shapes_enums(&self) -> ::std::vec::Vec<baz::Shape> {"));
+        assert!(!output.contains("super"));
+
+        assert_eq!(qualify_path("super::super::Color", "foo::"), "super::Color");
+        assert_eq!(qualify_path("self::Color", "foo::"), "foo::Color");
+        assert_eq!(qualify_path("::common::Color", "foo::"), "::common::Color");
+    }
+
+    #[test]
+    fn test_enum_field_defaults() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(enumeration = "Level", optional, tag = "1", default = "High")]
+                pub level: ::std::option::Option<i32>,
+                #[prost(enumeration = "Level", required, tag = "2", default = "High")]
+                pub min: i32,
+                #[prost(enumeration = "Level", optional, tag = "3")]
+                pub max: ::std::option::Option<i32>,
+            }
+            #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+            #[repr(i32)]
+            pub enum Level { Low = 0, High = 1 }
+        "#;
+        let output = generate_str(input, GenOpt::default());
+        assert!(output.contains("self.level.and_then(Level::from_i32).unwrap_or(Level::High)"));
+        assert!(output.contains("Level::from_i32(self.min).unwrap_or(Level::High)"));
+        assert!(output.contains("self.min = Level::High as i32"));
+        assert!(output.contains("self.max.and_then(Level::from_i32).unwrap_or_default()"));
+    }
+
+    #[test]
+    fn test_oneof_scalars() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(oneof = "foo::Key", tags = "1, 2, 3, 4")]
+                pub key: ::std::option::Option<foo::Key>,
+            }
+            pub mod foo {
+                #[derive(Clone, PartialEq, ::prost::Oneof)]
+                pub enum Key {
+                    #[prost(string, tag = "1")]
+                    Name(::prost::alloc::string::String),
+                    #[prost(uint64, tag = "2")]
+                    Id(u64),
+                    #[prost(bytes, tag = "3")]
+                    Raw(::prost::alloc::vec::Vec<u8>),
+                    #[prost(enumeration = "super::Color", tag = "4")]
+                    Color(i32),
+                }
+            }
+        "#;
+        let output = generate_str(input, GenOpt::default());
+        assert!(output.contains(
+            "pub fn set_name(&mut self, v: :: prost :: alloc :: string :: String) { self.key = ::std::option::Option::Some(foo::Key::Name(v)); }"
+        ));
+        assert!(output.contains("pub fn get_name(&self) -> &str {"));
+        assert!(output.contains("::std::option::Option::Some(foo::Key::Name(v)) => v,"));
+        assert!(output.contains(
+            "pub fn mut_name(&mut self) -> &mut :: prost :: alloc :: string :: String {"
+        ));
+        assert!(output
+            .contains("pub fn take_name(&mut self) -> :: prost :: alloc :: string :: String {"));
+
+        assert!(output.contains(
+            "pub fn set_id(&mut self, v: u64) { self.key = ::std::option::Option::Some(foo::Key::Id(v)); }"
+        ));
+        assert!(output.contains("pub fn get_id(&self) -> u64 {"));
+        assert!(output.contains("::std::option::Option::Some(foo::Key::Id(v)) => *v,"));
+        assert!(!output.contains("pub fn mut_id"));
+        assert!(!output.contains("pub fn take_id"));
+
+        assert!(output.contains("pub fn get_raw(&self) -> &[u8] {"));
+
+        assert!(output.contains(
+            "pub fn set_color(&mut self, v: Color) { self.key = ::std::option::Option::Some(foo::Key::Color(v as i32)); }"
+        ));
+        assert!(output.contains("pub fn get_color(&self) -> Color {"));
+        assert!(output.contains(
+            "::std::option::Option::Some(foo::Key::Color(v)) => Color::from_i32(*v).unwrap_or_default(),"
+        ));
+        assert!(output.contains("_ => Color::default(),"));
+    }
+
+    #[test]
+    fn test_descriptor_set() {
+        use prost_types::source_code_info::Location;
+        use prost_types::{FieldDescriptorProto, FieldOptions, SourceCodeInfo};
+
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(uint32, tag = "2")]
+                pub id: u32,
+                #[prost(string, tag = "1")]
+                pub old_name: std::string::String,
+            }
+            pub mod foo {
+                #[derive(Clone, PartialEq, ::prost::Message)]
+                pub struct Bar {
+                    #[prost(bool, tag = "1")]
+                    pub ok: bool,
+                }
+            }
+        "#;
+        let field = |name: &str, number, deprecated| FieldDescriptorProto {
+            name: Some(name.to_owned()),
+            number: Some(number),
+            options: Some(FieldOptions {
+                deprecated: Some(deprecated),
+                ..FieldOptions::default()
+            }),
+            ..FieldDescriptorProto::default()
+        };
+        let comment = |path: Vec<i32>, comment: &str| Location {
+            path,
+            leading_comments: Some(comment.to_owned()),
+            ..Location::default()
+        };
+        let file = FileDescriptorProto {
+            package: Some("test".to_owned()),
+            message_type: vec![DescriptorProto {
+                name: Some("Foo".to_owned()),
+                field: vec![field("old_name", 1, true), field("id", 2, false)],
+                nested_type: vec![DescriptorProto {
+                    name: Some("Bar".to_owned()),
+                    field: vec![field("ok", 1, false)],
+                    ..DescriptorProto::default()
+                }],
+                ..DescriptorProto::default()
+            }],
+            source_code_info: Some(SourceCodeInfo {
+                location: vec![
+                    comment(vec![4, 0, 2, 1], " The id.\n"),
+                    comment(vec![4, 0, 3, 0, 2, 0], " Whether it's ok.\n"),
+                ],
+            }),
+            ..FileDescriptorProto::default()
+        };
+        let other = FileDescriptorProto {
+            package: Some("other".to_owned()),
+            ..file.clone()
+        };
+        let set = FileDescriptorSet {
+            file: vec![other, file],
+        };
+        let mut bytes = Vec::new();
+        prost::Message::encode(&set, &mut bytes).unwrap();
+
+        let mut gen = wrapper_gen(input, GenOpt::default());
+        gen.descriptor_set(&bytes).unwrap();
+        assert_eq!(1, gen.descriptors.len());
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("#[doc = \" The id.\"]\n#[inline] pub fn get_id(&self)"));
+        assert!(output.contains("#[doc = \" The id.\"]\n#[inline] pub fn set_id("));
+        assert!(output.contains("#[deprecated]\n#[inline] pub fn set_old_name("));
+        assert!(!output.contains("#[deprecated]\n#[inline] pub fn set_id("));
+        assert!(output.contains("#[doc = \" Whether it's ok.\"]\n#[inline] pub fn get_ok(&self)"));
+        // The descriptors don't change the user's configuration.
+        assert_eq!(None, gen.config.field_doc_for("Foo", "id"));
+        assert!(!gen.config.is_deprecated_field("Foo", "old_name"));
+
+        let err = gen.descriptor_set(b"not a descriptor").unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidData, err.kind());
+    }
+
+    #[test]
+    fn test_inline_nested_messages() {
+        // As Prost generates `message Outer { message Inner { message
+        // Innermost { Outer outer = 1; } Innermost innermost = 1; } Inner
+        // inner = 1; }`.
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Outer {
+                #[prost(message, optional, tag = "1")]
+                pub inner: ::std::option::Option<outer::Inner>,
+            }
+            pub mod outer {
+                #[derive(Clone, PartialEq, ::prost::Message)]
+                pub struct Inner {
+                    #[prost(message, optional, tag = "1")]
+                    pub innermost: ::std::option::Option<inner::Innermost>,
+                }
+                pub mod inner {
+                    #[derive(Clone, PartialEq, ::prost::Message)]
+                    pub struct Innermost {
+                        #[prost(message, optional, boxed, tag = "1")]
+                        pub outer: ::std::option::Option<::std::boxed::Box<super::super::Outer>>,
+                    }
+                }
+            }
+        "#;
+        let output = generate_str(input, GenOpt::default());
+        assert!(output.contains("impl Outer {"));
+        assert!(output.contains("impl outer::Inner {"));
+        assert!(output.contains("impl outer::inner::Innermost {"));
+        assert!(output.contains(
+            "pub fn get_inner(&self) -> &outer :: Inner { self.inner.as_ref().unwrap_or_else(|| outer :: Inner::default_ref()) }"
+        ));
+        assert!(output.contains(
+            "pub fn get_innermost(&self) -> &outer :: inner :: Innermost { self.innermost.as_ref().unwrap_or_else(|| outer :: inner :: Innermost::default_ref()) }"
+        ));
+        assert!(output.contains(
+            "pub fn get_outer(&self) -> &Outer { self.outer.as_deref().unwrap_or_else(|| Outer::default_ref()) }"
+        ));
+        assert!(
+            output.contains("pub fn set_innermost(&mut self, v: outer :: inner :: Innermost) {")
+        );
+
+        // In a module, the wrappers are in modules named as Prost's.
+        let mut gen = wrapper_gen(input, GenOpt::default());
+        gen.config.module("wrappers");
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains(
+            "pub mod outer {\n#[allow(unused_imports)]\npub use super::super::outer::*;"
+        ));
+        assert!(output.contains(
+            "pub mod inner {\n#[allow(unused_imports)]\npub use super::super::super::outer::inner::*;"
+        ));
+        assert!(output.contains("impl Innermost {"));
+    }
+
+    #[test]
+    fn test_rust_protobuf_preset() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(message, optional, tag = "1")]
+                pub bar: ::std::option::Option<Bar>,
+                #[prost(string, repeated, tag = "2")]
+                pub names: ::std::vec::Vec<std::string::String>,
+            }
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::RUST_PROTOBUF);
+        gen.config.constructor_name("new");
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        for method in [
+            "pub fn new() -> Foo",
+            "pub fn get_bar(&self)",
+            "pub fn set_bar(&mut self",
+            "pub fn has_bar(&self)",
+            "pub fn mut_bar(&mut self)",
+            "pub fn take_bar(&mut self)",
+            "pub fn clear_bar(&mut self)",
+            "pub fn get_names_at(&self, index: usize)",
+            "pub fn merge_from(&mut self, other: Self)",
+            "pub fn copy_from(&mut self, other: &Self)",
+            "fn default_instance() -> &'static Foo",
+            "impl ::protobuf::Clear for Foo",
+        ] {
+            assert!(output.contains(method), "{}", method);
+        }
+    }
+
+    #[test]
+    fn test_display() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(string, tag = "1")]
+                pub r#type: std::string::String,
+            }
+            pub mod foo {
+                #[derive(Clone, PartialEq, ::prost::Message)]
+                pub struct Bar {
+                    #[prost(uint64, tag = "1")]
+                    pub id: u64,
+                }
+            }
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::default());
+        gen.config
+            .display_field("Foo", "type")
+            .display_field("foo::Bar", "id");
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("impl ::std::fmt::Display for Foo {"));
+        assert!(output.contains("::std::fmt::Display::fmt(&self.r#type, f)"));
+        assert!(output.contains("impl ::std::fmt::Display for foo::Bar {"));
+        assert!(output.contains("::std::fmt::Display::fmt(&self.id, f)"));
+
+        gen.config.display_field("Foo", "name");
+        let err = gen.generate(&mut Vec::new()).unwrap_err();
+        assert_eq!(
+            "Display field `name` not found in message `Foo`",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn test_as_ref_field() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(message, optional, tag = "1")]
+                pub bar: ::std::option::Option<Bar>,
+                #[prost(uint64, tag = "2")]
+                pub id: u64,
+            }
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::default());
+        gen.config.as_ref_field("Foo", "bar").option_getters(true);
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("impl ::std::convert::AsRef<Bar> for Foo {"));
+        assert!(output.contains(
+            "fn as_ref(&self) -> &Bar { self.bar.as_ref().unwrap_or_else(|| Bar::default_ref()) }"
+        ));
+        assert!(output.contains("impl ::std::convert::AsMut<Bar> for Foo {"));
+        assert!(output.contains("fn as_mut(&mut self) -> &mut Bar { if self.bar.is_none() {"));
+
+        gen.config.as_ref_field("Foo", "id");
+        let err = gen.generate(&mut Vec::new()).unwrap_err();
+        assert_eq!(
+            "AsRef field `id` of message `Foo` is not a message",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn test_redacted_debug() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(string, tag = "1")]
+                pub r#type: std::string::String,
+                #[prost(string, tag = "2")]
+                pub password: std::string::String,
+            }
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Bar {}
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::default());
+        gen.config.sensitive_field("Foo", "password");
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert_eq!(1, output.matches("pub fn redacted_debug(&self)").count());
+        assert!(output.contains("struct Redacted<'a>(&'a Foo);"));
+        assert!(output.contains(".field(\"type\", &self.0.r#type)"));
+        assert!(output.contains(".field(\"password\", &Mask)"));
+        assert!(output.contains("::std::format!(\"{:?}\", Redacted(self))"));
+
+        gen.config.sensitive_field("Foo", "secret");
+        let err = gen.generate(&mut Vec::new()).unwrap_err();
+        assert_eq!(
+            "Sensitive field `secret` not found in message `Foo`",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn test_fields_const() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(message, repeated, tag = "3")]
+                pub bars: ::std::vec::Vec<Bar>,
+                #[prost(string, tag = "1")]
+                pub r#type: std::string::String,
+                #[prost(oneof = "foo::Baz", tags = "5, 4")]
+                pub baz: ::std::option::Option<foo::Baz>,
+            }
+        "#;
+        let output = generate_str(input, GenOpt::default());
+        assert!(!output.contains("FIELDS"));
+
+        let output = generate_str(input, GenOpt::default() | GenOpt::FIELDS);
+        assert!(output.contains(
+            "pub const FIELDS: &[(&str, u32, &str)] = &[
+    (\"type\", 1, \"string\"),
+    (\"bars\", 3, \"repeated message\"),
+    ];
+pub const BAZ_TAGS: &[u32] = &[4, 5];"
+        ));
+    }
+
+    #[test]
+    fn test_field_mask() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(string, tag = "1")]
+                pub r#type: std::string::String,
+                #[prost(message, repeated, tag = "2")]
+                pub bars: ::std::vec::Vec<Bar>,
+            }
+        "#;
+        let output = generate_str(input, GenOpt::default());
+        assert!(!output.contains("by_name"));
+
+        let output = generate_str(input, GenOpt::default() | GenOpt::FIELD_MASK);
+        assert!(output.contains("pub fn clear_field_by_name(&mut self, name: &str) -> bool {"));
+        assert!(output
+            .contains("\"type\" => { self.r#type = ::std::default::Default::default(); true }"));
+        assert!(output
+            .contains("pub fn copy_field_by_name(&mut self, name: &str, other: &Self) -> bool {"));
+        assert!(output.contains("\"bars\" => { self.bars.clone_from(&other.bars); true }"));
+        assert!(output.contains("_ => false,"));
+
+        let mut gen = wrapper_gen(input, GenOpt::default() | GenOpt::FIELD_MASK);
+        gen.config.skip_method("Foo", "clear_field_by_name");
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(!output.contains("clear_field_by_name"));
+        assert!(output.contains("copy_field_by_name"));
+        syn::parse_file(&output).unwrap();
+    }
+
+    #[test]
+    fn test_field_names() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct FooBar {
+                #[prost(string, tag = "1")]
+                pub r#type: std::string::String,
+                #[prost(message, repeated, tag = "2")]
+                pub bars: ::std::vec::Vec<Bar>,
+                pub cached: u64,
+            }
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            struct Baz {
+                #[prost(uint32, tag = "1")]
+                pub id: u32,
             }
-            FieldKind::Float => {
-                result.ref_ty = RefType::Copy;
-                result.clear = Some("0.".to_owned());
+            pub mod foo {
+                #[derive(Clone, PartialEq, ::prost::Message)]
+                pub struct Qux {
+                    #[prost(bool, tag = "1")]
+                    pub ok: bool,
+                }
             }
-            FieldKind::Bool => {
-                result.ref_ty = RefType::Copy;
-                result.clear = Some("false".to_owned());
+        "#;
+        let output = generate_str(input, GenOpt::default());
+        assert!(!output.contains("_field_names"));
+
+        let output = generate_str(input, GenOpt::default() | GenOpt::FIELD_NAMES);
+        assert!(output.contains(
+            "pub mod foo_bar_field_names {
+    pub const TYPE: &str = \"type\";
+    pub const BARS: &str = \"bars\";
+}"
+        ));
+        assert!(output.contains(
+            "pub(crate) mod baz_field_names {
+    pub const ID: &str = \"id\";
+}"
+        ));
+        assert!(output.contains(
+            "pub mod foo_qux_field_names {
+    pub const OK: &str = \"ok\";
+}"
+        ));
+        syn::parse_file(&output).unwrap();
+    }
+
+    #[test]
+    fn test_maps() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(map = "string, uint64", tag = "1")]
+                pub hashed: ::std::collections::HashMap<std::string::String, u64>,
+                #[prost(btree_map = "string, uint64", tag = "2")]
+                pub ordered: ::std::collections::BTreeMap<std::string::String, u64>,
             }
-            FieldKind::Repeated { prefix } => {
-                result.mt = MethodKind::Standard;
-                result.take = Some(format!(
-                    "::std::mem::replace(&mut self.{}, ::std::vec::Vec::new())",
-                    result.name
-                ));
-                let mut unwrapped_type: &str =
-                    &unwrap_type(ty, "Vec").into_token_stream().to_string();
-
-                // The point of the prefix is to account for nesting of modules. However, it turns out
-                // that unwrapped_type may start with `super` so if we just smoosh the two together we
-                // get an invalid type. So the following small nightmare of code pops a suffix of
-                // prefix for every `super`, while there are both `super`s and segments of the prefix.
-                let mut segments: Vec<_> = if ["bool", "u32", "i32", "u64", "i32", "f32", "f64"]
-                    .contains(&unwrapped_type)
-                {
-                    // A built-in type should never be prefixed.
-                    Vec::new()
-                } else {
-                    prefix.split("::").collect()
-                };
-                while let Some(s) = segments.pop() {
-                    if s.is_empty() {
-                        continue;
-                    }
-                    if !unwrapped_type.starts_with("super ::") {
-                        segments.push(s);
-                        break;
-                    }
-                    unwrapped_type = &unwrapped_type[8..]
-                }
-                let mut prefix = segments.join("::");
-                if !prefix.is_empty() {
-                    prefix += "::"
-                }
+        "#;
+        let output = generate_str(input, GenOpt::default());
+        assert!(output.contains("pub fn clear_hashed(&mut self) { self.hashed.clear(); }"));
+        assert!(output.contains(
+            "pub fn take_hashed(&mut self) -> :: std :: collections :: HashMap < std :: string :: String , u64 > { ::core::mem::take(&mut self.hashed) }"
+        ));
+        assert!(output.contains("pub fn clear_ordered(&mut self) { self.ordered.clear(); }"));
+        assert!(output.contains(
+            "pub fn take_ordered(&mut self) -> :: std :: collections :: BTreeMap < std :: string :: String , u64 > { ::core::mem::take(&mut self.ordered) }"
+        ));
+    }
 
-                result.ref_ty = RefType::Deref(format!("[{}{}]", prefix, unwrapped_type));
-                result.override_ty = Some(format!("::std::vec::Vec<{}{}>", prefix, unwrapped_type));
+    #[test]
+    fn test_message_maps() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(map = "string, message", tag = "1")]
+                pub named: ::std::collections::HashMap<std::string::String, Bar>,
+                #[prost(btree_map = "uint32, message", tag = "2")]
+                pub numbered: ::std::collections::BTreeMap<u32, Bar>,
+                #[prost(map = "string, uint64", tag = "3")]
+                pub counts: ::std::collections::HashMap<std::string::String, u64>,
             }
-            FieldKind::Bytes => {
-                result.ref_ty = RefType::Deref("[u8]".to_owned());
-                result.mt = MethodKind::Standard;
-                result.take = Some(format!(
-                    "::std::mem::replace(&mut self.{}, ::std::vec::Vec::new())",
-                    result.name
-                ));
+        "#;
+        let output = generate_str(input, GenOpt::default() | GenOpt::INDEXED);
+        assert!(output.contains(
+            "pub fn get_named_or_default(&self, key: &str) -> &Bar { self.named.get(key).unwrap_or_else(|| Bar::default_ref()) }"
+        ));
+        assert!(output.contains(
+            "pub fn mut_named_or_default(&mut self, key: std :: string :: String) -> &mut Bar { self.named.entry(key).or_default() }"
+        ));
+        assert!(output.contains(
+            "pub fn get_numbered_or_default(&self, key: u32) -> &Bar { self.numbered.get(&key).unwrap_or_else(|| Bar::default_ref()) }"
+        ));
+        assert!(!output.contains("counts_or_default"));
+        assert!(!generate_str(input, GenOpt::default()).contains("_or_default"));
+    }
+
+    #[test]
+    fn test_raw_module_prefix() {
+        let input = r#"
+            pub mod r#type {
+                #[derive(Clone, PartialEq, ::prost::Message)]
+                pub struct Inner {
+                    #[prost(enumeration = "Kind", tag = "1")]
+                    pub kind: i32,
+                }
             }
-            FieldKind::String => {
-                result.ref_ty = RefType::Deref("str".to_owned());
-                result.mt = MethodKind::Standard;
-                result.take = Some(format!(
-                    "::std::mem::replace(&mut self.{}, ::std::string::String::new())",
-                    result.name
-                ));
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::default());
+        gen.config.display_field("type::Inner", "kind");
+        gen.config.field_methods(|info| {
+            assert_eq!("type::Inner", info.message);
+            None
+        });
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("impl r#type::Inner {"));
+        assert!(output.contains(
+            "pub fn get_kind(&self) -> r#type::Kind { r#type :: Kind::from_i32(self.kind).unwrap_or_default() }"
+        ));
+        assert!(output.contains("impl ::std::fmt::Display for r#type::Inner {"));
+    }
+
+    #[test]
+    fn test_method_prefix() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(message, optional, tag = "1")]
+                pub bar: ::std::option::Option<Bar>,
             }
-            FieldKind::Enumeration(enum_type) => {
-                result.override_ty = Some(enum_type.clone());
-                result.ref_ty = RefType::Copy;
-                result.clear = Some("0".to_owned());
-                result.set = Some("v as i32".to_owned());
-                result.enum_set = true;
-                result.get = Some(format!(
-                    "match {}::from_i32(self.{}) {{\
-                        Some(e) => e,
-                        None => panic!(\"Unknown enum variant: {{}}\", self.{1}),
-                    }}",
-                    type_in_expr_context(enum_type),
-                    result.name,
-                ));
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::default());
+        gen.config.method_prefix("pb_");
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("pub fn pb_has_bar(&self) -> bool"));
+        assert!(output.contains("pub fn pb_get_bar(&self) -> &Bar"));
+        assert!(output.contains("pub fn pb_mut_bar(&mut self) -> &mut Bar"));
+        assert!(!output.contains("pub fn get_bar("));
+        // Other functions are not prefixed.
+        assert!(output.contains("pub fn new_() -> Foo"));
+    }
+
+    #[test]
+    fn test_skip_constructors() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(message, optional, tag = "1")]
+                pub bar: ::std::option::Option<foo::Bar>,
             }
-            FieldKind::Map => {
-                result.mt = MethodKind::Standard;
+            pub mod foo {
+                #[derive(Clone, PartialEq, ::prost::Message)]
+                pub struct Bar {
+                    #[prost(uint64, tag = "1")]
+                    pub id: u64,
+                }
             }
-            // There's only a few `oneof`s and they are a bit complex, so easier to
-            // handle manually.
-            FieldKind::OneOf(_) => return None,
-        }
-
-        Some(result)
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::default() | GenOpt::MESSAGE_EXT);
+        gen.config.skip_constructors("foo::Bar");
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("impl foo::Bar {\n#[inline] pub fn clear_id("));
+        assert!(
+            output.contains("static ref DEFAULT: foo :: Bar = ::std::default::Default::default();")
+        );
+        assert!(!output.contains("foo :: Bar::default_ref()"));
+        // Other messages are unaffected.
+        assert!(output.contains("pub fn new_() -> Foo"));
+        assert!(output
+            .contains("#[inline] fn default_instance() -> &'static Self { Self::default_ref() }"));
     }
-}
 
-fn unwrap_type(ty: &Type, type_ctor: &str) -> Type {
-    match ty {
-        Type::Path(p) => {
-            let seg = p.path.segments.iter().last().unwrap();
-            if seg.ident == type_ctor {
-                match &seg.arguments {
-                    PathArguments::AngleBracketed(args) => match &args.args[0] {
-                        GenericArgument::Type(ty) => ty.clone(),
-                        _ => unreachable!(),
-                    },
-                    _ => unreachable!(),
+    #[test]
+    fn test_option_getters() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(message, optional, tag = "1")]
+                pub bar: ::std::option::Option<Bar>,
+                #[prost(message, optional, boxed, tag = "2")]
+                pub next: ::std::option::Option<::std::boxed::Box<Foo>>,
+                #[prost(oneof = "foo::Value", tags = "3")]
+                pub value: ::std::option::Option<foo::Value>,
+            }
+            pub mod foo {
+                #[derive(Clone, PartialEq, ::prost::Oneof)]
+                pub enum Value {
+                    #[prost(message, tag = "3")]
+                    Baz(::std::boxed::Box<super::Baz>),
                 }
-            } else {
-                ty.clone()
             }
-        }
-        _ => unreachable!(),
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::default());
+        gen.config.option_getters(true);
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains(
+            "pub fn get_bar(&self) -> ::std::option::Option<&Bar> { self.bar.as_ref() }"
+        ));
+        assert!(output.contains(
+            "pub fn get_next(&self) -> ::std::option::Option<&Foo> { self.next.as_deref() }"
+        ));
+        assert!(output.contains("pub fn get_baz(&self) -> ::std::option::Option<&Baz> {"));
+        assert!(output.contains(
+            "::std::option::Option::Some(foo::Value::Baz(v)) => ::std::option::Option::Some(&**v),"
+        ));
+        assert!(!output.contains("::default_ref()"));
+        assert!(output.contains("pub fn mut_bar(&mut self) -> &mut Bar {"));
     }
-}
 
-struct FieldMethods {
-    ty: String,
-    ref_ty: RefType,
-    override_ty: Option<String>,
-    name: Ident,
-    unesc_base: String,
-    has: bool,
-    // None = delegate to field's `clear`
-    // Some = default value
-    clear: Option<String>,
-    // None = set to `v`
-    // Some = expression to set.
-    set: Option<String>,
-    enum_set: bool,
-    // Some = custom getter expression.
-    get: Option<String>,
-    mt: MethodKind,
-    take: Option<String>,
-}
+    #[test]
+    fn test_cow_string_getters() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(string, tag = "1")]
+                pub name: ::std::string::String,
+                #[prost(string, optional, tag = "2")]
+                pub label: ::std::option::Option<::std::string::String>,
+            }
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::default());
+        gen.config.cow_string_getters(true);
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains(
+            "pub fn get_name(&self) -> ::std::borrow::Cow<'_, str> { ::std::borrow::Cow::Borrowed(&*self.name) }"
+        ));
+        assert!(output.contains(
+            "pub fn get_label(&self) -> ::std::borrow::Cow<'_, str> { self.label.as_deref().map_or(::std::borrow::Cow::Owned(::std::string::String::new()), ::std::borrow::Cow::Borrowed) }"
+        ));
+        assert!(output.contains("pub fn has_label(&self) -> bool { self.label.is_some() }"));
+        syn::parse_file(&output).unwrap();
 
-impl FieldMethods {
-    fn new(ty: &Type, ident: &Ident) -> FieldMethods {
-        let mut unesc_base = ident.to_string();
-        if unesc_base.starts_with("r#") {
-            unesc_base = unesc_base[2..].to_owned();
-        }
-        FieldMethods {
-            ty: ty.clone().into_token_stream().to_string(),
-            ref_ty: RefType::Ref,
-            override_ty: None,
-            name: ident.clone(),
-            unesc_base,
-            has: false,
-            clear: None,
-            set: None,
-            enum_set: false,
-            get: None,
-            mt: MethodKind::None,
-            take: None,
-        }
+        gen.config.no_std(true);
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(
+            output.contains("-> ::alloc::borrow::Cow<'_, str> { ::alloc::borrow::Cow::Borrowed(")
+        );
     }
 
-    fn write_methods<W>(&self, buf: &mut W, gen_opt: GenOpt) -> Result<(), io::Error>
-    where
-        W: Write,
-    {
-        // has_*
-        if self.has && gen_opt.contains(GenOpt::HAS) {
-            writeln!(
-                buf,
-                "#[inline] pub fn has_{}(&self) -> bool {{ self.{}.is_some() }}",
-                self.unesc_base, self.name
-            )?;
-        }
-        let ty = match &self.override_ty {
-            Some(s) => s.clone(),
-            None => self.ty.clone(),
-        };
-        let ref_ty = match &self.ref_ty {
-            RefType::Copy => ty.clone(),
-            RefType::Ref => format!("&{}", ty),
-            RefType::Deref(s) => format!("&{}", s),
-        };
-        // clear_*
-        if gen_opt.contains(GenOpt::CLEAR) {
-            match &self.clear {
-                Some(s) => writeln!(
-                    buf,
-                    "#[inline] pub fn clear_{}(&mut self) {{ self.{} = {} }}",
-                    self.unesc_base, self.name, s
-                )?,
-                None => writeln!(
-                    buf,
-                    "#[inline] pub fn clear_{}(&mut self) {{ self.{}.clear(); }}",
-                    self.unesc_base, self.name
-                )?,
+    #[test]
+    fn test_constructor_name() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {}
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::default());
+        gen.config.constructor_name("empty");
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("pub fn empty() -> Foo { ::std::default::Default::default() }"));
+        assert!(!output.contains("new_"));
+
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct FooRequest {}
+            pub mod foo {
+                #[derive(Clone, PartialEq, ::prost::Message)]
+                pub struct Bar {}
             }
-        }
-        // set_*
-        match &self.set {
-            Some(s) if !self.enum_set => writeln!(
-                buf,
-                "#[inline] pub fn set_{}(&mut self, v: {}) {{ self.{} = {}; }}",
-                self.unesc_base, ty, self.name, s
-            )?,
-            None if gen_opt.contains(GenOpt::TRIVIAL_SET) => writeln!(
-                buf,
-                "#[inline] pub fn set_{}(&mut self, v: {}) {{ self.{} = v; }}",
-                self.unesc_base, ty, self.name
-            )?,
-            _ => {}
-        }
-        // get_*
-        match &self.get {
-            Some(s) => writeln!(
-                buf,
-                "#[inline] pub fn get_{}(&self) -> {} {{ {} }}",
-                self.unesc_base, ref_ty, s
-            )?,
-            None => {
-                if gen_opt.contains(GenOpt::TRIVIAL_GET) {
-                    let rf = match &self.ref_ty {
-                        RefType::Copy => "",
-                        _ => "&",
-                    };
-                    writeln!(
-                        buf,
-                        "#[inline] pub fn get_{}(&self) -> {} {{ {}self.{} }}",
-                        self.unesc_base, ref_ty, rf, self.name
-                    )?
-                }
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::default());
+        gen.config
+            .constructor_name("empty")
+            .constructor_names(|message| match message.strip_suffix("Request") {
+                Some(_) => "build".to_owned(),
+                None => format!("new_{}", message.replace("::", "_").to_lowercase()),
+            });
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(
+            output.contains("pub fn build() -> FooRequest { ::std::default::Default::default() }")
+        );
+        assert!(output
+            .contains("pub fn new_foo_bar() -> foo::Bar { ::std::default::Default::default() }"));
+        assert!(!output.contains("empty"));
+    }
+
+    #[test]
+    fn test_impl_attributes() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(uint64, tag = "1")]
+                pub id: u64,
             }
-        }
-        // mut_*
-        if gen_opt.contains(GenOpt::MUT) {
-            match &self.mt {
-                MethodKind::Standard => {
-                    writeln!(
-                        buf,
-                        "#[inline] pub fn mut_{}(&mut self) -> &mut {} {{ &mut self.{} }}",
-                        self.unesc_base, ty, self.name
-                    )?;
-                }
-                MethodKind::Custom(s) => {
-                    writeln!(
-                        buf,
-                        "#[inline] pub fn mut_{}(&mut self) -> &mut {} {{ {} }} ",
-                        self.unesc_base, ty, s
-                    )?;
+            #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+            #[repr(i32)]
+            pub enum Color {
+                Red = 0,
+            }
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::default());
+        gen.config
+            .impl_attribute("#[cfg_attr(docsrs, doc(hidden))]");
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("#[cfg_attr(docsrs, doc(hidden))]\nimpl Foo {"));
+        assert!(output.contains("#[cfg_attr(docsrs, doc(hidden))]\nimpl Color {"));
+        assert!(!output.contains("#[cfg_attr(docsrs, doc(hidden))]\nimpl ::protobuf::Message"));
+    }
+
+    #[test]
+    fn test_item_attributes() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(uint64, tag = "1")]
+                pub id: u64,
+                #[prost(message, optional, tag = "2")]
+                pub bar: ::std::option::Option<foo::Bar>,
+            }
+            pub mod foo {
+                #[derive(Clone, PartialEq, ::prost::Message)]
+                pub struct Bar {
+                    #[prost(string, tag = "1")]
+                    pub name: ::std::string::String,
                 }
-                MethodKind::None => {}
             }
+            #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+            #[repr(i32)]
+            pub enum Color {
+                Red = 0,
+            }
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::all());
+        gen.config
+            .item_attribute("#[cfg(feature = \"proto\")]")
+            .impl_attribute("#[cfg_attr(docsrs, doc(hidden))]")
+            .display_field("Foo", "id")
+            .as_ref_field("Foo", "bar")
+            .accessor_traits(true);
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output
+            .contains("#[cfg(feature = \"proto\")]\n#[cfg_attr(docsrs, doc(hidden))]\nimpl Foo {"));
+        let file = syn::parse_file(&output).unwrap();
+        let cfg: Attribute = syn::parse_quote!(#[cfg(feature = "proto")]);
+        assert!(file.items.len() > 10);
+        for item in &file.items {
+            let attrs = match item {
+                Item::Impl(i) => &i.attrs,
+                Item::Trait(t) => &t.attrs,
+                Item::Mod(m) => &m.attrs,
+                _ => panic!("unexpected item: {}", item.to_token_stream()),
+            };
+            assert_eq!(attrs.first(), Some(&cfg));
         }
 
-        // take_*
-        if gen_opt.contains(GenOpt::TAKE) {
-            if let Some(s) = &self.take {
-                writeln!(
-                    buf,
-                    "#[inline] pub fn take_{}(&mut self) -> {} {{ {} }}",
-                    self.unesc_base, ty, s
-                )?;
+        // The module of nested wrappers has the attribute too.
+        let mut gen = wrapper_gen(input, GenOpt::default());
+        gen.config
+            .item_attribute("#[cfg(feature = \"proto\")]")
+            .module("wrappers");
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("#[cfg(feature = \"proto\")]\npub mod wrappers {"));
+        assert!(output.contains("#[cfg(feature = \"proto\")]\npub mod foo {"));
+    }
+
+    #[test]
+    fn test_custom_field_methods() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(uint64, tag = "1")]
+                pub timeout_ms: u64,
+                #[prost(string, tag = "2")]
+                pub name: std::string::String,
             }
-        }
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::default());
+        gen.config.field_methods(|info| {
+            if info.message == "Foo" && info.field == "timeout_ms" {
+                assert_eq!("int", info.kind);
+                assert_eq!(Some(1), info.tag);
+                Some("pub fn get_timeout(&self) -> ::std::time::Duration { ::std::time::Duration::from_millis(self.timeout_ms) }".to_owned())
+            } else {
+                None
+            }
+        });
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains(
+            "#[inline] pub fn get_timeout_ms(&self) -> u64 { self.timeout_ms }
+pub fn get_timeout(&self) -> ::std::time::Duration { ::std::time::Duration::from_millis(self.timeout_ms) }
+"
+        ));
+    }
 
-        Ok(())
+    #[test]
+    fn test_packed() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(int32, repeated, packed = "true", tag = "1")]
+                pub packed: ::std::vec::Vec<i32>,
+                #[prost(uint64, repeated, tag = "2")]
+                pub implicit: ::std::vec::Vec<u64>,
+                #[prost(sint32, repeated, packed = "false", tag = "3")]
+                pub unpacked: ::std::vec::Vec<i32>,
+                #[prost(string, repeated, tag = "4")]
+                pub names: ::std::vec::Vec<::std::string::String>,
+            }
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::default() | GenOpt::FIELDS);
+        gen.config.field_methods(|info| {
+            let expected = info.field == "packed" || info.field == "implicit";
+            assert_eq!(expected, info.packed, "{}", info.field);
+            None
+        });
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("pub fn get_packed(&self) -> &[i32] { &self.packed }"));
+        assert!(output.contains("(\"packed\", 1, \"repeated int\"),"));
+        assert!(output.contains("(\"unpacked\", 3, \"repeated int\"),"));
     }
-}
 
-enum RefType {
-    Copy,
-    Ref,
-    Deref(String),
-}
+    #[test]
+    fn test_into_setters() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(string, tag = "1")]
+                pub name: ::std::string::String,
+                #[prost(message, optional, tag = "2")]
+                pub bar: ::std::option::Option<Bar>,
+                #[prost(uint32, tag = "3")]
+                pub id: u32,
+            }
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::default() | GenOpt::TESTS);
+        gen.config.into_setters(true);
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("pub fn set_name(&mut self, v: impl ::std::convert::Into<:: std :: string :: String>) { let v = v.into(); self.name = v; }"));
+        assert!(output.contains("pub fn set_bar(&mut self, v: impl ::std::convert::Into<Bar>) { let v = v.into(); self.bar = ::std::option::Option::Some(v); }"));
+        assert!(output.contains("pub fn set_id(&mut self, v: u32) { self.id = v; }"));
+        assert!(output.contains("m.set_name(\"a\");"));
+        assert!(output.contains("m.set_bar(<Bar>::default());"));
+    }
 
-enum MethodKind {
-    None,
-    Standard,
-    Custom(String),
-}
+    #[test]
+    fn test_as_ref_str_setters() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(string, tag = "1")]
+                pub name: ::std::string::String,
+                #[prost(string, optional, tag = "2")]
+                pub label: ::std::option::Option<::std::string::String>,
+                #[prost(bytes, tag = "3")]
+                pub data: ::std::vec::Vec<u8>,
+            }
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::default() | GenOpt::TESTS);
+        gen.config.as_ref_str_setters(true).into_setters(true);
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("pub fn set_name(&mut self, v: impl ::std::convert::AsRef<str>) { let v = ::std::string::String::from(v.as_ref()); self.name = v; }"));
+        assert!(output.contains("pub fn set_label(&mut self, v: impl ::std::convert::AsRef<str>) { let v = ::std::string::String::from(v.as_ref()); self.label = ::std::option::Option::Some(v); }"));
+        assert!(output.contains(
+            "pub fn set_data(&mut self, v: impl ::std::convert::Into<:: std :: vec :: Vec < u8 >>)"
+        ));
+        assert!(output.contains("m.set_name(\"a\");"));
+    }
 
-fn is_message(attrs: &[Attribute]) -> bool {
-    for a in attrs {
-        if a.path.is_ident("derive") {
-            let tts = a.tokens.to_string();
-            if tts.contains(":: Message") {
-                return true;
+    #[test]
+    fn test_serde_helpers() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(string, tag = "1")]
+                pub r#type: ::std::string::String,
+                #[prost(message, optional, tag = "2")]
+                pub bar: ::std::option::Option<Bar>,
             }
-        }
+        "#;
+        assert!(!generate_str(input, GenOpt::default()).contains("is_default_"));
+
+        let mut gen = wrapper_gen(input, GenOpt::default());
+        gen.config.serde_helpers(true).accessor_traits(true);
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("#[allow(clippy::trivially_copy_pass_by_ref, clippy::ref_option)]\n#[inline] pub fn is_default_type(v: &:: std :: string :: String) -> bool { *v == <:: std :: string :: String as ::std::default::Default>::default() }"));
+        assert!(output
+            .contains("pub fn is_default_bar(v: &:: std :: option :: Option < Bar >) -> bool {"));
+        // In the inherent `impl`, not the accessor trait.
+        let accessors = &output[output.find("pub trait FooAccessors").unwrap()..];
+        assert!(!accessors.contains("is_default_"));
     }
-    false
-}
 
-fn is_enum(attrs: &[Attribute]) -> bool {
-    for a in attrs {
-        if a.path.is_ident("derive") {
-            let tts = a.tokens.to_string();
-            if tts.contains("Enumeration") {
-                return true;
+    #[test]
+    fn test_accessor_traits() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Foo {
+                #[prost(uint32, tag = "1")]
+                pub id: u32,
+                #[prost(string, repeated, tag = "2")]
+                pub names: ::std::vec::Vec<::std::string::String>,
             }
-        }
+            pub mod foo {
+                #[derive(Clone, PartialEq, ::prost::Message)]
+                pub struct Bar {
+                    #[prost(uint32, tag = "1")]
+                    pub id: u32,
+                }
+            }
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::default() | GenOpt::ITER);
+        gen.config.accessor_traits(true);
+        let mut buf = Vec::new();
+        let summary = gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("pub fn new_() -> Foo {"));
+        assert!(output.contains("pub trait FooAccessors {\nfn clear_id(&mut self);\n"));
+        assert!(output.contains("fn iter_names(&self) -> impl ::std::iter::Iterator<Item = &:: std :: string :: String>;"));
+        assert!(output.contains(
+            "impl FooAccessors for Foo {\n#[inline] fn clear_id(&mut self) { self.id = 0 }"
+        ));
+        assert!(output.contains("impl FooBarAccessors for foo::Bar {"));
+        assert!(!output.contains("pub fn get_id"));
+        assert_eq!(summary.methods, 18);
     }
-    false
-}
 
-// When a generic type is used in expression context, it might need to be adjusted.
-// For example, `Box<Foo>` becomes `Box::<Foo>`
-fn type_in_expr_context(s: &str) -> String {
-    let mut parsed: TypePath = syn::parse_str(s).expect("Not a type?");
-    let last_segment = parsed.path.segments.last_mut().unwrap();
-    if !last_segment.arguments.is_empty() {
-        if let PathArguments::AngleBracketed(ref mut a) = last_segment.arguments {
-            if a.colon2_token == None {
-                a.colon2_token = Some(Token![::](Span::call_site()));
+    #[test]
+    fn test_crate_visibility() {
+        let input = r#"
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub(crate) struct Foo {
+                #[prost(uint32, tag = "1")]
+                pub id: u32,
             }
-        }
+            #[derive(Clone, PartialEq, ::prost::Message)]
+            pub struct Bar {
+                #[prost(uint32, tag = "1")]
+                pub id: u32,
+            }
+        "#;
+        let output = generate_str(input, GenOpt::default() | GenOpt::FIELDS);
+        assert!(output.contains("impl Foo {\npub(crate) fn new_() -> Foo {"));
+        assert!(output.contains("#[inline] pub(crate) fn get_id(&self) -> u32 { self.id }"));
+        assert!(output.contains("pub(crate) const FIELDS: &[(&str, u32, &str)] = &["));
+        assert!(output.contains("impl Bar {\npub fn new_() -> Bar {"));
+        assert!(output.contains("pub const FIELDS: &[(&str, u32, &str)] = &["));
+
+        // Documentation is copied as is, even if it looks like a declaration.
+        let mut gen = wrapper_gen(input, GenOpt::default());
+        gen.config.method_prefix("pb_").field_doc(
+            "Foo",
+            "id",
+            " Like `pub fn id()`, see `pub const ID`.",
+        );
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains(
+            "#[doc = \" Like `pub fn id()`, see `pub const ID`.\"]\n#[inline] pub(crate) fn pb_get_id(&self) -> u32 { self.id }"
+        ));
+        assert!(!output.contains("pub(crate) fn pb_id()"));
+        assert!(!output.contains("pub(crate) const ID"));
+        assert!(output.contains("#[inline] pub fn pb_get_id(&self) -> u32 { self.id }"));
     }
-    parsed.to_token_stream().to_string()
-}
 
-#[cfg(test)]
-mod test {
-    use super::*;
+    #[test]
+    fn test_rename_field() {
+        let input = r#"
+            pub mod foo {
+                #[derive(Clone, PartialEq, ::prost::Message)]
+                pub struct Bar {
+                    #[prost(uint32, tag = "1")]
+                    pub r#type: u32,
+                    #[prost(uint32, tag = "2")]
+                    pub id: u32,
+                }
+            }
+        "#;
+        let mut gen = wrapper_gen(input, GenOpt::default() | GenOpt::TESTS);
+        gen.config.rename_field("foo::Bar", "type", "kind");
+        let mut buf = Vec::new();
+        gen.generate(&mut buf).unwrap();
+        let output = String::from_utf8(buf).unwrap();
+        assert!(output.contains("pub fn get_kind(&self) -> u32 { self.r#type }"));
+        assert!(output.contains("pub fn set_kind(&mut self, v: u32) { self.r#type = v; }"));
+        assert!(output.contains("m.set_kind(1);"));
+        assert!(output.contains("pub fn get_id(&self) -> u32 { self.id }"));
+        assert!(!output.contains("get_type"));
+    }
 
     #[test]
     fn test_type_in_expr_context() {
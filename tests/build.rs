@@ -1,7 +1,73 @@
 // Copyright 2019 PingCAP, Inc.
 
-use protobuf_build::Builder;
+use std::path::Path;
+
+use protobuf_build::{Builder, GenOpt, WrapperConfig};
 
 fn main() {
-    Builder::new().search_dir_for_protos("proto").generate()
+    // Wrappers are only generated for Prost, tests of them are compiled if
+    // they were.
+    println!("cargo:rustc-check-cfg=cfg(prost_wrappers)");
+
+    let mut wrapper_config = WrapperConfig::new();
+    wrapper_config
+        .display_field("event::Row", "type")
+        .impl_attribute("#[cfg_attr(docsrs, doc(hidden))]")
+        .skip_constructors("event::Entries")
+        .skip_method("Type", "get_inners")
+        .sensitive_field("Blob", "data")
+        .as_ref_field("Blob", "part")
+        .field_doc("Blob", "data", " The blob's contents.")
+        .strict(true);
+
+    Builder::new()
+        .search_dir_for_protos("proto")
+        .wrapper_options(GenOpt::all())
+        .wrapper_config(wrapper_config.clone())
+        .generate()
+        .unwrap();
+
+    // The same protos, with wrappers generated into nested modules and
+    // non-default options.
+    wrapper_config
+        .module("wrappers")
+        .deref_bytes(false)
+        .no_std(true)
+        .method_prefix("pb_")
+        .panic_on_bad_index(true)
+        .option_getters(true)
+        .into_setters(true)
+        .as_ref_str_setters(true)
+        .bytes_conversions(true)
+        .serde_helpers(true)
+        .shared_enum_helpers(true)
+        .prelude(true)
+        .pretty_print(true);
+    let out_dir = std::env::var("OUT_DIR").unwrap();
+    Builder::new()
+        .search_dir_for_protos("proto")
+        .out_dir(format!("{}/nested_protos", out_dir))
+        .wrapper_options(GenOpt::all())
+        .wrapper_config(wrapper_config)
+        .generate()
+        .unwrap();
+
+    // Wrappers split across several files, which `mod.rs` must not include
+    // as modules of their own.
+    let mut split_config = WrapperConfig::new();
+    split_config.split_files(2);
+    Builder::new()
+        .search_dir_for_protos("proto")
+        .out_dir(format!("{}/split_protos", out_dir))
+        .wrapper_options(GenOpt::all())
+        .wrapper_config(split_config)
+        .generate()
+        .unwrap();
+
+    if Path::new(&out_dir)
+        .join("nested_protos/wrapper_nested.rs")
+        .exists()
+    {
+        println!("cargo:rustc-cfg=prost_wrappers");
+    }
 }
@@ -1,11 +1,203 @@
 // Copyright 2019 PingCAP, Inc.
 
+// Used by the wrappers in `nested_protos`, which are generated for `no_std`.
+extern crate alloc;
+
 #[allow(dead_code)]
 #[allow(unknown_lints)]
 #[allow(clippy::all)]
 #[allow(renamed_and_removed_lints)]
 #[allow(bare_trait_objects)]
+#[allow(unused_parens)]
+#[allow(mismatched_lifetime_syntaxes)]
 
 mod protos {
     include!(concat!(env!("OUT_DIR"), "/protos/mod.rs"));
 }
+
+#[allow(dead_code)]
+#[allow(unknown_lints)]
+#[allow(clippy::all)]
+#[allow(renamed_and_removed_lints)]
+#[allow(bare_trait_objects)]
+#[allow(unused_parens)]
+#[allow(mismatched_lifetime_syntaxes)]
+mod nested_protos {
+    include!(concat!(env!("OUT_DIR"), "/nested_protos/mod.rs"));
+}
+
+#[allow(dead_code)]
+#[allow(unknown_lints)]
+#[allow(clippy::all)]
+#[allow(renamed_and_removed_lints)]
+#[allow(bare_trait_objects)]
+#[allow(unused_parens)]
+#[allow(mismatched_lifetime_syntaxes)]
+mod split_protos {
+    include!(concat!(env!("OUT_DIR"), "/split_protos/mod.rs"));
+}
+
+#[cfg(test)]
+mod test {
+    use crate::protos::optional::Blob;
+
+    #[test]
+    fn test_unset_optional() {
+        let blob = Blob::default();
+        assert_eq!("", blob.get_name());
+        assert_eq!(b"", blob.get_data());
+        assert_eq!(0, blob.get_size());
+    }
+
+    #[test]
+    fn test_message_getter_lifetime() {
+        // The result borrows `blob`, even when it is the static default.
+        fn part_data(blob: &Blob) -> &[u8] {
+            blob.get_part().get_data()
+        }
+
+        let mut blob = Blob::default();
+        let part = blob.get_part();
+        assert!(!part.has_data());
+        let mut part = part.clone();
+        part.set_data(b"foo".to_vec());
+        blob.set_part(part);
+        assert_eq!(b"foo", part_data(&blob));
+        blob.clear_part();
+        assert_eq!(b"", part_data(&blob));
+    }
+
+    #[test]
+    fn test_optional_enum() {
+        use crate::protos::optional::Level;
+
+        let mut blob = Blob::default();
+        assert!(!blob.has_level());
+        assert_eq!(Level::default(), blob.get_level());
+        blob.set_level(Level::default());
+        assert!(blob.has_level());
+        assert_eq!(Level::default(), blob.get_level());
+        blob.clear_level();
+        assert!(!blob.has_level());
+    }
+
+    #[test]
+    fn test_oneof_scalars() {
+        use crate::protos::nested::Event;
+
+        let mut event = Event::default();
+        assert_eq!("", event.get_name());
+        assert_eq!(0, event.get_id());
+        event.set_name("foo".to_owned());
+        assert!(event.has_name());
+        assert_eq!("foo", event.get_name());
+        event.set_id(42);
+        assert!(!event.has_name());
+        assert_eq!(42, event.get_id());
+        assert_eq!(b"", event.get_raw());
+    }
+
+    #[test]
+    fn test_oneof_set_replaces_variant() {
+        use crate::protos::nested::Event;
+
+        let mut event = Event::default();
+        event.set_entries(Default::default());
+        assert!(event.has_entries());
+        // Setting another variant, here a boxed one, unsets the first.
+        event.set_child(Event::default());
+        assert!(!event.has_entries());
+        assert!(event.has_child());
+        event.set_raw(b"foo".to_vec());
+        assert!(!event.has_child());
+        assert_eq!(b"foo", event.get_raw());
+        event.set_entries(Default::default());
+        assert!(!event.has_raw());
+        assert_eq!(b"", event.get_raw());
+    }
+
+    #[cfg(prost_wrappers)]
+    #[test]
+    fn test_as_ref_str_setters() {
+        use crate::nested_protos::optional::Blob;
+
+        let mut blob = Blob::default();
+        blob.pb_set_name("foo");
+        assert_eq!("foo", blob.pb_get_name());
+        let name = String::from("bar");
+        blob.pb_set_name(&name);
+        assert_eq!("bar", blob.pb_get_name());
+        blob.pb_set_name(name);
+        assert_eq!("bar", blob.pb_get_name());
+        blob.pb_set_name(Box::<str>::from("baz"));
+        assert_eq!("baz", blob.pb_get_name());
+    }
+
+    #[cfg(prost_wrappers)]
+    #[test]
+    fn test_bytes_conversions() {
+        use crate::nested_protos::optional::Blob;
+        use prost::Message;
+
+        let mut blob = Blob::default();
+        blob.pb_set_name("foo");
+        let bytes: Vec<u8> = blob.clone().into();
+        assert_eq!(blob, Blob::decode(&*bytes).unwrap());
+        let bytes: prost::bytes::Bytes = blob.clone().into();
+        assert_eq!(blob, Blob::decode(bytes).unwrap());
+    }
+
+    #[cfg(prost_wrappers)]
+    #[test]
+    fn test_mut_or_insert_with() {
+        use crate::nested_protos::nested::event::{Entries, Row};
+
+        let entries = Entries {
+            entries: vec![Row::default()],
+        };
+        let mut row = Row::default();
+        assert_eq!(
+            &entries,
+            row.pb_mut_parent_or_insert_with(|| entries.clone())
+        );
+        // A set field is left alone.
+        row.pb_mut_parent_or_insert_with(Entries::default);
+        assert_eq!(Some(&entries), row.pb_get_parent());
+    }
+
+    // `event::Entries` skips its constructors, so its default instance is a
+    // `static` in the wrappers which use it.
+    #[cfg(prost_wrappers)]
+    #[test]
+    fn test_nested_default_instance() {
+        use crate::protos::nested::event::{Entries, Row};
+        use crate::protos::MessageExt;
+
+        let row = Row::default();
+        assert_eq!(&Entries::default(), row.get_parent());
+        assert_eq!(&Entries::default(), Entries::default_instance());
+
+        // Wrappers in nested modules use the shared items at the root.
+        use crate::nested_protos::nested::event;
+        use crate::nested_protos::MessageExt as _;
+
+        let mut row = event::Row::default();
+        assert_eq!(None, row.pb_get_parent());
+        assert_eq!(&event::Entries::default(), row.pb_mut_parent());
+        assert!(row.pb_has_parent());
+        assert_eq!(
+            &event::Entries::default(),
+            event::Entries::default_instance()
+        );
+    }
+
+    #[cfg(prost_wrappers)]
+    #[test]
+    fn test_split_wrappers() {
+        use crate::split_protos::optional::Blob;
+
+        let mut blob = Blob::default();
+        blob.set_name("foo".to_owned());
+        assert_eq!("foo", blob.get_name());
+    }
+}